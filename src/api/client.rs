@@ -1,5 +1,6 @@
 use super::io_msg::{
-    AppendTermInput, AppendTermResult, RequestVoteInput, RequestVoteResult, UpdateNodeInput,
+    AppendTermInput, AppendTermResult, InstallSnapshotInput, InstallSnapshotResult,
+    RequestVoteInput, RequestVoteResult, TimeoutNowInput, TimeoutNowResult, UpdateNodeInput,
     UpdateNodeResult,
 };
 use crate::{
@@ -72,10 +73,12 @@ pub(crate) async fn post_update_node(
     target: &Url,
     settings: &Settings,
     uuid: [u8; 16],
+    learner: bool,
 ) -> WarnResult<UpdateNodeResult> {
     let body = UpdateNodeInput {
         hash: uuid,
         port: settings.port.clone(),
+        learner,
     };
     let target_uri = format!("http://{}/update_node", target);
     match build(
@@ -154,3 +157,86 @@ pub(crate) async fn post_request_vote(
         )),
     }
 }
+
+/// Ask a node if it would grant its vote, before starting an election
+///
+/// Note: The warning should be managed by the direct parent function and
+/// translated as an `Error` if needed
+pub(crate) async fn post_pre_vote(
+    target: &Url,
+    settings: &Settings,
+    input: RequestVoteInput,
+) -> WarnResult<RequestVoteResult> {
+    let target_uri = format!("http://{}/pre_vote", target);
+    trace!("pre vote to {}", target);
+    match build(
+        input,
+        target_uri,
+        Duration::from_millis(settings.response_timeout as u64),
+    )
+    .await
+    {
+        Ok(HttpResult::RequestVote(result)) => Ok(result),
+        Ok(HttpResult::Error(err_result)) => {
+            throw!(Warning::BadResult(err_result))
+        }
+        Err(warn) => throw!(*warn),
+        _ => throw!(Warning::WrongResult(
+            "unexpected result on received 'pre_vote' response",
+        )),
+    }
+}
+
+/// Send a snapshot to a node which is late behind the compacted logs, wait
+/// for the answer up to `settings.snapshot_timeout` in millisecond
+///
+/// Note: The warning should be managed by the direct parent function and
+/// translated as an `Error` if needed
+pub(crate) async fn post_install_snapshot(
+    target: &Url,
+    settings: &Settings,
+    input: InstallSnapshotInput,
+) -> WarnResult<InstallSnapshotResult> {
+    let target_uri = format!("http://{}/install_snapshot", target);
+    trace!("install snapshot to {}", target);
+    match build(input, target_uri, settings.get_snapshot_timeout_duration()).await {
+        Ok(HttpResult::InstallSnapshot(result)) => Ok(result),
+        Ok(HttpResult::Error(err_result)) => {
+            throw!(Warning::BadResult(err_result))
+        }
+        Err(warn) => throw!(*warn),
+        _ => throw!(Warning::WrongResult(
+            "unexpected result on received 'install_snapshot' response",
+        )),
+    }
+}
+
+/// Ask the target to start an election immediately, the local leader
+/// transfers its lead
+///
+/// Note: The warning should be managed by the direct parent function and
+/// translated as an `Error` if needed
+pub(crate) async fn post_timeout_now(
+    target: &Url,
+    settings: &Settings,
+    input: TimeoutNowInput,
+) -> WarnResult<TimeoutNowResult> {
+    let target_uri = format!("http://{}/timeout_now", target);
+    trace!("timeout now to {}", target);
+    match build(
+        input,
+        target_uri,
+        Duration::from_millis(settings.response_timeout as u64),
+    )
+    .await
+    {
+        Ok(HttpResult::TimeoutNow(result)) => Ok(result),
+        Ok(HttpResult::Error(err_result)) => {
+            throw!(Warning::BadResult(err_result))
+        }
+        Err(warn) => throw!(*warn),
+        _ => throw!(Warning::WrongResult(
+            "unexpected result on received 'timeout_now' response",
+        )),
+    }
+}
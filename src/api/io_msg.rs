@@ -2,50 +2,124 @@ use serde::{Deserialize, Serialize};
 
 use crate::common::error::HttpErrorResult;
 use crate::log_entry::Term;
+use crate::membership::Configuration;
 
 #[derive(Debug, Deserialize, Serialize)]
 pub enum HttpResult {
     RequestVote(RequestVoteResult),
     UpdateNode(UpdateNodeResult),
     AppendTerm(AppendTermResult),
+    InstallSnapshot(InstallSnapshotResult),
+    Propose(ProposeResult),
+    TimeoutNow(TimeoutNowResult),
+    RemoveNode(RemoveNodeResult),
     Error(HttpErrorResult),
 }
 
-#[derive(Debug, Deserialize, Serialize)]
+#[derive(Clone, Debug, Deserialize, Serialize)]
 pub struct RequestVoteInput {
+    /// Election term of the candidate
+    pub term: usize,
     pub candidate_id: String,
-    pub term: Term,
-    // commit index
-    pub last_term: usize,
+    /// Index of the candidate's last log entry
+    pub last_log_index: usize,
+    /// Election term of the candidate's last log entry
+    pub last_log_term: usize,
 }
 
 #[derive(Debug, Deserialize, Serialize)]
 pub struct RequestVoteResult {
-    pub current_term: Term,
+    /// Election term of the voter, for the candidate to update itself
+    pub current_term: usize,
     pub vote_granted: bool,
 }
 
-#[derive(Debug, Deserialize, Serialize)]
+#[derive(Clone, Debug, Deserialize, Serialize)]
 pub struct AppendTermInput {
-    pub term: Term,
+    /// Election term of the leader
+    pub term: usize,
     pub leader_id: String,
-    pub prev_term: Term,
+    /// Index of the entry immediately preceding the new ones
+    pub prev_log_index: usize,
+    /// Election term of the entry at `prev_log_index`
+    pub prev_log_term: usize,
+    /// Entries to store, empty for a heartbeat
     pub entries: Vec<Term>,
     pub leader_commit_index: usize,
 }
 
 #[derive(Debug, Deserialize, Serialize)]
 pub struct AppendTermResult {
-    pub current_term: Term,
+    /// Election term of the follower, for the leader to update itself
+    pub current_term: usize,
+    pub success: bool,
+    /// Index of the follower's last log entry, used by the leader to find
+    /// where the logs match after a failure
+    pub last_log_index: usize,
+    /// Election term of the follower's last log entry
+    pub last_log_term: usize,
+}
+
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct InstallSnapshotInput {
+    /// Election term of the leader
+    pub term: usize,
+    pub leader_id: String,
+    /// The snapshot replaces all terms up to this one included
+    pub last_included: Term,
+    #[serde(with = "crate::common::base64_bytes")]
+    pub data: Vec<u8>,
+    /// Latest configuration committed by the leader, the configuration
+    /// entries of the snapshot aren't in the logs anymore
+    #[serde(default)]
+    pub configuration: Configuration,
+}
+
+#[derive(Debug, Deserialize, Serialize)]
+pub struct InstallSnapshotResult {
+    pub current_term: usize,
+    pub success: bool,
+}
+
+/// Sent by a leader that transfers its lead, the target starts an election
+/// immediately.
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct TimeoutNowInput {
+    /// Election term of the leader
+    pub term: usize,
+    pub leader_id: String,
+}
+
+#[derive(Debug, Deserialize, Serialize)]
+pub struct TimeoutNowResult {
+    pub current_term: usize,
+    /// True if the target starts an election
     pub success: bool,
 }
 
+/// Answer of the leader to a client `/propose` request
+#[derive(Debug, Deserialize, Serialize)]
+pub struct ProposeResult {
+    /// Index of the committed entry
+    pub index: usize,
+}
+
+/// Answer of the leader to an admin `/remove_node` request
+#[derive(Debug, Deserialize, Serialize)]
+pub struct RemoveNodeResult {
+    /// Index of the committed configuration without the node
+    pub index: usize,
+}
+
 #[derive(Debug, Deserialize, Serialize)]
 pub struct UpdateNodeInput {
     /// Unique identifier of the node
     pub hash: [u8; 16],
     /// Open server port
     pub port: String,
+    /// The node never votes, see `Settings::follower`
+    #[serde(default)]
+    pub learner: bool,
 }
 
 impl PartialEq for UpdateNodeInput {
@@ -0,0 +1,234 @@
+// License:
+// This source code is licensed under the GPLv3 license, you can found the
+// LICENSE file in the root directory of this source tree.
+
+//! In-process transport. The nodes of an [InMemoryNetwork] exchange their
+//! requests through channels, so a whole cluster can run inside one
+//! binary, typically in a test.
+//!
+//! ```ignore
+//! let network = InMemoryNetwork::new();
+//! let node = Node::new_with_settings(settings, DefaultHook::new())
+//!     .with_transport(network.transport("127.0.0.1:3001"));
+//! ```
+
+use super::{
+    io_msg::{
+        AppendTermInput, AppendTermResult, HttpResult, InstallSnapshotInput, InstallSnapshotResult,
+        RequestVoteInput, RequestVoteResult, TimeoutNowInput, TimeoutNowResult, UpdateNodeResult,
+    },
+    server, Transport,
+};
+use crate::{
+    common::{
+        error::{throw, ErrorResult, WarnResult, Warning},
+        Url,
+    },
+    node::{Node, NodeInfo},
+};
+use async_trait::async_trait;
+use std::{
+    collections::HashMap,
+    sync::{Arc, RwLock},
+};
+use tokio::sync::{mpsc, oneshot};
+use tracing::trace;
+
+enum Message {
+    UpdateNode(NodeInfo),
+    AppendTerm(AppendTermInput),
+    RequestVote(RequestVoteInput),
+    PreVote(RequestVoteInput),
+    InstallSnapshot(InstallSnapshotInput),
+    TimeoutNow(TimeoutNowInput),
+}
+
+type Envelope = (Message, oneshot::Sender<HttpResult>);
+
+/// Registry of the nodes served in memory, shared by the transports of a
+/// cluster.
+#[derive(Clone, Default)]
+pub struct InMemoryNetwork {
+    nodes: Arc<RwLock<HashMap<Url, mpsc::UnboundedSender<Envelope>>>>,
+}
+
+impl InMemoryNetwork {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Transport of the node reachable at `addr` in the network. `addr`
+    /// should be the `addr:port` of the node settings, it's how the other
+    /// nodes know it.
+    pub fn transport<U: Into<Url>>(&self, addr: U) -> InMemoryTransport {
+        InMemoryTransport {
+            network: self.clone(),
+            addr: addr.into(),
+        }
+    }
+
+    /// Stop serving the node at `addr`, as if it crashed. The requests sent
+    /// to it fail until it serves again.
+    pub fn disconnect(&self, addr: &Url) {
+        self.nodes.write().unwrap().remove(addr);
+    }
+}
+
+pub struct InMemoryTransport {
+    network: InMemoryNetwork,
+    addr: Url,
+}
+
+impl InMemoryTransport {
+    async fn send(&self, target: &Url, request: Message) -> WarnResult<HttpResult> {
+        let sender = self.network.nodes.read().unwrap().get(target).cloned();
+        let sender = match sender {
+            Some(sender) => sender,
+            None => throw!(Warning::CommandFail(format!("{target} is unreachable"))),
+        };
+        let (send, recv) = oneshot::channel();
+        if sender.send((request, send)).is_err() {
+            throw!(Warning::CommandFail(format!("{target} is unreachable")))
+        }
+        match recv.await {
+            Ok(result) => Ok(result),
+            Err(_) => throw!(Warning::CommandFail(format!(
+                "{target} dropped the request"
+            ))),
+        }
+    }
+}
+
+#[async_trait]
+impl Transport for InMemoryTransport {
+    async fn update_node(
+        &self,
+        target: &Url,
+        uuid: [u8; 16],
+        learner: bool,
+    ) -> WarnResult<UpdateNodeResult> {
+        let info = NodeInfo {
+            hash: uuid,
+            addr: self.addr.to_string(),
+            learner,
+        };
+        match self.send(target, Message::UpdateNode(info)).await? {
+            HttpResult::UpdateNode(result) => Ok(result),
+            HttpResult::Error(err_result) => throw!(Warning::BadResult(err_result)),
+            _ => throw!(Warning::WrongResult(
+                "unexpected result on received 'update_node' response",
+            )),
+        }
+    }
+
+    async fn append_term(
+        &self,
+        target: &Url,
+        input: AppendTermInput,
+    ) -> WarnResult<AppendTermResult> {
+        match self.send(target, Message::AppendTerm(input)).await? {
+            HttpResult::AppendTerm(result) => Ok(result),
+            HttpResult::Error(err_result) => throw!(Warning::BadResult(err_result)),
+            _ => throw!(Warning::WrongResult(
+                "unexpected result on received 'append_term' response",
+            )),
+        }
+    }
+
+    async fn request_vote(
+        &self,
+        target: &Url,
+        input: RequestVoteInput,
+    ) -> WarnResult<RequestVoteResult> {
+        match self.send(target, Message::RequestVote(input)).await? {
+            HttpResult::RequestVote(result) => Ok(result),
+            HttpResult::Error(err_result) => throw!(Warning::BadResult(err_result)),
+            _ => throw!(Warning::WrongResult(
+                "unexpected result on received 'request_vote' response",
+            )),
+        }
+    }
+
+    async fn pre_vote(
+        &self,
+        target: &Url,
+        input: RequestVoteInput,
+    ) -> WarnResult<RequestVoteResult> {
+        match self.send(target, Message::PreVote(input)).await? {
+            HttpResult::RequestVote(result) => Ok(result),
+            HttpResult::Error(err_result) => throw!(Warning::BadResult(err_result)),
+            _ => throw!(Warning::WrongResult(
+                "unexpected result on received 'pre_vote' response",
+            )),
+        }
+    }
+
+    async fn install_snapshot(
+        &self,
+        target: &Url,
+        input: InstallSnapshotInput,
+    ) -> WarnResult<InstallSnapshotResult> {
+        match self.send(target, Message::InstallSnapshot(input)).await? {
+            HttpResult::InstallSnapshot(result) => Ok(result),
+            HttpResult::Error(err_result) => throw!(Warning::BadResult(err_result)),
+            _ => throw!(Warning::WrongResult(
+                "unexpected result on received 'install_snapshot' response",
+            )),
+        }
+    }
+
+    async fn timeout_now(
+        &self,
+        target: &Url,
+        input: TimeoutNowInput,
+    ) -> WarnResult<TimeoutNowResult> {
+        match self.send(target, Message::TimeoutNow(input)).await? {
+            HttpResult::TimeoutNow(result) => Ok(result),
+            HttpResult::Error(err_result) => throw!(Warning::BadResult(err_result)),
+            _ => throw!(Warning::WrongResult(
+                "unexpected result on received 'timeout_now' response",
+            )),
+        }
+    }
+
+    async fn serve(&self, node: Node) -> ErrorResult<()> {
+        trace!("serve {} in memory", self.addr);
+        let (send, mut recv) = mpsc::unbounded_channel::<Envelope>();
+        self.network
+            .nodes
+            .write()
+            .unwrap()
+            .insert(self.addr.clone(), send);
+        while let Some((request, reply)) = recv.recv().await {
+            let node = node.clone();
+            tokio::spawn(async move {
+                let _ = reply.send(dispatch(&node, request).await);
+            });
+        }
+        Ok(())
+    }
+}
+
+/// Call the workflow of the `node` matching the request, like the HTTP
+/// server does.
+async fn dispatch(node: &Node, request: Message) -> HttpResult {
+    match request {
+        Message::UpdateNode(info) => match node.receive_connection_request(info).await {
+            Some(result) => HttpResult::UpdateNode(result),
+            None => server::i_dont_know_the_leader(),
+        },
+        Message::AppendTerm(input) => match node.receive_append_term(input).await {
+            Ok(result) => HttpResult::AppendTerm(result),
+            Err(_) => server::err_append_term_server_generic(),
+        },
+        Message::RequestVote(input) => {
+            HttpResult::RequestVote(node.receive_request_vote(input).await)
+        }
+        Message::PreVote(input) => HttpResult::RequestVote(node.receive_pre_vote(input).await),
+        Message::InstallSnapshot(input) => match node.receive_install_snapshot(input).await {
+            Ok(result) => HttpResult::InstallSnapshot(result),
+            Err(_) => server::err_install_snapshot_server_generic(),
+        },
+        Message::TimeoutNow(input) => HttpResult::TimeoutNow(node.receive_timeout_now(input).await),
+    }
+}
@@ -1,5 +1,8 @@
-#[cfg(not(test))]
 pub mod client;
 pub mod io_msg;
-#[cfg(not(test))]
+mod memory;
 pub mod server;
+mod transport;
+
+pub use memory::{InMemoryNetwork, InMemoryTransport};
+pub use transport::{HttpTransport, Transport};
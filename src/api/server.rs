@@ -1,14 +1,16 @@
-use super::io_msg::{HttpResult, UpdateNodeInput};
+// The server is stubbed with the `mock_api` feature
+#![cfg_attr(feature = "mock_api", allow(dead_code, unused_imports))]
+
+use super::io_msg::{HttpResult, ProposeResult, RemoveNodeResult, UpdateNodeInput};
 use crate::{
-    common::error::{Error, ErrorResult, HttpErrorResult, ServerError},
+    common::error::{ErrorResult, HttpErrorResult, MembershipError, ProposeError, ServerError},
     node::{Node, NodeInfo},
 };
-use hyper::service::{make_service_fn, service_fn};
-use hyper::{body::Bytes, Uri};
-use hyper::{Body, Request, Response, Server};
+use hyper::{body::Bytes, header, Uri};
+use hyper::{Body, Request, Response};
 use hyper::{Method, StatusCode};
 use serde::Deserialize;
-use std::{convert::Infallible, net::SocketAddr};
+use std::net::SocketAddr;
 use tracing::{error, trace};
 
 async fn shutdown_signal() {
@@ -52,6 +54,7 @@ async fn on_receive_update_node(
         .receive_connection_request(NodeInfo {
             hash: input.hash,
             addr,
+            learner: input.learner,
         })
         .await;
     match res {
@@ -95,6 +98,138 @@ async fn on_receive_request_vote(node: &Node, bytes: &Bytes, response: &mut Resp
         .into()
 }
 
+async fn on_receive_pre_vote(node: &Node, bytes: &Bytes, response: &mut Response<Body>) {
+    let res = node
+        .receive_pre_vote(deserialize_body(bytes).unwrap())
+        .await;
+    *response.body_mut() = serde_json::to_string(&HttpResult::RequestVote(res))
+        .unwrap()
+        .into()
+}
+
+async fn on_receive_install_snapshot(node: &Node, bytes: &Bytes, response: &mut Response<Body>) {
+    let res = node
+        .receive_install_snapshot(deserialize_body(bytes).unwrap())
+        .await;
+    match res {
+        Ok(res) => {
+            *response.body_mut() = serde_json::to_string(&HttpResult::InstallSnapshot(res))
+                .unwrap()
+                .into()
+        }
+        Err(_) => {
+            *response.body_mut() = ERR_INSTALL_SNAPSHOT_SERVER_GENERIC.clone().into();
+        }
+    }
+}
+
+/// Client request, the body is the content to propose, binary or not. The
+/// leader answers when the entry is committed, a follower redirects the
+/// client to the leader it knows.
+pub(crate) async fn on_receive_propose(node: &Node, bytes: &Bytes, response: &mut Response<Body>) {
+    trace!("receive propose request");
+    let result = match node.propose(bytes.to_vec()).await {
+        Ok(index) => HttpResult::Propose(ProposeResult { index }),
+        Err(ProposeError::NotLeader(Some(leader))) => {
+            trace!("redirect the client to {leader}");
+            *response.status_mut() = StatusCode::TEMPORARY_REDIRECT;
+            response.headers_mut().insert(
+                header::LOCATION,
+                format!("http://{leader}/propose").parse().unwrap(),
+            );
+            i_am_not_the_leader(leader.to_string())
+        }
+        Err(ProposeError::NotLeader(None)) => {
+            *response.status_mut() = StatusCode::SERVICE_UNAVAILABLE;
+            i_dont_know_the_leader()
+        }
+        Err(ProposeError::LeadershipLost) => {
+            *response.status_mut() = StatusCode::SERVICE_UNAVAILABLE;
+            err_propose_leadership_lost()
+        }
+        Err(ProposeError::TransferInProgress) => {
+            *response.status_mut() = StatusCode::SERVICE_UNAVAILABLE;
+            err_propose_transfer_in_progress()
+        }
+        Err(ProposeError::Failed(err)) => {
+            error!("failed to propose a content, {:?}", err);
+            *response.status_mut() = StatusCode::INTERNAL_SERVER_ERROR;
+            err_propose_server_generic()
+        }
+    };
+    *response.body_mut() = serde_json::to_string(&result).unwrap().into();
+}
+
+/// Admin request, the body is the id of the node to remove from the
+/// voters. The leader answers when the new configuration is committed, a
+/// follower redirects to the leader it knows.
+pub(crate) async fn on_receive_remove_node(
+    node: &Node,
+    bytes: &Bytes,
+    response: &mut Response<Body>,
+) {
+    let node_id = match String::from_utf8(bytes.to_vec()) {
+        Ok(node_id) => node_id,
+        Err(_) => {
+            *response.status_mut() = StatusCode::BAD_REQUEST;
+            *response.body_mut() = ERR_REMOVE_NODE_NOT_UTF8.clone().into();
+            return;
+        }
+    };
+    trace!("receive remove node request for {node_id}");
+    let result = match node.remove_member(node_id).await {
+        Ok(index) => HttpResult::RemoveNode(RemoveNodeResult { index }),
+        Err(MembershipError::NotLeader(Some(leader))) => {
+            trace!("redirect the admin to {leader}");
+            *response.status_mut() = StatusCode::TEMPORARY_REDIRECT;
+            response.headers_mut().insert(
+                header::LOCATION,
+                format!("http://{leader}/remove_node").parse().unwrap(),
+            );
+            i_am_not_the_leader(leader.to_string())
+        }
+        Err(MembershipError::NotLeader(None)) => {
+            *response.status_mut() = StatusCode::SERVICE_UNAVAILABLE;
+            i_dont_know_the_leader()
+        }
+        Err(MembershipError::ChangeInProgress) => {
+            *response.status_mut() = StatusCode::CONFLICT;
+            err_membership_change_in_progress()
+        }
+        Err(MembershipError::UnknownNode(node_id)) => {
+            *response.status_mut() = StatusCode::NOT_FOUND;
+            err_unknown_node(node_id)
+        }
+        Err(MembershipError::NotCaughtUp(node_id)) => {
+            *response.status_mut() = StatusCode::CONFLICT;
+            err_learner_not_caught_up(node_id)
+        }
+        Err(MembershipError::EmptyConfiguration) => {
+            *response.status_mut() = StatusCode::BAD_REQUEST;
+            err_empty_configuration()
+        }
+        Err(MembershipError::LeadershipLost) => {
+            *response.status_mut() = StatusCode::SERVICE_UNAVAILABLE;
+            err_membership_leadership_lost()
+        }
+        Err(MembershipError::Failed(err)) => {
+            error!("failed to remove a node, {:?}", err);
+            *response.status_mut() = StatusCode::INTERNAL_SERVER_ERROR;
+            err_remove_node_server_generic()
+        }
+    };
+    *response.body_mut() = serde_json::to_string(&result).unwrap().into();
+}
+
+async fn on_receive_timeout_now(node: &Node, bytes: &Bytes, response: &mut Response<Body>) {
+    let res = node
+        .receive_timeout_now(deserialize_body(bytes).unwrap())
+        .await;
+    *response.body_mut() = serde_json::to_string(&HttpResult::TimeoutNow(res))
+        .unwrap()
+        .into()
+}
+
 async fn dispatch_commands(
     body: Body,
     method: &Method,
@@ -114,6 +249,17 @@ async fn dispatch_commands(
         (&Method::POST, "/request_vote") => {
             on_receive_request_vote(node, &bytes, &mut response).await
         }
+        (&Method::POST, "/pre_vote") => on_receive_pre_vote(node, &bytes, &mut response).await,
+        (&Method::POST, "/install_snapshot") => {
+            on_receive_install_snapshot(node, &bytes, &mut response).await
+        }
+        (&Method::POST, "/timeout_now") => {
+            on_receive_timeout_now(node, &bytes, &mut response).await
+        }
+        (&Method::POST, "/propose") => on_receive_propose(node, &bytes, &mut response).await,
+        (&Method::POST, "/remove_node") => {
+            on_receive_remove_node(node, &bytes, &mut response).await
+        }
         _ => {
             *response.status_mut() = StatusCode::NOT_FOUND;
         }
@@ -129,8 +275,8 @@ fn manage_server_error(
     // to the error we get.
     match result {
         Ok(response) => Ok(response),
-        Err(err) => {
-            error!("Server error: {:?}", err);
+        Err(ServerError::CannotDeserializeBody(message)) => {
+            error!("Server error: {}", message);
             Err("Server error")
         }
     }
@@ -151,8 +297,11 @@ async fn service(
 
 #[cfg(not(feature = "mock_api"))]
 pub async fn new(node: Node) -> ErrorResult<()> {
-    use crate::common::error::throw;
+    use crate::common::error::{throw, Error};
     use hyper::server::conn::AddrStream;
+    use hyper::service::{make_service_fn, service_fn};
+    use hyper::Server;
+    use std::convert::Infallible;
 
     let full_addr = &format!("{}:{}", node.settings.addr, node.settings.port);
     trace!("Startup server on {}", full_addr);
@@ -180,7 +329,7 @@ pub async fn new(node: Node) -> ErrorResult<()> {
 }
 
 #[cfg(feature = "mock_api")]
-pub async fn new(node: Node) -> ErrorResult<()> {
+pub async fn new(_node: Node) -> ErrorResult<()> {
     Ok(())
 }
 
@@ -188,21 +337,119 @@ pub async fn new(node: Node) -> ErrorResult<()> {
 /* ERRORS USED BY THE SERVER API              **/
 /***********************************************/
 
+pub(crate) fn i_dont_know_the_leader() -> HttpResult {
+    HttpResult::Error(HttpErrorResult {
+        err_id: "512".to_string(),
+        message: "sorry I don't know the leader of the network".to_string(),
+    })
+}
+
+pub(crate) fn err_append_term_server_generic() -> HttpResult {
+    HttpResult::Error(HttpErrorResult {
+        err_id: "513".to_string(),
+        message: "Server side generic error on append term".to_string(),
+    })
+}
+
+pub(crate) fn err_install_snapshot_server_generic() -> HttpResult {
+    HttpResult::Error(HttpErrorResult {
+        err_id: "514".to_string(),
+        message: "Server side generic error on install snapshot".to_string(),
+    })
+}
+
+pub(crate) fn i_am_not_the_leader(leader: String) -> HttpResult {
+    HttpResult::Error(HttpErrorResult {
+        err_id: "515".to_string(),
+        message: format!("I'm not the leader, the leader is {leader}"),
+    })
+}
+
+pub(crate) fn err_propose_leadership_lost() -> HttpResult {
+    HttpResult::Error(HttpErrorResult {
+        err_id: "516".to_string(),
+        message: "leadership lost before the commit of the content".to_string(),
+    })
+}
+
+pub(crate) fn err_propose_server_generic() -> HttpResult {
+    HttpResult::Error(HttpErrorResult {
+        err_id: "517".to_string(),
+        message: "Server side generic error on propose".to_string(),
+    })
+}
+
+pub(crate) fn err_propose_transfer_in_progress() -> HttpResult {
+    HttpResult::Error(HttpErrorResult {
+        err_id: "519".to_string(),
+        message: "the leader is transferring its lead, retry later".to_string(),
+    })
+}
+
+pub(crate) fn err_membership_change_in_progress() -> HttpResult {
+    HttpResult::Error(HttpErrorResult {
+        err_id: "520".to_string(),
+        message: "a membership change is already in progress".to_string(),
+    })
+}
+
+pub(crate) fn err_unknown_node(node_id: String) -> HttpResult {
+    HttpResult::Error(HttpErrorResult {
+        err_id: "521".to_string(),
+        message: format!("{node_id} isn't a member of the cluster"),
+    })
+}
+
+pub(crate) fn err_empty_configuration() -> HttpResult {
+    HttpResult::Error(HttpErrorResult {
+        err_id: "522".to_string(),
+        message: "the cluster needs at least one voter".to_string(),
+    })
+}
+
+pub(crate) fn err_membership_leadership_lost() -> HttpResult {
+    HttpResult::Error(HttpErrorResult {
+        err_id: "523".to_string(),
+        message: "leadership lost before the commit of the membership change".to_string(),
+    })
+}
+
+pub(crate) fn err_remove_node_server_generic() -> HttpResult {
+    HttpResult::Error(HttpErrorResult {
+        err_id: "524".to_string(),
+        message: "Server side generic error on remove node".to_string(),
+    })
+}
+
+pub(crate) fn err_remove_node_not_utf8() -> HttpResult {
+    HttpResult::Error(HttpErrorResult {
+        err_id: "525".to_string(),
+        message: "the node id isn't valid UTF-8".to_string(),
+    })
+}
+
+pub(crate) fn err_learner_not_caught_up(node_id: String) -> HttpResult {
+    HttpResult::Error(HttpErrorResult {
+        err_id: "526".to_string(),
+        message: format!("{node_id} doesn't have all the committed entries"),
+    })
+}
+
 lazy_static::lazy_static! {
     pub static ref I_DONT_NOW_THE_LEADER: String = {
-        serde_json::to_string(&HttpResult::Error(HttpErrorResult {
-            err_id: "512".to_string(),
-            message: "sorry I don't know the leader of the network".to_string(),
-        }))
-        .unwrap()
+        serde_json::to_string(&i_dont_know_the_leader()).unwrap()
     };
 
     pub static ref ERR_APPEND_TERM_SERVER_GENERIC: String = {
-        serde_json::to_string(&HttpResult::Error(HttpErrorResult {
-            err_id: "513".to_string(),
-            message: "Server side generic error on append term".to_string(),
-        }))
-        .unwrap()
+        serde_json::to_string(&err_append_term_server_generic()).unwrap()
+    };
+
+    pub static ref ERR_INSTALL_SNAPSHOT_SERVER_GENERIC: String = {
+        serde_json::to_string(&err_install_snapshot_server_generic()).unwrap()
+    };
+
+    pub static ref ERR_REMOVE_NODE_NOT_UTF8: String = {
+        serde_json::to_string(&err_remove_node_not_utf8()).unwrap()
     };
 }
 
@@ -211,4 +458,6 @@ lazy_static::lazy_static! {
 fn deser_server_err() {
     let _ = *I_DONT_NOW_THE_LEADER;
     let _ = *ERR_APPEND_TERM_SERVER_GENERIC;
+    let _ = *ERR_INSTALL_SNAPSHOT_SERVER_GENERIC;
+    let _ = *ERR_REMOVE_NODE_NOT_UTF8;
 }
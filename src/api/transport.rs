@@ -0,0 +1,142 @@
+// License:
+// This source code is licensed under the GPLv3 license, you can found the
+// LICENSE file in the root directory of this source tree.
+
+//! Transport used by a node to send requests to the other nodes and to
+//! receive theirs. A node uses the HTTP transport by default, the requests
+//! are served by hyper on `addr:port` (see the settings). Use
+//! `Node::with_transport` to replace it, for example with an
+//! [InMemoryTransport](super::InMemoryTransport) to run several nodes in
+//! the same process.
+
+use super::{
+    client,
+    io_msg::{
+        AppendTermInput, AppendTermResult, InstallSnapshotInput, InstallSnapshotResult,
+        RequestVoteInput, RequestVoteResult, TimeoutNowInput, TimeoutNowResult, UpdateNodeResult,
+    },
+    server,
+};
+use crate::{
+    common::{
+        config::Settings,
+        error::{ErrorResult, WarnResult},
+        Url,
+    },
+    node::Node,
+};
+use async_trait::async_trait;
+
+#[async_trait]
+pub trait Transport: Send + Sync {
+    /// Send a connection request with the unique id of the local node,
+    /// answered by `Node::receive_connection_request`. `learner` is true if
+    /// the local node never votes.
+    async fn update_node(
+        &self,
+        target: &Url,
+        uuid: [u8; 16],
+        learner: bool,
+    ) -> WarnResult<UpdateNodeResult>;
+    async fn append_term(
+        &self,
+        target: &Url,
+        input: AppendTermInput,
+    ) -> WarnResult<AppendTermResult>;
+    async fn request_vote(
+        &self,
+        target: &Url,
+        input: RequestVoteInput,
+    ) -> WarnResult<RequestVoteResult>;
+    /// Ask if the `target` would grant its vote, answered by
+    /// `Node::receive_pre_vote`. Nothing changes on the target.
+    async fn pre_vote(
+        &self,
+        target: &Url,
+        input: RequestVoteInput,
+    ) -> WarnResult<RequestVoteResult>;
+    async fn install_snapshot(
+        &self,
+        target: &Url,
+        input: InstallSnapshotInput,
+    ) -> WarnResult<InstallSnapshotResult>;
+    /// Ask the `target` to start an election now, answered by
+    /// `Node::receive_timeout_now`.
+    async fn timeout_now(
+        &self,
+        target: &Url,
+        input: TimeoutNowInput,
+    ) -> WarnResult<TimeoutNowResult>;
+    /// Receive the requests of the other nodes and dispatch them to the
+    /// `node`. Run in a background task as long as the node lives.
+    async fn serve(&self, node: Node) -> ErrorResult<()>;
+}
+
+/// Default transport, requests are JSON bodies posted over HTTP.
+pub struct HttpTransport {
+    settings: Settings,
+}
+
+impl HttpTransport {
+    pub fn new(settings: &Settings) -> Self {
+        Self {
+            settings: settings.clone(),
+        }
+    }
+}
+
+#[async_trait]
+impl Transport for HttpTransport {
+    async fn update_node(
+        &self,
+        target: &Url,
+        uuid: [u8; 16],
+        learner: bool,
+    ) -> WarnResult<UpdateNodeResult> {
+        client::post_update_node(target, &self.settings, uuid, learner).await
+    }
+
+    async fn append_term(
+        &self,
+        target: &Url,
+        input: AppendTermInput,
+    ) -> WarnResult<AppendTermResult> {
+        client::post_append_term(target, &self.settings, input).await
+    }
+
+    async fn request_vote(
+        &self,
+        target: &Url,
+        input: RequestVoteInput,
+    ) -> WarnResult<RequestVoteResult> {
+        client::post_request_vote(target, &self.settings, input).await
+    }
+
+    async fn pre_vote(
+        &self,
+        target: &Url,
+        input: RequestVoteInput,
+    ) -> WarnResult<RequestVoteResult> {
+        client::post_pre_vote(target, &self.settings, input).await
+    }
+
+    async fn install_snapshot(
+        &self,
+        target: &Url,
+        input: InstallSnapshotInput,
+    ) -> WarnResult<InstallSnapshotResult> {
+        client::post_install_snapshot(target, &self.settings, input).await
+    }
+
+    async fn timeout_now(
+        &self,
+        target: &Url,
+        input: TimeoutNowInput,
+    ) -> WarnResult<TimeoutNowResult> {
+        client::post_timeout_now(target, &self.settings, input).await
+    }
+
+    async fn serve(&self, node: Node) -> ErrorResult<()> {
+        server::new(node).await
+    }
+}
@@ -33,9 +33,30 @@ const fn default_timeout_max() -> usize {
 const fn default_prepare_term_period() -> u64 {
     80
 }
+const fn default_send_term_period() -> u64 {
+    50
+}
 const fn default_node_id() -> String {
     String::new()
 }
+const fn default_data_dir() -> Option<String> {
+    None
+}
+const fn default_snapshot_threshold() -> usize {
+    1000
+}
+const fn default_snapshot_timeout() -> u64 {
+    10000
+}
+const fn default_suspect_after() -> usize {
+    3
+}
+const fn default_max_backoff() -> u64 {
+    1000
+}
+const fn default_evict_after() -> Option<u64> {
+    None
+}
 
 /// Represent the user settings in the settings.toml
 #[derive(Debug, Deserialize, Clone)]
@@ -56,20 +77,60 @@ pub struct Settings {
     pub response_timeout: usize,
     #[serde(default = "default_prepare_term_period")]
     pub prepare_term_period: u64,
+    /// Value in millisecond that separe two sending sessions of a leader,
+    /// should be lower than `timeout_min`
+    #[serde(default = "default_send_term_period")]
+    pub send_term_period: u64,
     #[serde(default = "default_node_id")]
     pub node_id: String,
+    /// Directory where the node persists the state that must survive a
+    /// restart. Nothing is written on disk if `None`.
+    #[serde(default = "default_data_dir")]
+    pub data_dir: Option<String>,
+    /// Number of committed terms in the logs before taking a snapshot and
+    /// compacting the logs. Zero to never compact.
+    #[serde(default = "default_snapshot_threshold")]
+    pub snapshot_threshold: usize,
+    /// Value in millisecond the leader waits for a node to install a
+    /// snapshot, the hook restores the whole state meanwhile
+    #[serde(default = "default_snapshot_timeout")]
+    pub snapshot_timeout: u64,
+    /// Number of consecutive failed calls before the leader suspects a
+    /// peer is down and calls the `lost_connection` hook
+    #[serde(default = "default_suspect_after")]
+    pub suspect_after: usize,
+    /// Maximum value in millisecond between two calls of the leader to a
+    /// suspect peer
+    #[serde(default = "default_max_backoff")]
+    pub max_backoff: u64,
+    /// Value in millisecond a voter stays suspect before the leader
+    /// proposes its removal. The suspect voters are never removed if `None`.
+    #[serde(default = "default_evict_after")]
+    pub evict_after: Option<u64>,
 }
 
 impl Settings {
     /// Compute a random heartbeat timeout before it start a candidate
-    /// workflow. Use range `[timeout_min..=timeout_max]`
-    pub fn get_randomized_timeout(&self) -> Duration {
-        let mut rng = rand::thread_rng();
+    /// workflow. Use range `[timeout_min..=timeout_max]`, drawn from the
+    /// random generator of the node.
+    pub fn get_randomized_timeout<R: Rng>(&self, rng: &mut R) -> Duration {
         Duration::from_millis(rng.gen_range(self.timeout_min..=self.timeout_max) as u64)
     }
     pub fn get_prepare_term_sleep_duration(&self) -> Duration {
         Duration::from_millis(self.prepare_term_period)
     }
+    pub fn get_send_term_sleep_duration(&self) -> Duration {
+        Duration::from_millis(self.send_term_period)
+    }
+    pub fn get_snapshot_timeout_duration(&self) -> Duration {
+        Duration::from_millis(self.snapshot_timeout)
+    }
+    pub fn get_max_backoff_duration(&self) -> Duration {
+        Duration::from_millis(self.max_backoff)
+    }
+    pub fn get_evict_after_duration(&self) -> Option<Duration> {
+        self.evict_after.map(Duration::from_millis)
+    }
 }
 
 impl Default for Settings {
@@ -83,7 +144,14 @@ impl Default for Settings {
             follower: default_follower(),
             response_timeout: default_response_timeout(),
             prepare_term_period: default_prepare_term_period(),
+            send_term_period: default_send_term_period(),
             node_id: default_node_id(),
+            data_dir: default_data_dir(),
+            snapshot_threshold: default_snapshot_threshold(),
+            snapshot_timeout: default_snapshot_timeout(),
+            suspect_after: default_suspect_after(),
+            max_backoff: default_max_backoff(),
+            evict_after: default_evict_after(),
         }
     }
 }
@@ -0,0 +1,302 @@
+// License:
+// This source code is licensed under the GPLv3 license, you can found the
+// LICENSE file in the root directory of this source tree.
+
+//! Co-process mode of the default hook. Instead of a script by call, the
+//! node starts one helper process and sends it the calls as JSON lines on
+//! its standard input. The helper answers each request with one JSON line
+//! on its standard output.
+//!
+//! ```text
+//! > {"id":1,"method":"commit_term","params":{"term":{"index":3,...}}}
+//! < {"id":1,"result":null}
+//! > {"id":2,"method":"prepare_term","params":{}}
+//! < {"id":2,"error":{"kind":"failed","message":"database unavailable"}}
+//! ```
+//!
+//! The contents of the terms, the result of `prepare_term` and `snapshot`,
+//! and the `data` of `restore_snapshot` are base64 strings.
+//!
+//! The kind of an error is `rejected`, `failed` or `fatal`, see
+//! [HookError]. A helper that doesn't answer in time is killed, a helper
+//! that exits is restarted with the next call. The call fails in both
+//! cases.
+
+use super::{
+    base64_bytes::Base64,
+    error::{HookError, HookResult},
+    hook_trait::AsyncHook,
+    scripts::status_name,
+};
+use crate::{log_entry::Term, state::EStatus};
+use async_trait::async_trait;
+use serde::{de::DeserializeOwned, Deserialize, Serialize};
+use serde_json::{json, Value};
+use std::{process::Stdio, time::Duration};
+use tokio::{
+    io::{AsyncBufReadExt, AsyncWriteExt, BufReader, Lines},
+    process::{Child, ChildStdin, ChildStdout, Command},
+    sync::Mutex,
+};
+use tracing::{debug, warn};
+
+/// Hook that talks to a long-lived helper process, see the module
+/// documentation for the protocol.
+pub struct CoprocessHook {
+    program: String,
+    args: Vec<String>,
+    /// Maximum time of a call, request and response included
+    timeout: Duration,
+    /// Running helper, started with the first call
+    helper: Mutex<Option<Helper>>,
+}
+
+struct Helper {
+    /// Killed when the helper is dropped
+    _child: Child,
+    stdin: ChildStdin,
+    stdout: Lines<BufReader<ChildStdout>>,
+    next_id: u64,
+}
+
+#[derive(Serialize)]
+struct Request<'a> {
+    id: u64,
+    method: &'a str,
+    params: Value,
+}
+
+#[derive(Deserialize)]
+struct Response {
+    id: u64,
+    #[serde(default)]
+    result: Value,
+    #[serde(default)]
+    error: Option<ResponseError>,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "snake_case")]
+enum ErrorKind {
+    Rejected,
+    Failed,
+    Fatal,
+}
+
+/// Error answered to a call, also used by the webhooks
+#[derive(Deserialize)]
+pub(super) struct ResponseError {
+    kind: ErrorKind,
+    #[serde(default)]
+    message: String,
+}
+
+impl From<ResponseError> for HookError {
+    fn from(error: ResponseError) -> Self {
+        match error.kind {
+            ErrorKind::Rejected => HookError::Rejected(error.message),
+            ErrorKind::Failed => HookError::Failed(error.message),
+            ErrorKind::Fatal => HookError::Fatal(error.message),
+        }
+    }
+}
+
+impl CoprocessHook {
+    /// Hook running the helper `program`, each call times out after one
+    /// second by default.
+    pub fn new(program: impl Into<String>) -> Self {
+        Self {
+            program: program.into(),
+            args: vec![],
+            timeout: Duration::from_secs(1),
+            helper: Mutex::new(None),
+        }
+    }
+
+    /// Arguments given to the helper when it starts
+    pub fn with_args(self, args: Vec<String>) -> Self {
+        Self { args, ..self }
+    }
+
+    /// Maximum duration of a call, the helper is killed after that delay
+    pub fn with_timeout(self, timeout: Duration) -> Self {
+        Self { timeout, ..self }
+    }
+
+    fn spawn(&self) -> HookResult<Helper> {
+        debug!("start the hook helper {}", self.program);
+        let mut child = Command::new(&self.program)
+            .args(&self.args)
+            .stdin(Stdio::piped())
+            .stdout(Stdio::piped())
+            .kill_on_drop(true)
+            .spawn()
+            .map_err(|err| HookError::Failed(format!("unable to start {}, {err}", self.program)))?;
+        let stdin = child.stdin.take().expect("piped stdin");
+        let stdout = child.stdout.take().expect("piped stdout");
+        Ok(Helper {
+            _child: child,
+            stdin,
+            stdout: BufReader::new(stdout).lines(),
+            next_id: 1,
+        })
+    }
+
+    /// Send the request to the helper and parse the result. The helper is
+    /// dropped, and so killed, if it doesn't answer properly in time.
+    async fn call<R: DeserializeOwned>(&self, method: &str, params: Value) -> HookResult<R> {
+        let mut guard = self.helper.lock().await;
+        if guard.is_none() {
+            *guard = Some(self.spawn()?);
+        }
+        let helper = guard.as_mut().unwrap();
+        let res = match tokio::time::timeout(self.timeout, helper.call(method, params)).await {
+            Ok(res) => res,
+            Err(_) => Err(HookError::Failed(format!(
+                "{method} timed out after {:?}",
+                self.timeout
+            ))),
+        };
+        let result = match res {
+            Ok(Ok(result)) => result,
+            Ok(Err(err)) => return Err(err),
+            Err(err) => {
+                warn!("restart the hook helper, {err}");
+                *guard = None;
+                return Err(err);
+            }
+        };
+        serde_json::from_value(result)
+            .map_err(|err| HookError::Failed(format!("unexpected result of {method}, {err}")))
+    }
+
+    /// Call for an event, a failure is only logged
+    async fn notify(&self, method: &str, params: Value) {
+        if let Err(err) = self.call::<Value>(method, params).await {
+            warn!("{method}: {err}");
+        }
+    }
+}
+
+impl Helper {
+    /// Exchange a request and its response. The outer error means that the
+    /// helper is broken, the inner one is the error it answered.
+    async fn call(&mut self, method: &str, params: Value) -> HookResult<HookResult<Value>> {
+        let id = self.next_id;
+        self.next_id += 1;
+        let mut line = serde_json::to_string(&Request { id, method, params })
+            .map_err(|err| HookError::Failed(err.to_string()))?;
+        line.push('\n');
+        let broken = |err: std::io::Error| HookError::Failed(format!("hook helper: {err}"));
+        self.stdin
+            .write_all(line.as_bytes())
+            .await
+            .map_err(broken)?;
+        self.stdin.flush().await.map_err(broken)?;
+        loop {
+            let line = match self.stdout.next_line().await.map_err(broken)? {
+                Some(line) => line,
+                None => return Err(HookError::Failed("hook helper exited".into())),
+            };
+            let response: Response = serde_json::from_str(&line)
+                .map_err(|err| HookError::Failed(format!("invalid response {line:?}, {err}")))?;
+            if response.id != id {
+                debug!("ignore the response {} of the hook helper", response.id);
+                continue;
+            }
+            return Ok(match response.error {
+                None => Ok(response.result),
+                Some(error) => Err(error.into()),
+            });
+        }
+    }
+}
+
+#[async_trait]
+impl AsyncHook for CoprocessHook {
+    async fn update_node(&self) -> HookResult<()> {
+        self.call("update_node", json!({})).await
+    }
+
+    async fn pre_append_term(&self, term: &Term) -> HookResult<usize> {
+        self.call("pre_append_term", json!({ "term": term })).await
+    }
+
+    async fn append_term(&self, term: &Term) -> HookResult<()> {
+        self.call("append_term", json!({ "term": term })).await
+    }
+
+    async fn commit_term(&self, term: &Term) -> HookResult<()> {
+        self.call("commit_term", json!({ "term": term })).await
+    }
+
+    async fn prepare_term(&self) -> HookResult<Vec<u8>> {
+        let content: Base64 = self.call("prepare_term", json!({})).await?;
+        Ok(content.0)
+    }
+
+    async fn retreive_term(&self, index: usize) -> HookResult<Term> {
+        self.call("retreive_term", json!({ "index": index })).await
+    }
+
+    async fn retreive_terms(&self, from: usize, to: usize) -> HookResult<Vec<Term>> {
+        self.call("retreive_terms", json!({ "from": from, "to": to }))
+            .await
+    }
+
+    async fn switch_status(&self, status: EStatus) {
+        self.notify("switch_status", json!({ "status": status_name(status) }))
+            .await
+    }
+
+    async fn snapshot(&self, index: usize) -> HookResult<Option<Vec<u8>>> {
+        let data: Option<Base64> = self.call("snapshot", json!({ "index": index })).await?;
+        Ok(data.map(|data| data.0))
+    }
+
+    async fn restore_snapshot(&self, index: usize, data: &[u8]) -> HookResult<()> {
+        let data = base64::encode(data);
+        self.call("restore_snapshot", json!({ "index": index, "data": data }))
+            .await
+    }
+
+    async fn remove_connection(&self, node: &str) {
+        self.notify("remove_connection", json!({ "node": node }))
+            .await
+    }
+
+    async fn lost_connection(&self, node: &str) {
+        self.notify("lost_connection", json!({ "node": node }))
+            .await
+    }
+
+    async fn leader_change(&self, leader: &str) {
+        self.notify("leader_change", json!({ "leader": leader }))
+            .await
+    }
+
+    async fn pre_add_connection(&self, node: &str) -> HookResult<()> {
+        self.call("pre_add_connection", json!({ "node": node }))
+            .await
+    }
+
+    async fn post_add_connection(&self, node: &str) {
+        self.notify("post_add_connection", json!({ "node": node }))
+            .await
+    }
+
+    async fn request_vote(&self, candidate: &str, granted: bool) {
+        let params = json!({ "candidate": candidate, "granted": granted });
+        self.notify("request_vote", params).await
+    }
+
+    async fn receive_vote(&self, node: &str, granted: bool) {
+        let params = json!({ "node": node, "granted": granted });
+        self.notify("receive_vote", params).await
+    }
+
+    async fn send_term(&self, node: &str, last_index: usize, success: bool) {
+        let params = json!({ "node": node, "last_index": last_index, "success": success });
+        self.notify("send_term", params).await
+    }
+}
@@ -1,8 +1,10 @@
+use super::Url;
 use config::ConfigError;
 use serde::{Deserialize, Serialize};
 
 pub type ErrorResult<T> = std::result::Result<T, Box<Error>>;
 pub type WarnResult<T> = std::result::Result<T, Box<Warning>>;
+pub type HookResult<T> = std::result::Result<T, HookError>;
 
 macro_rules! throw {
     ($err: expr) => {
@@ -38,6 +40,41 @@ pub enum Error {
     InitializationFail(&'static str),
     ImpossibleToBootstrap,
     WrongStatus,
+    CannotLoadState(String),
+    CannotPersistState(String),
+    LogStoreFailure(String),
+    /// A hook returned a fatal error, see `HookError::Fatal`
+    HookFailure(HookError),
+}
+
+/// Failure of a hook. The node reacts depending on the call, see
+/// [crate::AsyncHook]. A fatal error always stops the node, `Node::start`
+/// returns `Error::HookFailure`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum HookError {
+    /// The hook refuses the call, for example a term that doesn't pass the
+    /// checks of the application
+    Rejected(String),
+    /// The hook failed to handle the call, the node may call it again later
+    Failed(String),
+    /// The hook can't go on, the node stops
+    Fatal(String),
+}
+
+impl HookError {
+    pub fn is_fatal(&self) -> bool {
+        matches!(self, HookError::Fatal(_))
+    }
+}
+
+impl std::fmt::Display for HookError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            HookError::Rejected(reason) => write!(f, "rejected by the hook: {reason}"),
+            HookError::Failed(reason) => write!(f, "hook failed: {reason}"),
+            HookError::Fatal(reason) => write!(f, "fatal hook error: {reason}"),
+        }
+    }
 }
 
 #[derive(Debug)]
@@ -60,6 +97,64 @@ pub enum Warning {
     WrongResult(&'static str),  // When a request return an unexpected result
 }
 
+/// Reason why a proposed content isn't committed, see `Node::propose`.
+#[derive(Debug)]
+pub enum ProposeError {
+    /// The node isn't the leader, contains the leader it knows if any
+    NotLeader(Option<Url>),
+    /// The node lost the lead before the commit of the entry, the next
+    /// leader commits it or replaces it by one of its entries
+    LeadershipLost,
+    /// The leader is transferring its lead to another node
+    TransferInProgress,
+    /// The content couldn't be appended to the logs
+    Failed(Box<Error>),
+}
+
+/// Reason why a linearizable read can't be served, see `Node::read_index`.
+#[derive(Debug)]
+pub enum ReadIndexError {
+    /// The node isn't the leader, contains the leader it knows if any
+    NotLeader(Option<Url>),
+    /// The node lost the lead before confirming the read
+    LeadershipLost,
+}
+
+/// Reason why the voters don't change, see `Node::change_membership`.
+#[derive(Debug)]
+pub enum MembershipError {
+    /// The node isn't the leader, contains the leader it knows if any
+    NotLeader(Option<Url>),
+    /// The previous change isn't committed yet, or the lead is transferred
+    ChangeInProgress,
+    /// A configuration needs at least one voter
+    EmptyConfiguration,
+    /// The node to remove isn't a member of the cluster, or the node to
+    /// promote isn't a learner
+    UnknownNode(String),
+    /// The learner to promote doesn't have all the committed entries
+    NotCaughtUp(String),
+    /// The node lost the lead before the commit of the change
+    LeadershipLost,
+    /// The configuration couldn't be appended to the logs
+    Failed(Box<Error>),
+}
+
+/// Reason why the lead isn't transferred, see `Node::transfer_leadership`.
+#[derive(Debug)]
+pub enum TransferError {
+    /// The node isn't the leader, contains the leader it knows if any
+    NotLeader(Option<Url>),
+    /// The target isn't a voter of the cluster
+    UnknownNode(Url),
+    /// The target didn't start an election
+    Refused,
+    /// The target didn't take the lead before an election timeout
+    Timeout,
+    /// The append terms to the target failed
+    Failed(Box<Error>),
+}
+
 impl std::fmt::Display for Warning {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         match self {
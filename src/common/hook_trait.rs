@@ -1,4 +1,75 @@
-use crate::{log_entry::Term, state::EStatus};
+use crate::{
+    common::{
+        error::{HookError, HookResult},
+        Url,
+    },
+    log_entry::Term,
+    state::EStatus,
+};
+use async_trait::async_trait;
+use std::sync::{
+    atomic::{AtomicUsize, Ordering},
+    Arc,
+};
+use tokio::sync::watch;
+
+/// View of the state of the node, given to the hook when the node starts
+/// with `set_context`. The values follow the node, a hook keeps the context
+/// and reads it when it needs.
+#[derive(Clone)]
+pub struct NodeContext {
+    node_id: String,
+    status: watch::Receiver<(EStatus, Option<Url>)>,
+    applied: watch::Receiver<usize>,
+    last_index: Arc<AtomicUsize>,
+}
+
+impl NodeContext {
+    pub(crate) fn new(
+        node_id: String,
+        status: watch::Receiver<(EStatus, Option<Url>)>,
+        applied: watch::Receiver<usize>,
+        last_index: Arc<AtomicUsize>,
+    ) -> Self {
+        Self {
+            node_id,
+            status,
+            applied,
+            last_index,
+        }
+    }
+
+    /// Address of the node, its identifier in the cluster
+    pub fn node_id(&self) -> &str {
+        &self.node_id
+    }
+
+    pub fn status(&self) -> EStatus {
+        self.status.borrow().0
+    }
+
+    /// Address of the leader known by the node, the node itself if it leads
+    pub fn leader(&self) -> Option<String> {
+        let status = self.status.borrow();
+        match status.0 {
+            EStatus::Leader => Some(self.node_id.clone()),
+            _ => status.1.as_ref().map(|leader| leader.to_string()),
+        }
+    }
+
+    /// Index of the latest term committed by the hook
+    pub fn commit_index(&self) -> usize {
+        *self.applied.borrow()
+    }
+
+    /// Index of the latest term in the logs
+    pub fn last_index(&self) -> usize {
+        self.last_index.load(Ordering::Relaxed)
+    }
+}
+
+/// Synchronous hook, the node runs it on the blocking threads of tokio with
+/// a [BlockingHook]. See [AsyncHook] for the description of the methods.
 pub trait Hook: Send + Sync {
     // all script method are fn
     // when declaring a Node, give an struct that impl Hook
@@ -13,12 +84,224 @@ pub trait Hook: Send + Sync {
     // hook : <-- default mode in binary
     // - binary is lib + reading script folder
     // - service.systemd reading sockets
-    fn update_node(&self) -> bool;
-    fn pre_append_term(&self, term: &Term) -> Option<usize>;
-    fn append_term(&self, term: &Term) -> bool;
-    fn commit_term(&self, term: &Term) -> bool;
-    fn prepare_term(&self) -> String;
-    fn retreive_term(&self, index: usize) -> Option<Term>;
-    fn retreive_terms(&self, from: usize, to: usize) -> Option<Vec<Term>>;
+    fn update_node(&self) -> HookResult<()>;
+    fn pre_append_term(&self, term: &Term) -> HookResult<usize>;
+    fn append_term(&self, term: &Term) -> HookResult<()>;
+    fn commit_term(&self, term: &Term) -> HookResult<()>;
+    fn prepare_term(&self) -> HookResult<Vec<u8>>;
+    fn retreive_term(&self, index: usize) -> HookResult<Term>;
+    fn retreive_terms(&self, from: usize, to: usize) -> HookResult<Vec<Term>>;
     fn switch_status(&self, status: EStatus);
+    fn snapshot(&self, _index: usize) -> HookResult<Option<Vec<u8>>> {
+        Ok(None)
+    }
+    fn restore_snapshot(&self, _index: usize, _data: &[u8]) -> HookResult<()> {
+        Err(HookError::Rejected(
+            "snapshots aren't supported".to_string(),
+        ))
+    }
+    fn remove_connection(&self, _node: &str) {}
+    fn lost_connection(&self, _node: &str) {}
+    fn leader_change(&self, _leader: &str) {}
+    fn pre_add_connection(&self, _node: &str) -> HookResult<()> {
+        Ok(())
+    }
+    fn post_add_connection(&self, _node: &str) {}
+    fn request_vote(&self, _candidate: &str, _granted: bool) {}
+    fn receive_vote(&self, _node: &str, _granted: bool) {}
+    fn send_term(&self, _node: &str, _last_index: usize, _success: bool) {}
+    fn set_context(&self, _context: NodeContext) {}
+}
+
+/// Interface between the node and the application. The node awaits the
+/// hook, a hook that talks to a database or another service doesn't block
+/// the runtime of the node.
+///
+/// A `HookError::Fatal` stops the node whatever the call, the other errors
+/// are handled as described on each method.
+#[async_trait]
+pub trait AsyncHook: Send + Sync {
+    /// A node asks to join the cluster. An error refuses the connection.
+    async fn update_node(&self) -> HookResult<()>;
+    /// Check a term sent by the leader before appending it. Return the
+    /// first index missing in the application, the term is rejected if it's
+    /// lower than the index of the term. An error rejects the term, the
+    /// node replies `success: false` to the leader.
+    async fn pre_append_term(&self, term: &Term) -> HookResult<usize>;
+    /// A term is appended to the logs. On a follower an error rejects the
+    /// term, the leader sends it again later. On the leader an error makes
+    /// the node step down.
+    async fn append_term(&self, term: &Term) -> HookResult<()>;
+    /// A term is committed, the terms are committed in order. The internal
+    /// terms are committed too, see `Term::is_internal`. An error stops the
+    /// commit at that term, the node calls the hook again with the next
+    /// commit.
+    async fn commit_term(&self, term: &Term) -> HookResult<()>;
+    /// Content of the next term of the leader. An error skips the term, a
+    /// rejection means that there is nothing to prepare.
+    async fn prepare_term(&self) -> HookResult<Vec<u8>>;
+    /// Rebuild a term that isn't in the logs anymore. An error makes the
+    /// leader step down, it can't send the term.
+    async fn retreive_term(&self, index: usize) -> HookResult<Term>;
+    async fn retreive_terms(&self, from: usize, to: usize) -> HookResult<Vec<Term>>;
+    async fn switch_status(&self, status: EStatus);
+    /// Produce a snapshot of the state machine with all the terms up to
+    /// `index` included. Return `None` if snapshots aren't supported, the
+    /// log is never compacted in that case. An error skips the compaction.
+    async fn snapshot(&self, _index: usize) -> HookResult<Option<Vec<u8>>> {
+        Ok(None)
+    }
+    /// Replace the state machine with the snapshot sent by the leader. An
+    /// error fails the installation, the leader sends the snapshot again.
+    async fn restore_snapshot(&self, _index: usize, _data: &[u8]) -> HookResult<()> {
+        Err(HookError::Rejected(
+            "snapshots aren't supported".to_string(),
+        ))
+    }
+    /// A node has been removed from the voters of the cluster, called on
+    /// the removed node too.
+    async fn remove_connection(&self, _node: &str) {}
+    /// The leader failed to reach the `node` several times in a row, or a
+    /// follower stopped receiving the heartbeats of the leader `node`.
+    async fn lost_connection(&self, _node: &str) {}
+    /// The node follows a new `leader`, or took the lead itself.
+    async fn leader_change(&self, _leader: &str) {}
+    /// Called on the leader when a new `node` asks to join the cluster. An
+    /// error refuses the connection.
+    async fn pre_add_connection(&self, _node: &str) -> HookResult<()> {
+        Ok(())
+    }
+    /// A node has been added to the members of the cluster, called on the
+    /// added node too.
+    async fn post_add_connection(&self, _node: &str) {}
+    /// The node answered the vote request of the `candidate`. The
+    /// observers (`request_vote`, `receive_vote` and `send_term`) are
+    /// called in a task of their own, the node doesn't wait for them.
+    async fn request_vote(&self, _candidate: &str, _granted: bool) {}
+    /// The candidate received the answer of `node` to its vote request.
+    async fn receive_vote(&self, _node: &str, _granted: bool) {}
+    /// The leader sent the terms up to `last_index` to `node`, `success` is
+    /// false if the node rejected them.
+    async fn send_term(&self, _node: &str, _last_index: usize, _success: bool) {}
+    /// Called once when the node starts, the hook keeps the `context` to
+    /// know the state of the node.
+    fn set_context(&self, _context: NodeContext) {}
+}
+
+/// Adapter of a synchronous [Hook] to an [AsyncHook]. Each call runs on the
+/// blocking threads of tokio with `spawn_blocking`, the heartbeats go on
+/// while a slow hook works. A panic of the hook is propagated to the
+/// caller.
+pub struct BlockingHook<H>(Arc<H>);
+
+impl<H: Hook + 'static> BlockingHook<H> {
+    pub fn new(hook: H) -> Self {
+        Self(Arc::new(hook))
+    }
+
+    async fn call<R, F>(&self, call: F) -> R
+    where
+        R: Send + 'static,
+        F: FnOnce(&H) -> R + Send + 'static,
+    {
+        let hook = self.0.clone();
+        tokio::task::spawn_blocking(move || call(&hook))
+            .await
+            .unwrap_or_else(|err| std::panic::resume_unwind(err.into_panic()))
+    }
+}
+
+#[async_trait]
+impl<H: Hook + 'static> AsyncHook for BlockingHook<H> {
+    async fn update_node(&self) -> HookResult<()> {
+        self.call(|hook| hook.update_node()).await
+    }
+
+    async fn pre_append_term(&self, term: &Term) -> HookResult<usize> {
+        let term = term.clone();
+        self.call(move |hook| hook.pre_append_term(&term)).await
+    }
+
+    async fn append_term(&self, term: &Term) -> HookResult<()> {
+        let term = term.clone();
+        self.call(move |hook| hook.append_term(&term)).await
+    }
+
+    async fn commit_term(&self, term: &Term) -> HookResult<()> {
+        let term = term.clone();
+        self.call(move |hook| hook.commit_term(&term)).await
+    }
+
+    async fn prepare_term(&self) -> HookResult<Vec<u8>> {
+        self.call(|hook| hook.prepare_term()).await
+    }
+
+    async fn retreive_term(&self, index: usize) -> HookResult<Term> {
+        self.call(move |hook| hook.retreive_term(index)).await
+    }
+
+    async fn retreive_terms(&self, from: usize, to: usize) -> HookResult<Vec<Term>> {
+        self.call(move |hook| hook.retreive_terms(from, to)).await
+    }
+
+    async fn switch_status(&self, status: EStatus) {
+        self.call(move |hook| hook.switch_status(status)).await
+    }
+
+    async fn snapshot(&self, index: usize) -> HookResult<Option<Vec<u8>>> {
+        self.call(move |hook| hook.snapshot(index)).await
+    }
+
+    async fn restore_snapshot(&self, index: usize, data: &[u8]) -> HookResult<()> {
+        let data = data.to_vec();
+        self.call(move |hook| hook.restore_snapshot(index, &data))
+            .await
+    }
+
+    async fn remove_connection(&self, node: &str) {
+        let node = node.to_string();
+        self.call(move |hook| hook.remove_connection(&node)).await
+    }
+
+    async fn lost_connection(&self, node: &str) {
+        let node = node.to_string();
+        self.call(move |hook| hook.lost_connection(&node)).await
+    }
+
+    async fn leader_change(&self, leader: &str) {
+        let leader = leader.to_string();
+        self.call(move |hook| hook.leader_change(&leader)).await
+    }
+
+    async fn pre_add_connection(&self, node: &str) -> HookResult<()> {
+        let node = node.to_string();
+        self.call(move |hook| hook.pre_add_connection(&node)).await
+    }
+
+    async fn post_add_connection(&self, node: &str) {
+        let node = node.to_string();
+        self.call(move |hook| hook.post_add_connection(&node)).await
+    }
+
+    async fn request_vote(&self, candidate: &str, granted: bool) {
+        let candidate = candidate.to_string();
+        self.call(move |hook| hook.request_vote(&candidate, granted))
+            .await
+    }
+
+    async fn receive_vote(&self, node: &str, granted: bool) {
+        let node = node.to_string();
+        self.call(move |hook| hook.receive_vote(&node, granted))
+            .await
+    }
+
+    async fn send_term(&self, node: &str, last_index: usize, success: bool) {
+        let node = node.to_string();
+        self.call(move |hook| hook.send_term(&node, last_index, success))
+            .await
+    }
+
+    fn set_context(&self, context: NodeContext) {
+        self.0.set_context(context)
+    }
 }
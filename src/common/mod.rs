@@ -1,7 +1,11 @@
+pub(crate) mod base64_bytes;
 pub mod config;
+pub mod coprocess;
 pub mod error;
 pub mod hook_trait;
 pub mod scripts;
+pub mod state_machine;
+pub mod webhook;
 
 mod url;
 pub use url::*;
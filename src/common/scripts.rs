@@ -2,229 +2,502 @@
 //!       (should be designed in another repository, using hook
 //!       as a library)
 
-use super::hook_trait::Hook;
-use crate::{log_entry::Term, state::EStatus};
+use super::{
+    error::{HookError, HookResult},
+    hook_trait::{Hook, NodeContext},
+};
+use crate::{
+    log_entry::{EntryKind, Term},
+    state::EStatus,
+};
 use chrono::{SecondsFormat, Utc};
 use serde::Deserialize;
-use std::{env, fs, process::Command};
+use std::{
+    collections::HashMap,
+    env,
+    fs::{self, OpenOptions},
+    io::{self, Read, Write},
+    path::PathBuf,
+    process::{self, Command, ExitStatus, Stdio},
+    sync::OnceLock,
+    thread::{self, JoinHandle},
+    time::{Duration, Instant},
+};
 use tracing::{debug, warn};
 
-pub struct DefaultHook;
-impl Hook for DefaultHook {
-    fn update_node(&self) -> bool {
-        update_node()
+#[cfg(unix)]
+use std::os::unix::fs::OpenOptionsExt;
+
+/// Hook running a script of the hooks directory at each call. See
+/// [super::coprocess::CoprocessHook] for a helper process that stays alive.
+///
+/// A script succeeds when it exits with 0, it rejects the call when it exits
+/// with 1 and fails otherwise. A script that runs for longer than its
+/// timeout is killed and fails.
+pub struct DefaultHook {
+    /// Directory of the scripts, the current directory if none
+    dir: Option<PathBuf>,
+    /// Maximum duration of a script
+    timeout: Duration,
+    /// Timeouts of some scripts, by name
+    timeouts: HashMap<String, Duration>,
+    /// State of the node, set when the node starts
+    context: OnceLock<NodeContext>,
+}
+
+impl Default for DefaultHook {
+    fn default() -> Self {
+        Self {
+            dir: None,
+            timeout: Duration::from_secs(1),
+            timeouts: HashMap::new(),
+            context: OnceLock::new(),
+        }
     }
+}
 
-    fn pre_append_term(&self, _term: &Term) -> Option<usize> {
-        pre_append_term(_term)
+impl DefaultHook {
+    /// Hook running the scripts of the current directory, each script
+    /// times out after one second.
+    pub fn new() -> Self {
+        Self::default()
     }
 
-    fn append_term(&self, _term: &Term) -> bool {
-        append_term(_term)
+    /// Look for the scripts in `dir`
+    pub fn with_dir(self, dir: impl Into<PathBuf>) -> Self {
+        Self {
+            dir: Some(dir.into()),
+            ..self
+        }
     }
 
-    fn commit_term(&self, _term: &Term) -> bool {
-        commit_term(_term)
+    /// Maximum duration of the scripts
+    pub fn with_timeout(self, timeout: Duration) -> Self {
+        Self { timeout, ..self }
     }
 
-    fn prepare_term(&self) -> String {
-        prepare_term()
+    /// Maximum duration of the script `name`, like `snapshot`
+    pub fn with_script_timeout(mut self, name: &str, timeout: Duration) -> Self {
+        self.timeouts.insert(name.to_string(), timeout);
+        self
     }
 
-    fn retreive_term(&self, index: usize) -> Option<Term> {
-        retreive_term(index)
+    fn get_script_path(&self, prefix: &str) -> Option<PathBuf> {
+        let dir = match &self.dir {
+            Some(dir) => dir.clone(),
+            None => env::current_dir().ok()?,
+        };
+        let entries = match fs::read_dir(&dir) {
+            Ok(entries) => entries,
+            Err(err) => {
+                warn!("unable to read the hooks directory {:?}, {err}", dir);
+                return None;
+            }
+        };
+        for entry in entries.flatten() {
+            let name = entry.file_name();
+            let name = name.to_string_lossy();
+            if name.starts_with(prefix) && !name.ends_with(".sample") {
+                return Some(entry.path());
+            }
+        }
+        debug!("no {prefix} found script");
+        None
     }
 
-    fn retreive_terms(&self, from: usize, to: usize) -> Option<Vec<Term>> {
-        retreive_terms(from, to)
+    /// Variables describing the state of the node, given to the scripts
+    fn context_env(&self) -> Vec<(&'static str, String)> {
+        let context = match self.context.get() {
+            Some(context) => context,
+            None => return vec![],
+        };
+        vec![
+            ("HOOK_NODE_ID", context.node_id().to_string()),
+            ("HOOK_STATUS", status_name(context.status()).to_string()),
+            ("HOOK_LEADER", context.leader().unwrap_or_default()),
+            ("HOOK_COMMIT_INDEX", context.commit_index().to_string()),
+            ("HOOK_LAST_INDEX", context.last_index().to_string()),
+        ]
     }
 
-    fn switch_status(&self, status: EStatus) {
-        switch_status(status)
+    /// Run the script `name` with the `args` if there is one, the `input`
+    /// is written on its standard input. Return its standard output.
+    fn exec(&self, name: &str, args: Vec<String>, input: &[u8]) -> Option<HookResult<String>> {
+        let output = self.exec_raw(name, args, input)?;
+        Some(output.and_then(|output| {
+            String::from_utf8(output)
+                .map_err(|err| HookError::Failed(format!("{name} output isn't utf8, {err}")))
+        }))
+    }
+
+    /// Same as `exec`, the output isn't text
+    fn exec_raw(&self, name: &str, args: Vec<String>, input: &[u8]) -> Option<HookResult<Vec<u8>>> {
+        let script = self.get_script_path(name)?;
+        debug!(
+            "exec script {:?} with args: {:?}, {} bytes of input",
+            script,
+            args,
+            input.len()
+        );
+        let timeout = self.timeouts.get(name).copied().unwrap_or(self.timeout);
+        Some(self.exec_cmd(name, Command::new(&script).args(args), input, timeout))
     }
-}
 
-fn get_script_path(prefix: &'static str) -> Option<String> {
-    let current_dir = match env::current_dir() {
-        Ok(dir) => dir,
-        _ => {
-            debug!("no {prefix} found script");
-            return None;
+    fn exec_cmd(
+        &self,
+        name: &str,
+        cmd: &mut Command,
+        input: &[u8],
+        timeout: Duration,
+    ) -> HookResult<Vec<u8>> {
+        let mut child = cmd
+            .envs(self.context_env())
+            .stdin(Stdio::piped())
+            .stdout(Stdio::piped())
+            .stderr(Stdio::piped())
+            .spawn()
+            .map_err(|err| HookError::Failed(format!("unable to run {name}, {err}")))?;
+        // A script that doesn't read its input makes the writer fail, the
+        // writer isn't waited
+        if let Some(mut stdin) = child.stdin.take() {
+            let input = input.to_vec();
+            thread::spawn(move || stdin.write_all(&input));
         }
-    };
-    for entry in fs::read_dir(current_dir).unwrap().flatten() {
-        let name = entry.file_name();
-        let name = name.to_str().unwrap();
-        if name.starts_with(prefix) && !name.ends_with(&".sample") {
-            return Some(entry.path().to_str().unwrap().to_string());
+        let stdout = read_pipe(child.stdout.take());
+        let stderr = read_pipe(child.stderr.take());
+        let deadline = Instant::now() + timeout;
+        let status = loop {
+            match child.try_wait() {
+                Ok(Some(status)) => break status,
+                Ok(None) if Instant::now() >= deadline => {
+                    let _ = child.kill();
+                    let _ = child.wait();
+                    // The pipes may be kept open by the children of the
+                    // script, the readers are left behind
+                    return Err(HookError::Failed(format!(
+                        "{name} killed after {timeout:?}"
+                    )));
+                }
+                Ok(None) => thread::sleep(Duration::from_millis(2)),
+                Err(err) => return Err(HookError::Failed(format!("{name}: {err}"))),
+            }
+        };
+        let stdout = stdout.join().unwrap_or_default();
+        let stderr = stderr.join().unwrap_or_default();
+        for line in String::from_utf8_lossy(&stderr).lines() {
+            if status.success() {
+                debug!("{name}: {line}");
+            } else {
+                warn!("{name}: {line}");
+            }
         }
+        check_status(name, status)?;
+        Ok(stdout)
     }
-    debug!("no {prefix} found script");
-    None
-}
 
-fn exec_cmd(script: String, input: Option<Vec<String>>) -> Option<String> {
-    let mut cmd = Command::new(script.clone());
-    debug!("exec script {} with args: {:?}", script, input);
-    if let Some(args) = input {
-        cmd.args(args);
+    /// Run the script of an event if there is one, its output is ignored
+    fn notify(&self, name: &str, args: Vec<String>) {
+        if let Some(Err(err)) = self.exec(name, args, &[]) {
+            warn!("{name}: {err}");
+        }
     }
-    let output = cmd.output().expect("failed reading output");
-    debug!("{}: {:#?}", script, output);
-    Some(String::from_utf8(output.stdout).unwrap().to_lowercase())
 }
 
-fn update_node() -> bool {
-    match get_script_path("update_node") {
-        Some(script) => {
-            if let Some(res) = exec_cmd(script, None) {
-                res == "true"
-            } else {
-                false
+/// File exchanged with a script, created with a unique name that no other
+/// process owns. The file is removed when dropped.
+struct TempFile {
+    path: PathBuf,
+}
+
+impl TempFile {
+    /// Create an empty file in the temporary directory, only readable by
+    /// the current user
+    fn create(prefix: &str) -> HookResult<Self> {
+        loop {
+            let path = env::temp_dir().join(format!(
+                "{prefix}_{}_{:016x}",
+                process::id(),
+                rand::random::<u64>()
+            ));
+            let mut options = OpenOptions::new();
+            options.write(true).create_new(true);
+            #[cfg(unix)]
+            options.mode(0o600);
+            match options.open(&path) {
+                Ok(_) => return Ok(Self { path }),
+                Err(err) if err.kind() == io::ErrorKind::AlreadyExists => continue,
+                Err(err) => {
+                    return Err(HookError::Failed(format!(
+                        "unable to create a temporary file, {err}"
+                    )))
+                }
             }
         }
-        None => true,
+    }
+
+    fn arg(&self) -> String {
+        self.path.to_string_lossy().to_string()
     }
 }
 
-fn pre_append_term(term: &Term) -> Option<usize> {
-    let script = if let Some(script) = get_script_path("pre_append_term") {
-        script
-    } else {
-        return Some(term.id);
-    };
-    let res = exec_cmd(
-        script,
-        Some(vec![format!("{}", term.id), term.content.clone()]),
-    )?;
-    if res.is_empty() {
-        None
-    } else {
-        Some(res.parse().expect("Failed to parse pre_append_term output"))
+impl Drop for TempFile {
+    fn drop(&mut self) {
+        let _ = fs::remove_file(&self.path);
     }
 }
 
-fn append_term(term: &Term) -> bool {
-    match get_script_path("append_term") {
-        Some(script) => {
-            if let Some(res) = exec_cmd(
-                script,
-                Some(vec![format!("{}", term.id), term.content.clone()]),
-            ) {
-                res == "true"
-            } else {
-                false
-            }
+/// Read the whole `pipe` in a thread
+fn read_pipe(pipe: Option<impl Read + Send + 'static>) -> JoinHandle<Vec<u8>> {
+    thread::spawn(move || {
+        let mut output = vec![];
+        if let Some(mut pipe) = pipe {
+            let _ = pipe.read_to_end(&mut output);
         }
-        None => true,
+        output
+    })
+}
+
+/// The exit code 1 rejects the call, the other failures fail it
+fn check_status(name: &str, status: ExitStatus) -> HookResult<()> {
+    match status.code() {
+        Some(0) => Ok(()),
+        Some(1) => Err(HookError::Rejected(format!("{name} exited with 1"))),
+        _ => Err(HookError::Failed(format!("{name} exited with {status}"))),
     }
 }
 
-fn commit_term(term: &Term) -> bool {
-    match get_script_path("commit_term") {
-        Some(script) => {
-            if let Some(res) = exec_cmd(
-                script,
-                Some(vec![format!("{}", term.id), term.content.clone()]),
-            ) {
-                res == "true"
-            } else {
-                false
-            }
-        }
-        None => true,
+/// Scripts that print "false" reject the call
+fn accepted(name: &str, output: String) -> HookResult<()> {
+    if output.trim().eq_ignore_ascii_case("false") {
+        Err(HookError::Rejected(format!("{name} answered false")))
+    } else {
+        Ok(())
     }
 }
 
-fn prepare_term() -> String {
-    if let Some(script) = get_script_path("prepare_term") {
-        if let Some(output) = exec_cmd(script, None) {
-            return output;
-        }
+/// Name of the status given to the hooks
+pub(super) fn status_name(status: EStatus) -> &'static str {
+    match status {
+        EStatus::ConnectionPending => "pending",
+        EStatus::Follower => "follower",
+        EStatus::Leader => "leader",
+        EStatus::Candidate => "candidate",
     }
-    "default".into()
 }
 
-fn retreive_term(index: usize) -> Option<Term> {
-    debug!("call retrieve term script");
-    let timestamp = Utc::now().to_rfc3339_opts(SecondsFormat::Nanos, false);
-    let script = if let Some(script) = get_script_path("retrieve_term") {
-        script
-    } else {
-        return Some(Term {
-            id: index,
-            timestamp,
-            content: "default".into(),
-        });
-    };
-    let content = exec_cmd(script, Some(vec![format!("{index}")]))?;
-    debug!("content retrieved {} {}", index, content);
-    Some(Term {
-        id: index,
-        timestamp,
-        content,
-    })
+/// Name of the kind of an entry given to the scripts
+fn kind_name(kind: &EntryKind) -> &'static str {
+    match kind {
+        EntryKind::Normal => "normal",
+        EntryKind::Noop => "noop",
+        EntryKind::Configuration(_) => "configuration",
+    }
 }
 
-fn retreive_terms(from: usize, to: usize) -> Option<Vec<Term>> {
-    debug!("call retrieve termS script");
-    let timestamp = Utc::now().to_rfc3339_opts(SecondsFormat::Nanos, false);
-    let script = get_script_path("retrieve_n_term");
-    if script.is_none() {
-        return Some(
-            (from..=to)
-                .map(|id| Term {
-                    id,
-                    timestamp: timestamp.clone(),
-                    content: "default".into(),
-                })
-                .collect(),
-        );
+/// Arguments of the scripts that receive a term, the content is given on
+/// the standard input
+fn term_args(term: &Term) -> Vec<String> {
+    vec![format!("{}", term.index), kind_name(&term.kind).to_string()]
+}
+
+impl Hook for DefaultHook {
+    fn update_node(&self) -> HookResult<()> {
+        match self.exec("update_node", vec![], &[]) {
+            Some(output) => accepted("update_node", output?),
+            None => Ok(()),
+        }
+    }
+
+    fn pre_append_term(&self, term: &Term) -> HookResult<usize> {
+        let res = match self.exec("pre_append_term", term_args(term), &term.content) {
+            Some(output) => output?,
+            None => return Ok(term.index),
+        };
+        if res.trim().is_empty() {
+            Err(HookError::Rejected(
+                "pre_append_term answered nothing".into(),
+            ))
+        } else {
+            res.trim().parse().map_err(|_| {
+                HookError::Failed(format!("failed to parse pre_append_term output {res:?}"))
+            })
+        }
+    }
+
+    fn append_term(&self, term: &Term) -> HookResult<()> {
+        match self.exec("append_term", term_args(term), &term.content) {
+            Some(output) => accepted("append_term", output?),
+            None => Ok(()),
+        }
+    }
+
+    fn commit_term(&self, term: &Term) -> HookResult<()> {
+        match self.exec("commit_term", term_args(term), &term.content) {
+            Some(output) => accepted("commit_term", output?),
+            None => Ok(()),
+        }
     }
 
-    let output = exec_cmd(
-        script.unwrap(),
-        Some(vec![format!("{from}"), format!("{to}")]),
-    )?;
+    fn prepare_term(&self) -> HookResult<Vec<u8>> {
+        match self.exec_raw("prepare_term", vec![], &[]) {
+            Some(output) => output,
+            None => Err(HookError::Rejected("no prepare_term script".into())),
+        }
+    }
 
-    #[derive(Deserialize)]
-    struct TermWithoutTimestamp {
-        id: usize,
-        content: String,
+    fn retreive_term(&self, index: usize) -> HookResult<Term> {
+        debug!("call retrieve term script");
+        let timestamp = Utc::now().to_rfc3339_opts(SecondsFormat::Nanos, false);
+        let output = match self.exec_raw("retrieve_term", vec![format!("{index}")], &[]) {
+            Some(output) => output?,
+            None => return Err(HookError::Failed("no retrieve_term script".into())),
+        };
+        // The first line is the election term of the entry, the content
+        // follows as is
+        let (term, content) = match output.iter().position(|&byte| byte == b'\n') {
+            Some(end) => (&output[..end], output[end + 1..].to_vec()),
+            None => (&output[..], vec![]),
+        };
+        let term = String::from_utf8_lossy(term).trim().parse().map_err(|_| {
+            HookError::Failed(format!(
+                "retrieve_term didn't print the election term of {index}"
+            ))
+        })?;
+        debug!("content retrieved {} {} bytes", index, content.len());
+        Ok(Term {
+            index,
+            term,
+            timestamp,
+            content,
+            kind: EntryKind::Normal,
+        })
     }
 
-    match serde_json::from_str::<Vec<TermWithoutTimestamp>>(&output) {
-        Ok(terms) => {
-            debug!("parse retrieve termS succeed");
-            Some(
-                terms
+    fn retreive_terms(&self, from: usize, to: usize) -> HookResult<Vec<Term>> {
+        debug!("call retrieve termS script");
+        let timestamp = Utc::now().to_rfc3339_opts(SecondsFormat::Nanos, false);
+        let output = match self.exec(
+            "retrieve_n_term",
+            vec![format!("{from}"), format!("{to}")],
+            &[],
+        ) {
+            Some(output) => output?,
+            None => return Err(HookError::Failed("no retrieve_n_term script".into())),
+        };
+
+        #[derive(Deserialize)]
+        struct TermWithoutTimestamp {
+            id: usize,
+            /// Election term of the entry
+            term: usize,
+            /// Base64 content of the entry
+            #[serde(deserialize_with = "crate::common::base64_bytes::deserialize")]
+            content: Vec<u8>,
+        }
+
+        match serde_json::from_str::<Vec<TermWithoutTimestamp>>(&output) {
+            Ok(terms) => {
+                debug!("parse retrieve termS succeed");
+                Ok(terms
                     .into_iter()
                     .map(|term| Term {
-                        id: term.id,
+                        index: term.id,
+                        term: term.term,
                         timestamp: timestamp.clone(),
                         content: term.content,
+                        kind: EntryKind::Normal,
                     })
-                    .collect(),
-            )
+                    .collect())
+            }
+            Err(err) => {
+                warn!("{:?} failed to parse retrieve termS output {}", err, output);
+                Err(HookError::Failed(format!(
+                    "failed to parse retrieve_n_term output, {err}"
+                )))
+            }
         }
-        Err(err) => {
-            warn!("{:?} failed to parse retrieve termS output {}", err, output);
-            None
+    }
+
+    fn switch_status(&self, status: EStatus) {
+        self.notify("switch_status", vec![status_name(status).to_string()])
+    }
+
+    fn snapshot(&self, index: usize) -> HookResult<Option<Vec<u8>>> {
+        if self.get_script_path("snapshot").is_none() {
+            return Ok(None);
         }
+        let file = TempFile::create("hook_snapshot")?;
+        let args = vec![format!("{index}"), file.arg()];
+        match self.exec("snapshot", args, &[]) {
+            Some(output) => output?,
+            None => return Ok(None),
+        };
+        fs::read(&file.path)
+            .map(Some)
+            .map_err(|err| HookError::Failed(format!("unable to read the snapshot, {err}")))
     }
-}
 
-fn switch_status(status: EStatus) {
-    if let Some(script) = get_script_path("switch_status") {
-        exec_cmd(
-            script,
-            Some(vec![match status {
-                EStatus::ConnectionPending => "pending",
-                EStatus::Follower => "follower",
-                EStatus::Leader => "leader",
-                EStatus::Candidate => "candidate",
-            }
-            .to_string()]),
-        );
+    fn restore_snapshot(&self, index: usize, data: &[u8]) -> HookResult<()> {
+        if self.get_script_path("restore_snapshot").is_none() {
+            return Err(HookError::Rejected("no restore_snapshot script".into()));
+        }
+        let file = TempFile::create("hook_restore_snapshot")?;
+        fs::write(&file.path, data).map_err(|err| {
+            HookError::Failed(format!("unable to write the snapshot to restore, {err}"))
+        })?;
+        let args = vec![format!("{index}"), file.arg()];
+        match self.exec("restore_snapshot", args, &[]) {
+            Some(output) => output.map(|_| ()),
+            None => Err(HookError::Failed("restore_snapshot script removed".into())),
+        }
+    }
+
+    fn remove_connection(&self, node: &str) {
+        self.notify("remove_connection", vec![node.to_string()])
+    }
+
+    fn lost_connection(&self, node: &str) {
+        self.notify("lost_connection", vec![node.to_string()])
+    }
+
+    fn leader_change(&self, leader: &str) {
+        self.notify("leader_change", vec![leader.to_string()])
+    }
+
+    fn pre_add_connection(&self, node: &str) -> HookResult<()> {
+        match self.exec("pre_add_connection", vec![node.to_string()], &[]) {
+            Some(output) => accepted("pre_add_connection", output?),
+            None => Ok(()),
+        }
+    }
+
+    fn post_add_connection(&self, node: &str) {
+        self.notify("post_add_connection", vec![node.to_string()])
+    }
+
+    fn request_vote(&self, candidate: &str, granted: bool) {
+        self.notify(
+            "request_vote",
+            vec![candidate.to_string(), granted.to_string()],
+        )
+    }
+
+    fn receive_vote(&self, node: &str, granted: bool) {
+        self.notify("receive_vote", vec![node.to_string(), granted.to_string()])
+    }
+
+    fn send_term(&self, node: &str, last_index: usize, success: bool) {
+        self.notify(
+            "send_term",
+            vec![
+                node.to_string(),
+                last_index.to_string(),
+                success.to_string(),
+            ],
+        )
+    }
+
+    fn set_context(&self, context: NodeContext) {
+        let _ = self.context.set(context);
     }
 }
@@ -0,0 +1,203 @@
+// License:
+// This source code is licensed under the GPLv3 license, you can found the
+// LICENSE file in the root directory of this source tree.
+
+//! State machine mode of the hook. Instead of handling every call of the
+//! [Hook], the application implements a [StateMachine] that applies the
+//! committed commands. The [StateMachineHook] adapter applies each
+//! committed entry exactly once and in order, whatever the number of times
+//! the node commits it.
+
+use super::{
+    error::{Error, HookError, HookResult, ProposeError},
+    hook_trait::Hook,
+};
+use crate::{log_entry::Term, node::Node, state::EStatus};
+use std::{
+    collections::BTreeMap,
+    sync::{Arc, Mutex},
+};
+use tracing::trace;
+
+/// Maximum number of outputs waiting for their proposer, the oldest output
+/// is dropped first.
+const MAX_OUTPUTS: usize = 1024;
+
+/// Deterministic state machine replicated by the cluster.
+pub trait StateMachine: Send + 'static {
+    /// Result of a command, returned to the proposer
+    type Output: Send + 'static;
+
+    /// Apply the committed `command` at `index`. The commands are applied in
+    /// the order of the logs, each one once.
+    fn apply(&mut self, index: usize, command: &[u8]) -> Self::Output;
+
+    /// Serialize the state, the commands applied so far included
+    fn snapshot(&self) -> HookResult<Vec<u8>>;
+
+    /// Replace the state with a snapshot taken by another node
+    fn restore(&mut self, data: &[u8]) -> HookResult<()>;
+}
+
+struct Applied<M: StateMachine> {
+    machine: M,
+    /// Index of the latest applied entry
+    index: usize,
+    /// Outputs of the latest commands, by index
+    outputs: BTreeMap<usize, M::Output>,
+}
+
+/// Hook applying the committed entries to a [StateMachine]
+pub struct StateMachineHook<M: StateMachine> {
+    applied: Arc<Mutex<Applied<M>>>,
+}
+
+/// Access to the state machine of a [StateMachineHook] once the hook is
+/// given to the node.
+pub struct StateMachineHandle<M: StateMachine> {
+    applied: Arc<Mutex<Applied<M>>>,
+}
+
+impl<M: StateMachine> Clone for StateMachineHandle<M> {
+    fn clone(&self) -> Self {
+        Self {
+            applied: self.applied.clone(),
+        }
+    }
+}
+
+impl<M: StateMachine> StateMachineHook<M> {
+    /// Adapter of an empty `machine`, no command has been applied
+    pub fn new(machine: M) -> Self {
+        Self {
+            applied: Arc::new(Mutex::new(Applied {
+                machine,
+                index: 0,
+                outputs: BTreeMap::new(),
+            })),
+        }
+    }
+
+    /// Handle to propose the commands and read the state machine
+    pub fn handle(&self) -> StateMachineHandle<M> {
+        StateMachineHandle {
+            applied: self.applied.clone(),
+        }
+    }
+}
+
+impl<M: StateMachine> StateMachineHandle<M> {
+    /// Propose a `command` to the cluster through the leader `node`. Resolve
+    /// with the output of the command once it's applied.
+    ///
+    /// # Error
+    /// See `Node::propose`
+    pub async fn propose(
+        &self,
+        node: &Node,
+        command: impl Into<Vec<u8>>,
+    ) -> Result<M::Output, ProposeError> {
+        let index = node.propose(command).await?;
+        self.take_output(index).ok_or_else(|| {
+            ProposeError::Failed(Box::new(Error::HookFailure(HookError::Failed(format!(
+                "output of the entry {index} dropped"
+            )))))
+        })
+    }
+
+    /// Take the output of the command applied at `index`. Only the latest
+    /// outputs are kept.
+    pub fn take_output(&self, index: usize) -> Option<M::Output> {
+        self.applied.lock().unwrap().outputs.remove(&index)
+    }
+
+    /// Index of the latest applied entry
+    pub fn applied_index(&self) -> usize {
+        self.applied.lock().unwrap().index
+    }
+
+    /// Read the state machine
+    pub fn read<R>(&self, read: impl FnOnce(&M) -> R) -> R {
+        read(&self.applied.lock().unwrap().machine)
+    }
+}
+
+impl<M: StateMachine> Hook for StateMachineHook<M> {
+    fn update_node(&self) -> HookResult<()> {
+        Ok(())
+    }
+
+    /// The entries are kept by the node, every entry is accepted
+    fn pre_append_term(&self, term: &Term) -> HookResult<usize> {
+        Ok(term.index)
+    }
+
+    /// Nothing is applied before the commit
+    fn append_term(&self, _term: &Term) -> HookResult<()> {
+        Ok(())
+    }
+
+    fn commit_term(&self, term: &Term) -> HookResult<()> {
+        let mut applied = self.applied.lock().unwrap();
+        if term.index <= applied.index {
+            trace!("term {} already applied", term.index);
+            return Ok(());
+        }
+        if term.index != applied.index + 1 {
+            // The state machine missed some commands, applying the next ones
+            // would diverge from the other nodes
+            return Err(HookError::Fatal(format!(
+                "term {} committed after {}",
+                term.index, applied.index
+            )));
+        }
+        applied.index = term.index;
+        if term.is_internal() {
+            return Ok(());
+        }
+        let output = applied.machine.apply(term.index, &term.content);
+        applied.outputs.insert(term.index, output);
+        if applied.outputs.len() > MAX_OUTPUTS {
+            applied.outputs.pop_first();
+        }
+        Ok(())
+    }
+
+    /// The commands are proposed with `StateMachineHandle::propose`
+    fn prepare_term(&self) -> HookResult<Vec<u8>> {
+        Err(HookError::Rejected("nothing to prepare".into()))
+    }
+
+    fn retreive_term(&self, index: usize) -> HookResult<Term> {
+        Err(HookError::Failed(format!(
+            "term {index} compacted, send the snapshot"
+        )))
+    }
+
+    fn retreive_terms(&self, from: usize, to: usize) -> HookResult<Vec<Term>> {
+        Err(HookError::Failed(format!(
+            "terms {from} to {to} compacted, send the snapshot"
+        )))
+    }
+
+    fn switch_status(&self, _status: EStatus) {}
+
+    fn snapshot(&self, index: usize) -> HookResult<Option<Vec<u8>>> {
+        let applied = self.applied.lock().unwrap();
+        if applied.index != index {
+            return Err(HookError::Failed(format!(
+                "snapshot at {index} but {} applied",
+                applied.index
+            )));
+        }
+        applied.machine.snapshot().map(Some)
+    }
+
+    fn restore_snapshot(&self, index: usize, data: &[u8]) -> HookResult<()> {
+        let mut applied = self.applied.lock().unwrap();
+        applied.machine.restore(data)?;
+        applied.index = index;
+        applied.outputs.clear();
+        Ok(())
+    }
+}
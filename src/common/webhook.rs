@@ -0,0 +1,237 @@
+// License:
+// This source code is licensed under the GPLv3 license, you can found the
+// LICENSE file in the root directory of this source tree.
+
+//! Webhook mode of the hook. The node posts each call as JSON to an HTTP
+//! service, the service answers with the result of the call.
+//!
+//! ```text
+//! > POST /hook {"method":"commit_term","params":{"term":{"index":3,...}}}
+//! < 200 {"result":null}
+//! > POST /hook {"method":"prepare_term","params":{}}
+//! < 200 {"error":{"kind":"rejected","message":"nothing to send"}}
+//! ```
+//!
+//! The errors and the base64 encoding of the bytes are the ones of the
+//! co-process hook, see [super::coprocess]. A request that fails, doesn't
+//! get a response in time or gets a status other than 2xx is sent again, so
+//! the service may receive a call several times.
+
+use super::{
+    base64_bytes::Base64,
+    coprocess::ResponseError,
+    error::{HookError, HookResult},
+    hook_trait::AsyncHook,
+    scripts::status_name,
+};
+use crate::{log_entry::Term, state::EStatus};
+use async_trait::async_trait;
+use hyper::{client::HttpConnector, header, Body, Client, Method, Request};
+use serde::{de::DeserializeOwned, Deserialize, Serialize};
+use serde_json::{json, Value};
+use std::time::Duration;
+use tracing::{debug, warn};
+
+/// Hook that posts the calls to an HTTP service, see the module
+/// documentation for the protocol.
+pub struct WebhookHook {
+    url: String,
+    /// Maximum time of an attempt, request and response included
+    timeout: Duration,
+    /// Attempts after the first one
+    retries: usize,
+    /// Pause between two attempts
+    retry_delay: Duration,
+    client: Client<HttpConnector>,
+}
+
+#[derive(Serialize)]
+struct WebhookRequest<'a> {
+    method: &'a str,
+    params: Value,
+}
+
+#[derive(Deserialize)]
+struct WebhookResponse {
+    #[serde(default)]
+    result: Value,
+    #[serde(default)]
+    error: Option<ResponseError>,
+}
+
+impl WebhookHook {
+    /// Hook posting the calls to `url`, like `http://127.0.0.1:9000/hook`.
+    /// An attempt times out after one second and a call is tried 3 times
+    /// by default.
+    pub fn new(url: impl Into<String>) -> Self {
+        Self {
+            url: url.into(),
+            timeout: Duration::from_secs(1),
+            retries: 2,
+            retry_delay: Duration::from_millis(100),
+            client: Client::new(),
+        }
+    }
+
+    /// Maximum duration of an attempt
+    pub fn with_timeout(self, timeout: Duration) -> Self {
+        Self { timeout, ..self }
+    }
+
+    /// Number of attempts after a failed one, and the pause between them
+    pub fn with_retries(self, retries: usize, retry_delay: Duration) -> Self {
+        Self {
+            retries,
+            retry_delay,
+            ..self
+        }
+    }
+
+    /// Post the call until the service answers, and parse the result
+    async fn call<R: DeserializeOwned>(&self, method: &str, params: Value) -> HookResult<R> {
+        let body = serde_json::to_string(&WebhookRequest { method, params })
+            .map_err(|err| HookError::Failed(err.to_string()))?;
+        let mut attempt = 0;
+        let response = loop {
+            match tokio::time::timeout(self.timeout, self.post(body.clone())).await {
+                Ok(Ok(response)) => break response,
+                Ok(Err(err)) if attempt >= self.retries => return Err(err),
+                Err(_) if attempt >= self.retries => {
+                    return Err(HookError::Failed(format!(
+                        "{method} timed out after {:?}",
+                        self.timeout
+                    )))
+                }
+                Ok(Err(err)) => debug!("retry {method}, {err}"),
+                Err(_) => debug!("retry {method}, timed out"),
+            }
+            attempt += 1;
+            tokio::time::sleep(self.retry_delay).await;
+        };
+        if let Some(error) = response.error {
+            return Err(error.into());
+        }
+        serde_json::from_value(response.result)
+            .map_err(|err| HookError::Failed(format!("unexpected result of {method}, {err}")))
+    }
+
+    /// One attempt of a call
+    async fn post(&self, body: String) -> HookResult<WebhookResponse> {
+        let req = Request::builder()
+            .method(Method::POST)
+            .uri(&self.url)
+            .header(header::CONTENT_TYPE, "application/json")
+            .body(Body::from(body))
+            .map_err(|err| HookError::Failed(format!("invalid webhook request, {err}")))?;
+        let resp = self
+            .client
+            .request(req)
+            .await
+            .map_err(|err| HookError::Failed(format!("webhook: {err}")))?;
+        let status = resp.status();
+        let bytes = hyper::body::to_bytes(resp.into_body())
+            .await
+            .map_err(|err| HookError::Failed(format!("webhook: {err}")))?;
+        if !status.is_success() {
+            return Err(HookError::Failed(format!("webhook answered {status}")));
+        }
+        serde_json::from_slice(&bytes)
+            .map_err(|err| HookError::Failed(format!("invalid webhook response, {err}")))
+    }
+
+    /// Call for an event, a failure is only logged
+    async fn notify(&self, method: &str, params: Value) {
+        if let Err(err) = self.call::<Value>(method, params).await {
+            warn!("{method}: {err}");
+        }
+    }
+}
+
+#[async_trait]
+impl AsyncHook for WebhookHook {
+    async fn update_node(&self) -> HookResult<()> {
+        self.call("update_node", json!({})).await
+    }
+
+    async fn pre_append_term(&self, term: &Term) -> HookResult<usize> {
+        self.call("pre_append_term", json!({ "term": term })).await
+    }
+
+    async fn append_term(&self, term: &Term) -> HookResult<()> {
+        self.call("append_term", json!({ "term": term })).await
+    }
+
+    async fn commit_term(&self, term: &Term) -> HookResult<()> {
+        self.call("commit_term", json!({ "term": term })).await
+    }
+
+    async fn prepare_term(&self) -> HookResult<Vec<u8>> {
+        let content: Base64 = self.call("prepare_term", json!({})).await?;
+        Ok(content.0)
+    }
+
+    async fn retreive_term(&self, index: usize) -> HookResult<Term> {
+        self.call("retreive_term", json!({ "index": index })).await
+    }
+
+    async fn retreive_terms(&self, from: usize, to: usize) -> HookResult<Vec<Term>> {
+        self.call("retreive_terms", json!({ "from": from, "to": to }))
+            .await
+    }
+
+    async fn switch_status(&self, status: EStatus) {
+        self.notify("switch_status", json!({ "status": status_name(status) }))
+            .await
+    }
+
+    async fn snapshot(&self, index: usize) -> HookResult<Option<Vec<u8>>> {
+        let data: Option<Base64> = self.call("snapshot", json!({ "index": index })).await?;
+        Ok(data.map(|data| data.0))
+    }
+
+    async fn restore_snapshot(&self, index: usize, data: &[u8]) -> HookResult<()> {
+        let data = base64::encode(data);
+        self.call("restore_snapshot", json!({ "index": index, "data": data }))
+            .await
+    }
+
+    async fn remove_connection(&self, node: &str) {
+        self.notify("remove_connection", json!({ "node": node }))
+            .await
+    }
+
+    async fn lost_connection(&self, node: &str) {
+        self.notify("lost_connection", json!({ "node": node }))
+            .await
+    }
+
+    async fn leader_change(&self, leader: &str) {
+        self.notify("leader_change", json!({ "leader": leader }))
+            .await
+    }
+
+    async fn pre_add_connection(&self, node: &str) -> HookResult<()> {
+        self.call("pre_add_connection", json!({ "node": node }))
+            .await
+    }
+
+    async fn post_add_connection(&self, node: &str) {
+        self.notify("post_add_connection", json!({ "node": node }))
+            .await
+    }
+
+    async fn request_vote(&self, candidate: &str, granted: bool) {
+        let params = json!({ "candidate": candidate, "granted": granted });
+        self.notify("request_vote", params).await
+    }
+
+    async fn receive_vote(&self, node: &str, granted: bool) {
+        let params = json!({ "node": node, "granted": granted });
+        self.notify("receive_vote", params).await
+    }
+
+    async fn send_term(&self, node: &str, last_index: usize, success: bool) {
+        let params = json!({ "node": node, "last_index": last_index, "success": success });
+        self.notify("send_term", params).await
+    }
+}
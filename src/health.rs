@@ -0,0 +1,81 @@
+// License:
+// This source code is licensed under the GPLv3 license, you can found the
+// LICENSE file in the root directory of this source tree.
+
+//! Failure detector of the leader. The leader tracks the health of each
+//! peer it sends the terms to: the latest successful contact, the number of
+//! consecutive failures and the round trip time.
+//!
+//! After `suspect_after` consecutive failures a peer is suspect, the leader
+//! calls the `lost_connection` hook and contacts it less and less often, up
+//! to once each `max_backoff` milliseconds. If `evict_after` is set, the
+//! leader proposes the removal of a voter suspect for longer than that
+//! grace period. A successful contact clears the suspicion.
+
+use crate::common::config::Settings;
+use std::time::Duration;
+use tokio::time::Instant;
+
+/// Health of a peer seen by the leader, see `Node::peer_health`
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub struct PeerHealth {
+    /// Latest time the peer answered
+    pub last_contact: Option<Instant>,
+    /// Number of failed calls since the latest answer
+    pub failures: usize,
+    /// Smoothed round trip time of the calls, none until the first answer
+    pub rtt: Option<Duration>,
+    /// Time the peer became suspect
+    pub suspect_since: Option<Instant>,
+    /// The leader doesn't contact a suspect peer before that time
+    pub retry_at: Option<Instant>,
+}
+
+impl PeerHealth {
+    pub fn is_suspect(&self) -> bool {
+        self.suspect_since.is_some()
+    }
+
+    /// False if the peer is suspect and the backoff isn't over
+    pub(crate) fn should_contact(&self, now: Instant) -> bool {
+        self.retry_at.is_none_or(|retry_at| now >= retry_at)
+    }
+
+    /// The peer answered after `rtt`. Return true if it was suspect.
+    pub(crate) fn succeed(&mut self, now: Instant, rtt: Duration) -> bool {
+        // Same smoothing as TCP, the latest measure counts for 1/8
+        self.rtt = Some(match self.rtt {
+            Some(srtt) => (srtt * 7 + rtt) / 8,
+            None => rtt,
+        });
+        self.last_contact = Some(now);
+        self.failures = 0;
+        self.retry_at = None;
+        self.suspect_since.take().is_some()
+    }
+
+    /// A call to the peer failed. Return true if the peer became suspect.
+    pub(crate) fn fail(&mut self, now: Instant, settings: &Settings) -> bool {
+        self.failures += 1;
+        if self.failures < settings.suspect_after.max(1) {
+            return false;
+        }
+        // Double the delay between two calls at each new failure
+        let exponent = (self.failures - settings.suspect_after.max(1)).min(16) as u32;
+        let backoff = settings.get_send_term_sleep_duration() * 2u32.pow(exponent);
+        self.retry_at = Some(now + backoff.min(settings.get_max_backoff_duration()));
+        if self.suspect_since.is_some() {
+            return false;
+        }
+        self.suspect_since = Some(now);
+        true
+    }
+
+    /// True if the peer is suspect since longer than `evict_after`
+    pub(crate) fn should_evict(&self, now: Instant, settings: &Settings) -> bool {
+        match (self.suspect_since, settings.get_evict_after_duration()) {
+            (Some(since), Some(grace)) => now.duration_since(since) >= grace,
+            _ => false,
+        }
+    }
+}
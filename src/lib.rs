@@ -7,7 +7,7 @@
 //! /// Start a new node
 //! fn main() {
 //!     let rt = tokio::runtime::Runtime::new().expect("Runtime expected to start but failed");
-//!     match Node::new(DefaultHook {}).start(rt) {
+//!     match Node::new(DefaultHook::new()).start(rt) {
 //!         Ok(_) => println!("Successfully exit"),
 //!         Err(err) => eprintln!("Node crash with error: {:?}", err),
 //!     }
@@ -16,13 +16,29 @@
 
 mod api;
 mod common;
+mod health;
 mod log_entry;
+mod membership;
 mod node;
 mod state;
+mod storage;
 mod workflow;
 
+pub use api::{io_msg, HttpTransport, InMemoryNetwork, InMemoryTransport, Transport};
 pub use common::config::Settings;
-pub use common::hook_trait::Hook;
+pub use common::coprocess::CoprocessHook;
+pub use common::error::{
+    Error, ErrorResult, HookError, HookResult, HttpErrorResult, MembershipError, ProposeError,
+    ReadIndexError, TransferError, WarnResult, Warning,
+};
+pub use common::hook_trait::{AsyncHook, BlockingHook, Hook, NodeContext};
 pub use common::scripts::DefaultHook;
-pub use log_entry::Term;
+pub use common::state_machine::{StateMachine, StateMachineHandle, StateMachineHook};
+pub use common::webhook::WebhookHook;
+pub use common::Url;
+pub use health::PeerHealth;
+pub use log_entry::{EntryKind, Term};
+pub use membership::Configuration;
 pub use node::Node;
+pub use storage::{FileLogStore, LogStore, MemoryLogStore};
+pub use workflow::propose::CommittedIndex;
@@ -2,169 +2,266 @@
 // This source code is licensed under the GPLv3 license, you can found the
 // LICENSE file in the root directory of this source tree.
 
-use std::{cmp::Ordering, collections::HashMap, fmt::Display};
+use std::sync::{
+    atomic::{AtomicUsize, Ordering},
+    Arc,
+};
 
+use crate::{
+    common::error::{throw, Error, ErrorResult},
+    membership::Configuration,
+    storage::{LogStore, MemoryLogStore},
+};
 use chrono::{SecondsFormat, Utc};
 use serde::{Deserialize, Serialize};
 use tracing::{debug, warn};
 
-#[derive(Debug, Default, Deserialize, Serialize, Clone)]
+/// What an entry of the log carries. The hooks get every entry, the
+/// content of the internal entries (all but `Normal`) is empty.
+#[derive(Debug, Default, Deserialize, Serialize, Clone, PartialEq, Eq)]
+pub enum EntryKind {
+    /// Content of the user, prepared by the hook or proposed
+    #[default]
+    Normal,
+    /// Empty entry
+    Noop,
+    /// Voters of the cluster from that entry, see [crate::membership]
+    Configuration(Configuration),
+}
+
+/// An entry of the log. The `index` is the position of the entry in the
+/// log, the `term` is the election term of the leader that created it.
+#[derive(Debug, Default, Deserialize, Serialize, Clone, PartialEq, Eq)]
 pub struct LogEntry {
-    pub id: usize,
+    pub index: usize,
+    pub term: usize,
     pub timestamp: String,
-    pub content: String,
+    /// Content of the user, base64 in JSON
+    #[serde(with = "crate::common::base64_bytes")]
+    pub content: Vec<u8>,
+    #[serde(default)]
+    pub kind: EntryKind,
 }
 
-impl PartialEq for Term {
-    fn eq(&self, other: &Self) -> bool {
-        self.id == other.id && self.content == other.content && self.timestamp == other.timestamp
-    }
+/// Log of the node, terms are stored in a [LogStore] until they are
+/// compacted.
+pub struct Entries {
+    store: Box<dyn LogStore>,
+    /// Copy of the last index of the store
+    last_index: Arc<AtomicUsize>,
 }
 
-impl PartialOrd for Term {
-    fn partial_cmp(&self, other: &Self) -> Option<std::cmp::Ordering> {
-        match self.id.partial_cmp(&other.id) {
-            Some(core::cmp::Ordering::Equal) => {}
-            ord => return ord,
-        }
-        match self.timestamp.partial_cmp(&other.timestamp) {
-            Some(core::cmp::Ordering::Equal) => Some(Ordering::Equal),
-            ord => ord,
-        }
+impl Default for Entries {
+    fn default() -> Self {
+        Self::new()
     }
 }
 
-#[derive(Debug, Default, Deserialize, Serialize)]
-pub struct Entries {
-    inner: HashMap<usize, (String, String)>,
-    latest: usize,
-    commit_index: usize,
-    current: LogEntry,
-}
-
 pub type Term = LogEntry;
 
 impl Term {
-    pub fn _new<T: Display>(id: usize, content: T) -> Self {
+    pub fn _new(index: usize, term: usize, content: impl Into<Vec<u8>>) -> Self {
         let timestamp = Utc::now().to_rfc3339_opts(SecondsFormat::Nanos, false);
         Term {
-            id,
+            index,
+            term,
             timestamp,
-            content: content.to_string(),
+            content: content.into(),
+            kind: EntryKind::Normal,
+        }
+    }
+
+    /// True if the entry is created by the node, not by the user
+    pub fn is_internal(&self) -> bool {
+        self.kind != EntryKind::Normal
+    }
+
+    /// Voters of the cluster from that entry, for a configuration entry
+    pub fn configuration(&self) -> Option<&Configuration> {
+        match &self.kind {
+            EntryKind::Configuration(configuration) => Some(configuration),
+            _ => None,
         }
     }
 }
 
 impl Entries {
-    /// Same as default
+    /// Entries stored in memory
     pub fn new() -> Self {
-        Self {
-            inner: HashMap::default(),
-            latest: 0,
-            commit_index: 0,
-            current: LogEntry::default(),
-        }
+        Self::with_store(Box::<MemoryLogStore>::default())
     }
 
-    pub fn contains(&self, term: &Term) -> bool {
-        self.inner.contains_key(&term.id)
+    /// Entries stored in the given `store`
+    pub fn with_store(store: Box<dyn LogStore>) -> Self {
+        let last_index = Arc::new(AtomicUsize::new(store.last_index()));
+        Self { store, last_index }
+    }
+
+    /// Index of the last entry, kept up to date with the logs. Read by the
+    /// hook without locking the logs, see [crate::NodeContext].
+    pub(crate) fn shared_last_index(&self) -> Arc<AtomicUsize> {
+        self.last_index.clone()
     }
 
     /// Insert or replace a term. Used in the leader and append_term workflow.
     /// If the term exists, we would like to rollback to avoid conflicts.
     ///
     /// The call of the hook is deferred to the caller if that methods return true.
-    pub fn insert(&mut self, term: &Term) {
-        if term.id <= self.commit_index {
+    pub fn insert(&mut self, term: &Term) -> ErrorResult<()> {
+        if term.index <= self.commit_index() {
             panic!("Trying to re-insert an already committed term")
         }
-        if term.id <= self.latest {
-            for i in term.id..=self.latest {
-                self.inner.remove(&i);
-            }
+        if term.index <= self.store.last_index() {
+            store_result(self.store.truncate_from(term.index))?;
         }
-        self.latest = term.id;
-        self.current = term.clone();
-        self.inner
-            .insert(term.id, (term.timestamp.clone(), term.content.clone()));
+        let res = store_result(self.store.insert(term));
+        self.last_index
+            .store(self.store.last_index(), Ordering::Relaxed);
+        res
+    }
+
+    /// Create a new entry from a content in the election `term`
+    /// Return the created entry
+    pub fn append(&mut self, term: usize, content: Vec<u8>) -> ErrorResult<Term> {
+        self.append_entry(term, content, EntryKind::Normal)
+    }
+
+    /// Create an empty entry in the election `term`, appended by a new
+    /// leader. Return the created entry
+    pub fn append_noop(&mut self, term: usize) -> ErrorResult<Term> {
+        self.append_entry(term, vec![], EntryKind::Noop)
+    }
+
+    /// Create a configuration entry in the election `term`, see
+    /// [crate::membership]. Return the created entry
+    pub fn append_configuration(
+        &mut self,
+        term: usize,
+        configuration: Configuration,
+    ) -> ErrorResult<Term> {
+        self.append_entry(term, vec![], EntryKind::Configuration(configuration))
     }
 
-    /// Create a new term from a content
-    /// Return the created term
-    pub fn append(&mut self, content: String) -> Term {
-        let id = self.latest + 1;
+    fn append_entry(
+        &mut self,
+        term: usize,
+        content: Vec<u8>,
+        kind: EntryKind,
+    ) -> ErrorResult<Term> {
+        let index = self.store.last_index() + 1;
         let timestamp = Utc::now().to_rfc3339_opts(SecondsFormat::Nanos, false);
-        debug!("log entry: append term {} {} {}", id, content, timestamp);
+        debug!(
+            "log entry: append term {} ({}) {} bytes {}",
+            index,
+            term,
+            content.len(),
+            timestamp
+        );
         let t = Term {
-            id,
+            index,
+            term,
             timestamp,
             content,
+            kind,
         };
-        self.latest = id;
-        self.current = t.clone();
-        self.insert(&t);
-        t
+        self.insert(&t)?;
+        Ok(t)
     }
 
+    /// Find the term at `index`, the last term included in the snapshot is
+    /// also found after a compaction.
     pub fn find(&self, index: usize) -> Option<Term> {
-        self.inner.get(&index).map(|(t, c)| LogEntry {
-            id: index,
-            timestamp: t.clone(),
-            content: c.clone(),
-        })
+        self.store
+            .find(index)
+            .or_else(|| self.store.compacted().filter(|term| term.index == index))
+    }
+
+    /// Election term of the entry at `index`. The index 0 is the empty log
+    /// and always has the term 0.
+    pub fn term_at(&self, index: usize) -> Option<usize> {
+        if index == 0 {
+            return Some(0);
+        }
+        self.find(index).map(|term| term.term)
+    }
+
+    /// Terms in the log between `from` and `to` included
+    pub fn range(&self, from: usize, to: usize) -> Vec<Term> {
+        self.store.range(from, to)
     }
 
     pub fn check_commit(&self, index: usize) -> bool {
-        if index <= self.commit_index {
+        if index <= self.commit_index() {
             warn!("Trying to re-commit index {index}");
             return false;
         }
-        if index > self.latest {
+        if index > self.last_index() {
             warn!("Trying to commit an unknown index");
             return false;
         }
         true
     }
 
-    pub fn set_commit(&mut self, index: usize) {
+    pub fn set_commit(&mut self, index: usize) -> ErrorResult<()> {
         if self.check_commit(index) {
-            self.inner.remove(&index);
-            self.commit_index = index;
+            store_result(self.store.set_commit_index(index))?;
         }
+        Ok(())
     }
 
-    /// Find the latest log entry. Creates a new one if
-    /// empty.
-    pub fn latest(&mut self) -> (bool, Term) {
-        if let Some((t, c)) = self.inner.get(&self.latest) {
-            debug!("log entry: latest found {}", self.latest);
-            (
-                false,
-                LogEntry {
-                    id: self.latest,
-                    timestamp: t.clone(),
-                    content: c.clone(),
-                },
-            )
-        } else {
-            debug!("log entry: latest: append a new empty entry");
-            (true, self.append("default".into()))
-        }
+    pub fn last_index(&self) -> usize {
+        self.store.last_index()
     }
 
-    pub fn last_index(&self) -> usize {
-        self.latest
+    /// Election term of the last entry, 0 if the log is empty.
+    pub fn last_term(&self) -> usize {
+        self.term_at(self.last_index()).unwrap_or_default()
     }
 
-    pub fn set_last_index(&mut self, index: usize) {
-        self.latest = index;
+    /// Restore the commit index saved before a restart, in case the store
+    /// doesn't remember it.
+    pub fn restore(&mut self, commit_index: usize) -> ErrorResult<()> {
+        if commit_index > self.store.commit_index() {
+            store_result(self.store.set_commit_index(commit_index))?;
+        }
+        Ok(())
     }
 
     pub fn commit_index(&self) -> usize {
-        self.commit_index
+        self.store.commit_index()
+    }
+
+    /// Last term included in the latest snapshot, `None` if the log has never
+    /// been compacted.
+    pub fn compacted(&self) -> Option<Term> {
+        self.store.compacted()
+    }
+
+    /// Drop the committed terms up to `last_included` included.
+    pub fn compact(&mut self, last_included: &Term) -> ErrorResult<()> {
+        store_result(self.store.compact(last_included))
     }
 
-    pub fn current_term(&self) -> Term {
-        self.current.clone()
+    /// Reset the log on a snapshot sent by the leader. The entries following
+    /// the snapshot are kept if the log contains the last included term,
+    /// otherwise the whole log is discarded.
+    pub fn install_snapshot(&mut self, last_included: &Term) -> ErrorResult<()> {
+        let keep_suffix = self.term_at(last_included.index) == Some(last_included.term);
+        store_result(self.store.compact(last_included))?;
+        if !keep_suffix {
+            store_result(self.store.truncate_from(last_included.index + 1))?;
+        }
+        self.last_index
+            .store(self.store.last_index(), Ordering::Relaxed);
+        Ok(())
+    }
+}
+
+/// Translate a store failure into an `Error`. A node that can't write its
+/// log should stop.
+fn store_result<T>(res: std::io::Result<T>) -> ErrorResult<T> {
+    match res {
+        Ok(val) => Ok(val),
+        Err(err) => throw!(Error::LogStoreFailure(err.to_string())),
     }
 }
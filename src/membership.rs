@@ -0,0 +1,177 @@
+// License:
+// This source code is licensed under the GPLv3 license, you can found the
+// LICENSE file in the root directory of this source tree.
+
+//! Cluster membership. The voters of the cluster are described by
+//! configuration entries replicated in the logs. A node uses the latest
+//! configuration of its logs, committed or not.
+//!
+//! The voters change with a joint consensus (§6 of the raft thesis). The
+//! leader first appends a joint configuration C_old,new, the decisions need
+//! a majority of both the old and the new voters. Once it's committed, the
+//! leader appends C_new.
+//!
+//! The learners receive the terms but don't vote, they are never counted in
+//! a quorum. They are added and removed with a single configuration entry,
+//! and join the voters with a joint consensus.
+
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, BTreeSet};
+
+/// Voters of the cluster, carried by a configuration entry of the logs.
+#[derive(Debug, Default, Clone, PartialEq, Eq, Deserialize, Serialize)]
+pub struct Configuration {
+    /// Voters of the configuration, C_old during a joint consensus
+    pub voters: BTreeSet<String>,
+    /// New voters during a joint consensus, C_new
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub next: Option<BTreeSet<String>>,
+    /// Nodes that receive the terms without voting
+    #[serde(default, skip_serializing_if = "BTreeSet::is_empty")]
+    pub learners: BTreeSet<String>,
+}
+
+impl Configuration {
+    pub fn new(voters: BTreeSet<String>) -> Self {
+        Self {
+            voters,
+            next: None,
+            learners: BTreeSet::new(),
+        }
+    }
+
+    /// True during a joint consensus
+    pub fn is_joint(&self) -> bool {
+        self.next.is_some()
+    }
+
+    /// True if the `node` votes in the old or in the new configuration
+    pub fn is_voter(&self, node: &str) -> bool {
+        self.voters.contains(node) || self.next.as_ref().is_some_and(|n| n.contains(node))
+    }
+
+    pub fn is_learner(&self, node: &str) -> bool {
+        self.learners.contains(node)
+    }
+
+    /// True if the `node` has its place in the configuration, among the
+    /// learners for a pure follower and the voters otherwise
+    pub(crate) fn has_joined(&self, node: &str, learner: bool) -> bool {
+        if learner {
+            self.is_learner(node)
+        } else {
+            self.is_voter(node)
+        }
+    }
+
+    /// Voters of both configurations and learners, the nodes that receive
+    /// the terms
+    pub fn members(&self) -> BTreeSet<String> {
+        let mut members = self.voters.clone();
+        if let Some(next) = &self.next {
+            members.extend(next.iter().cloned());
+        }
+        members.extend(self.learners.iter().cloned());
+        members
+    }
+
+    /// Joint configuration from the current voters to the `next` ones, the
+    /// learners that become voters leave the learners
+    pub(crate) fn joint(&self, next: BTreeSet<String>) -> Self {
+        Self {
+            voters: self.voters.clone(),
+            learners: self.learners.difference(&next).cloned().collect(),
+            next: Some(next),
+        }
+    }
+
+    /// New configuration that ends the joint consensus, the learners stay
+    pub(crate) fn leave_joint(&self) -> Self {
+        Self {
+            voters: self.next.clone().unwrap_or_else(|| self.voters.clone()),
+            next: None,
+            learners: self.learners.clone(),
+        }
+    }
+
+    /// True if the nodes that `agree` are a majority of the voters, and of
+    /// the new voters during a joint consensus.
+    pub fn has_quorum(&self, agree: impl Fn(&str) -> bool) -> bool {
+        let majority = |voters: &BTreeSet<String>| {
+            voters.iter().filter(|node| agree(node)).count() > voters.len() / 2
+        };
+        majority(&self.voters) && self.next.as_ref().is_none_or(majority)
+    }
+
+    /// Greatest index stored by a majority of the voters, and of the new
+    /// voters during a joint consensus. `index_of` gives the latest index
+    /// stored by a node.
+    pub fn quorum_index(&self, index_of: impl Fn(&str) -> usize) -> usize {
+        let quorum_index = |voters: &BTreeSet<String>| {
+            let mut indexes: Vec<usize> = voters.iter().map(|node| index_of(node)).collect();
+            indexes.sort_unstable_by(|a, b| b.cmp(a));
+            indexes.get(voters.len() / 2).copied().unwrap_or_default()
+        };
+        let index = quorum_index(&self.voters);
+        match &self.next {
+            Some(next) => index.min(quorum_index(next)),
+            None => index,
+        }
+    }
+}
+
+/// Configurations known by a node, see `Node::membership`
+#[derive(Debug, Default, Clone)]
+pub(crate) struct Membership {
+    /// Index of the latest committed configuration entry, 0 for the
+    /// configuration given by the settings
+    pub committed_index: usize,
+    /// Latest committed configuration
+    pub committed: Configuration,
+    /// Configuration entries of the logs that aren't committed yet
+    pub pending: BTreeMap<usize, Configuration>,
+}
+
+impl Membership {
+    pub fn new(committed_index: usize, committed: Configuration) -> Self {
+        Self {
+            committed_index,
+            committed,
+            pending: BTreeMap::new(),
+        }
+    }
+
+    /// Configuration in use, the latest of the logs
+    pub fn active(&self) -> &Configuration {
+        match self.pending.values().next_back() {
+            Some(configuration) => configuration,
+            None => &self.committed,
+        }
+    }
+
+    /// True if a configuration entry isn't committed yet, or if the
+    /// committed configuration is joint. Only one change at a time.
+    pub fn is_changing(&self) -> bool {
+        !self.pending.is_empty() || self.committed.is_joint()
+    }
+
+    /// A configuration entry has been appended at `index`
+    pub fn append(&mut self, index: usize, configuration: Configuration) {
+        self.pending.insert(index, configuration);
+    }
+
+    /// The entries from `index` have been removed from the logs
+    pub fn truncate(&mut self, index: usize) {
+        self.pending.split_off(&index);
+    }
+
+    /// The entries up to `commit_index` are committed
+    pub fn commit(&mut self, commit_index: usize) {
+        let pending = self.pending.split_off(&(commit_index + 1));
+        let committed = std::mem::replace(&mut self.pending, pending);
+        if let Some((index, configuration)) = committed.into_iter().next_back() {
+            self.committed_index = index;
+            self.committed = configuration;
+        }
+    }
+}
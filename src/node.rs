@@ -3,30 +3,41 @@
 // LICENSE file in the root directory of this source tree.
 
 use crate::{
+    api::{HttpTransport, Transport},
     common::{
         config::{self, Settings},
-        error::{throw, Error, ErrorResult},
-        hook_trait::Hook,
+        error::{throw, Error, ErrorResult, HookError},
+        hook_trait::{AsyncHook, BlockingHook, Hook, NodeContext},
         Url,
     },
+    health::PeerHealth,
     log_entry::Entries,
+    membership::{Configuration, Membership},
     state::{EStatus, Status},
+    storage::{open_log_store, HardStateStorage, LogStore, Snapshot},
+    workflow::{propose::Proposal, read_index::Rounds},
 };
+use rand::{rngs::StdRng, Rng, SeedableRng};
 use serde::{Deserialize, Serialize};
 use std::{
-    collections::{HashMap, HashSet, VecDeque},
-    sync::Arc,
+    collections::{BTreeMap, BTreeSet, HashMap, VecDeque},
+    sync::{atomic::AtomicBool, Arc},
+    time::Duration,
 };
 use tokio::{
     runtime::Runtime,
-    sync::{oneshot::Sender, Mutex, RwLock},
+    sync::{oneshot::Sender, watch, Mutex, RwLock},
     task::JoinHandle,
+    time::Instant,
 };
 use tracing::metadata::LevelFilter;
 use tracing_subscriber::{
     filter::filter_fn, prelude::__tracing_subscriber_SubscriberExt, util::SubscriberInitExt, Layer,
 };
 
+/// Latest index a leader knows a node has in its log. The index is
+/// `Validated` when the node acknowledged the entries up to it, `Pending`
+/// when it's a guess to check with the next append term.
 pub enum NextIndex {
     Validated(usize),
     Pending(usize),
@@ -43,11 +54,34 @@ impl NextIndex {
     pub fn validated(&self) -> usize {
         match self {
             NextIndex::Validated(val) => *val,
-            NextIndex::Pending(_) => 1, /* return minimum */
+            NextIndex::Pending(_) => 0, /* return minimum */
         }
     }
 }
 
+/// Election state of the node, both values change together and are
+/// persisted before the node replies to a request.
+#[derive(Debug, Default, Clone, PartialEq, Eq, Deserialize, Serialize)]
+pub struct Election {
+    /// Latest election term the node has seen
+    pub current_term: usize,
+    /// Candidate that received the vote in the current term
+    pub vote_for: Option<String>,
+}
+
+impl Election {
+    /// Move to a newer election `term`, the vote is reset. Return false if
+    /// `term` isn't newer than the current term.
+    pub fn update_term(&mut self, term: usize) -> bool {
+        if term <= self.current_term {
+            return false;
+        }
+        self.current_term = term;
+        self.vote_for = None;
+        true
+    }
+}
+
 #[derive(Clone)]
 pub struct Node {
     /// Current state of the local node
@@ -61,17 +95,55 @@ pub struct Node {
     /// Next indexes by know nodes (table of None at initialization)
     /// Is initialized on comes to power.
     pub next_indexes: Arc<RwLock<HashMap<Url, NextIndex>>>,
-    /// Wait to connect
-    pub waiting_nodes: Arc<Mutex<VecDeque<String>>>,
-    /// List of nodes that can be potential leader and candidates
-    /// Note only these nodes votes
-    pub node_list: Arc<RwLock<HashSet<String>>>,
-    /// Last vote Some(node id, last log term) if voted, None otherwise
-    pub vote_for: Arc<RwLock<Option<(String, usize)>>>,
+    /// Nodes that connected to the leader and wait to join the cluster
+    pub waiting_nodes: Arc<Mutex<VecDeque<NodeInfo>>>,
+    /// Members of the active configuration except the local node, the
+    /// leader sends them the terms. See `membership`
+    pub node_list: Arc<RwLock<BTreeSet<String>>>,
+    /// Configurations of the cluster found in the logs
+    pub(crate) membership: Arc<RwLock<Membership>>,
+    /// Current election term and vote of the node
+    pub election: Arc<RwLock<Election>>,
     /// hook interface
-    pub hook: Arc<Box<dyn Hook>>,
+    pub hook: Arc<Box<dyn AsyncHook>>,
+    /// Network interface to the other nodes
+    pub transport: Arc<Box<dyn Transport>>,
+    /// Stable storage of the election state
+    pub storage: Arc<HardStateStorage>,
+    /// Latest snapshot of the state machine, sent to the nodes that are
+    /// late behind the compacted logs
+    pub snapshot: Arc<RwLock<Option<Snapshot>>>,
+    /// Nodes the leader is sending its snapshot to, the snapshot isn't sent
+    /// again before they answer
+    pub(crate) installing: Arc<std::sync::Mutex<BTreeSet<String>>>,
+    /// Proposals waiting for the commit of their entry, by index
+    pub(crate) proposals: Arc<Mutex<BTreeMap<usize, Proposal>>>,
+    /// Latest time the node accepted a request from a leader
+    pub(crate) leader_contact: Arc<std::sync::Mutex<Option<Instant>>>,
+    /// Index of the latest entry applied by the hook
+    pub(crate) applied: Arc<watch::Sender<usize>>,
+    /// Held while the hook applies the committed entries or restores a
+    /// snapshot, the entries that follow `applied` are applied in order and
+    /// once. The logs aren't locked meanwhile.
+    pub(crate) committing: Arc<Mutex<()>>,
+    /// Sending sessions of the leader, used to confirm the lead before a
+    /// read
+    pub(crate) rounds: Arc<watch::Sender<Rounds>>,
+    /// Target of the leadership transfer in progress, the leader doesn't
+    /// accept proposals meanwhile
+    pub(crate) transfer: Arc<std::sync::Mutex<Option<Url>>>,
+    /// Set by a `timeout_now` request, the next candidature skips the pre
+    /// vote round
+    pub(crate) forced_election: Arc<AtomicBool>,
+    /// Health of the peers, tracked by the leader. See `health`
+    pub(crate) health: Arc<std::sync::Mutex<BTreeMap<String, PeerHealth>>>,
+    /// Set when a hook returns a fatal error, the main loop stops
+    pub(crate) halt: Arc<watch::Sender<Option<HookError>>>,
     /// Unique node id, used as a temporary identifier in the network
     pub uuid: [u8; 16],
+    /// Random generator of the node, seeded from the entropy by default.
+    /// See `Node::with_seed`
+    pub rng: Arc<std::sync::Mutex<StdRng>>,
     /// Container for mock return values in some unit tests
     #[cfg(test)]
     pub utest_data: UTestData,
@@ -86,34 +158,62 @@ pub struct UTestData {
 
 // todo: verify if leader correctly update the `last_applied` and call the
 //       `apply_term` script each time he create a term
-// todo: the logs (Entries structure) should prune the oldest committed terms
-//       with a kind of buffer (size in the settings)
 // todo: add a maximum for logs production
 // todo: we need to define what should be in the debug level of tracing.
 
 impl Node {
     /// Private default implementation
-    fn default(settings: Settings, hook: impl Hook + 'static) -> Self {
+    fn default(settings: Settings, hook: impl AsyncHook + 'static) -> Self {
+        let mut rng = StdRng::from_entropy();
+        let local = format!("{}:{}", settings.addr, settings.port);
+        let peers: BTreeSet<String> = settings
+            .nodes
+            .iter()
+            .filter(|node| **node != local)
+            .cloned()
+            .collect();
+        let mut voters = peers.clone();
+        voters.insert(local);
         Self {
             p_status: Status::connection_pending(),
             heartbeat: Default::default(),
-            logs: Default::default(),
+            logs: Arc::new(Mutex::new(Entries::with_store(open_log_store(&settings)))),
             next_indexes: Default::default(),
             waiting_nodes: Default::default(),
-            node_list: Arc::new(RwLock::new(HashSet::from_iter(
-                settings.nodes.iter().cloned(),
-            ))),
+            node_list: Arc::new(RwLock::new(peers)),
+            membership: Arc::new(RwLock::new(Membership::new(0, Configuration::new(voters)))),
+            storage: Arc::new(HardStateStorage::new(&settings)),
+            transport: Arc::new(Box::new(HttpTransport::new(&settings))),
+            snapshot: Default::default(),
+            installing: Default::default(),
+            proposals: Default::default(),
+            leader_contact: Default::default(),
+            applied: Arc::new(watch::Sender::new(0)),
+            committing: Default::default(),
+            rounds: Arc::new(watch::Sender::new(Rounds::default())),
+            transfer: Default::default(),
+            forced_election: Default::default(),
+            health: Default::default(),
+            halt: Arc::new(watch::Sender::new(None)),
             settings,
-            vote_for: Default::default(),
+            election: Default::default(),
             hook: Arc::new(Box::new(hook)),
-            uuid: generate_uuid(),
+            uuid: generate_uuid(&mut rng),
+            rng: Arc::new(std::sync::Mutex::new(rng)),
             #[cfg(test)]
             utest_data: Default::default(),
         }
     }
 
-    /// Creates a new default node
+    /// Creates a new default node, the settings are read from the file
+    /// given as first argument of the program. The synchronous `hook` runs
+    /// on the blocking threads, see [BlockingHook].
     pub fn new(hook: impl Hook + 'static) -> Self {
+        Self::new_async(BlockingHook::new(hook))
+    }
+
+    /// Creates a new default node with an asynchronous hook
+    pub fn new_async(hook: impl AsyncHook + 'static) -> Self {
         let layer = tracing_subscriber::fmt::layer()
             .with_filter(filter_fn(|metadata| metadata.target().starts_with("hook")))
             .with_filter(LevelFilter::TRACE); // todo: use an input or a setting for log level
@@ -142,7 +242,7 @@ impl Node {
 
     #[cfg(test)]
     /// Creates a node with the given settings and status
-    pub fn test_new(settings: Settings, p_status: Status, hook: impl Hook + 'static) -> Self {
+    pub fn test_new(settings: Settings, p_status: Status, hook: impl AsyncHook + 'static) -> Self {
         Self {
             p_status,
             ..Self::default(settings, hook)
@@ -150,17 +250,54 @@ impl Node {
     }
 
     pub fn new_with_settings(settings: Settings, hook: impl Hook + 'static) -> Self {
+        Self::default(settings, BlockingHook::new(hook))
+    }
+
+    pub fn new_with_settings_async(settings: Settings, hook: impl AsyncHook + 'static) -> Self {
+        Self::default(settings, hook)
+    }
+
+    /// Replace the log store of the node, by default the log store is
+    /// defined by the settings (see `data_dir`).
+    pub fn with_log_store(self, store: impl LogStore + 'static) -> Self {
         Self {
-            ..Self::default(settings, hook)
+            logs: Arc::new(Mutex::new(Entries::with_store(Box::new(store)))),
+            ..self
+        }
+    }
+
+    /// Replace the transport of the node, by default the node talks HTTP
+    /// (see [HttpTransport]).
+    pub fn with_transport(self, transport: impl Transport + 'static) -> Self {
+        Self {
+            transport: Arc::new(Box::new(transport)),
+            ..self
+        }
+    }
+
+    /// Seed the random generator of the node. The uuid and the election
+    /// timeouts are drawn from it, a cluster of seeded nodes takes the same
+    /// decisions at each run on a paused tokio clock.
+    pub fn with_seed(self, seed: u64) -> Self {
+        let mut rng = StdRng::seed_from_u64(seed);
+        Self {
+            uuid: generate_uuid(&mut rng),
+            rng: Arc::new(std::sync::Mutex::new(rng)),
+            ..self
         }
     }
 
     async fn internal_main_loop(&self) -> ErrorResult<()> {
+        let last_index = self.logs.lock().await.shared_last_index();
+        self.hook.set_context(NodeContext::new(
+            self.node_url(),
+            self.p_status.subscribe(),
+            self.applied.subscribe(),
+            last_index,
+        ));
         self.initialize().await?;
         loop {
-            if self.p_status.is_pending().await {
-                self.p_status.wait();
-            }
+            self.p_status.wait_while(EStatus::ConnectionPending).await;
             let status_loop = async {
                 match self.p_status.status().await {
                     EStatus::Leader => self.run_leader().await,
@@ -178,6 +315,9 @@ impl Node {
                         throw!(*err)
                     }
                 },
+                err = self.halted() => {
+                    throw!(Error::HookFailure(err))
+                },
                 _ = tokio::signal::ctrl_c() => {
                     println!("Handle a graceful shutdown");
                     break
@@ -197,8 +337,26 @@ impl Node {
         tokio::spawn(async move { self.internal_main_loop().await })
     }
 
+    /// Voters of the active configuration, the local node included
     pub(crate) async fn get_node_list(&self) -> Vec<String> {
-        self.node_list.read().await.iter().cloned().collect()
+        let membership = self.membership.read().await;
+        membership.active().members().into_iter().collect()
+    }
+
+    /// Address of the local node, used as identifier in the raft requests
+    pub(crate) fn node_url(&self) -> String {
+        format!("{}:{}", self.settings.addr, self.settings.port)
+    }
+
+    /// Current election term of the node
+    pub(crate) async fn current_term(&self) -> usize {
+        self.election.read().await.current_term
+    }
+
+    /// Random heartbeat timeout, see `Settings::get_randomized_timeout`
+    pub(crate) fn randomized_timeout(&self) -> Duration {
+        self.settings
+            .get_randomized_timeout(&mut *self.rng.lock().unwrap())
     }
 }
 
@@ -206,12 +364,13 @@ impl Node {
 pub struct NodeInfo {
     pub hash: [u8; 16],
     pub addr: String,
+    /// The node never votes, it stays a learner
+    #[serde(default)]
+    pub learner: bool,
 }
 
-pub(crate) fn generate_uuid() -> [u8; 16] {
+pub(crate) fn generate_uuid<R: Rng>(rng: &mut R) -> [u8; 16] {
     let mut ret = [0u8; 16];
-    for a in ret.iter_mut() {
-        *a = rand::random();
-    }
+    rng.fill(&mut ret);
     ret
 }
@@ -28,13 +28,15 @@ use crate::common::{
     error::{throw, Error, ErrorResult},
     Url,
 };
-use std::sync::{Arc, Condvar, Mutex};
-use tokio::sync::RwLock;
+use std::sync::Arc;
+use tokio::sync::watch;
 use tracing::trace;
 
 mod node;
 
-#[derive(Clone, Copy)]
+pub(crate) use node::StatusEvent;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
 pub enum EStatus {
     ConnectionPending,
     Follower,
@@ -46,117 +48,122 @@ pub enum EStatus {
 /* "public" access to the status */
 /***********************************************/
 
+/// The status and the known leader are kept in a watch channel, so the
+/// workflows can wait a modification without blocking the runtime.
 #[derive(Clone)]
 pub struct Status {
-    inner: Arc<RwLock<(EStatus, Option<Url>)>>,
-    cv: Arc<(Mutex<()>, Condvar)>,
+    inner: Arc<watch::Sender<(EStatus, Option<Url>)>>,
 }
 
 #[cfg(test)]
 impl Status {
     /// Test feature. Create a leader status.
     pub fn leader() -> Status {
-        let inner = Arc::new(RwLock::new((EStatus::Leader, None)));
-        let cv = Arc::new((Mutex::new(()), Condvar::new()));
-        Status { inner, cv }
+        Status::create(EStatus::Leader, None)
     }
 
     /// Test feature. Create a follower status.
     pub fn follower(leader: Url) -> Status {
-        let inner = Arc::new(RwLock::new((EStatus::Follower, Some(leader))));
-        let cv = Arc::new((Mutex::new(()), Condvar::new()));
-        Status { inner, cv }
+        Status::create(EStatus::Follower, Some(leader))
     }
 
     /// Test feature. Create a follower status.
     pub fn candidate() -> Status {
-        let inner = Arc::new(RwLock::new((EStatus::Candidate, None)));
-        let cv = Arc::new((Mutex::new(()), Condvar::new()));
-        Status { inner, cv }
+        Status::create(EStatus::Candidate, None)
     }
 }
 
 impl Status {
-    /// Wait a modification of the status
-    pub(crate) fn wait(&self) {
-        let (mutex, condvar) = &*self.cv;
-        let guard = mutex.lock().unwrap();
-        let _unused = condvar.wait(guard).unwrap();
+    fn create(status: EStatus, leader: Option<Url>) -> Status {
+        let (sender, _) = watch::channel((status, leader));
+        Status {
+            inner: Arc::new(sender),
+        }
+    }
+
+    /// Wait while the status matches `status`
+    pub(crate) async fn wait_while(&self, status: EStatus) {
+        let mut recv = self.inner.subscribe();
+        let _ = recv.wait_for(|(current, _)| *current != status).await;
+    }
+
+    /// Receiver of the status changes, with the leader known by the node
+    pub(crate) fn subscribe(&self) -> watch::Receiver<(EStatus, Option<Url>)> {
+        self.inner.subscribe()
     }
 
     pub(crate) async fn status(&self) -> EStatus {
-        self.inner.read().await.0
+        self.inner.borrow().0
     }
 
     /// Switch the current status to candidate.
     /// Follower -> Candidate
     pub(crate) async fn switch_to_candidate(&self) -> ErrorResult<()> {
-        let mut inner = self.inner.write().await;
-        match inner.0 {
-            EStatus::Follower => { /* OK */ }
-            EStatus::ConnectionPending => { /* OK */ }
-            _ => throw!(Error::WrongStatus),
-        }
-        trace!("switch to candidate");
-        *inner = (EStatus::Candidate, None);
-        let (mutex, condvar) = &*self.cv;
-        let _guard = mutex.lock().unwrap();
-        condvar.notify_all();
-        Ok(())
+        self.switch(EStatus::Candidate, None, |status| {
+            matches!(status, EStatus::Follower | EStatus::ConnectionPending)
+        })
     }
 
     /// Switch the current status to leader.
     /// Candidate -> Leader
     pub(crate) async fn switch_to_leader(&self) -> ErrorResult<()> {
-        let mut inner = self.inner.write().await;
-        match inner.0 {
-            EStatus::Candidate => { /* OK */ }
-            _ => throw!(Error::WrongStatus),
-        }
-        trace!("switch to leader");
-        *inner = (EStatus::Leader, None);
-        let (mutex, condvar) = &*self.cv;
-        let _guard = mutex.lock().unwrap();
-        condvar.notify_all();
-        Ok(())
+        self.switch(EStatus::Leader, None, |status| {
+            matches!(status, EStatus::Candidate)
+        })
     }
 
-    /// Switch the current status to follower.
+    /// Switch the current status to follower, the leader is `None` when
+    /// the node stepped down without knowing the new leader yet.
     /// Every state can turn into a follower
-    pub(crate) async fn switch_to_follower(&self, leader: Url) -> ErrorResult<()> {
-        trace!("switch to follower");
-        let mut inner = self.inner.write().await;
-        *inner = (EStatus::Follower, Some(leader));
-        let (mutex, condvar) = &*self.cv;
-        let _guard = mutex.lock().unwrap();
-        condvar.notify_all();
+    pub(crate) async fn switch_to_follower(&self, leader: Option<Url>) -> ErrorResult<()> {
+        self.switch(EStatus::Follower, leader, |_| true)
+    }
+
+    /// Set the new status if the current one is `allowed`, and notify the
+    /// waiting workflows.
+    fn switch(
+        &self,
+        status: EStatus,
+        leader: Option<Url>,
+        allowed: impl FnOnce(EStatus) -> bool,
+    ) -> ErrorResult<()> {
+        let mut switched = false;
+        self.inner.send_if_modified(|inner| {
+            switched = allowed(inner.0);
+            if switched {
+                trace!("switch to {:?}", status);
+                *inner = (status, leader);
+            }
+            switched
+        });
+        if !switched {
+            throw!(Error::WrongStatus)
+        }
         Ok(())
     }
 
     /// Create a connection pending status, which is the default status.
     pub fn connection_pending() -> Status {
-        let inner = Arc::new(RwLock::new((EStatus::ConnectionPending, None)));
-        let cv = Arc::new((Mutex::new(()), Condvar::new()));
-        Status { inner, cv }
+        Status::create(EStatus::ConnectionPending, None)
     }
 
     pub async fn get_leader(&self) -> Option<Url> {
-        self.inner.read().await.1.clone()
+        self.inner.borrow().1.clone()
     }
 
     pub async fn is_pending(&self) -> bool {
-        matches!(&self.inner.read().await.0, EStatus::ConnectionPending)
+        matches!(self.inner.borrow().0, EStatus::ConnectionPending)
     }
 
     pub async fn is_candidate(&self) -> bool {
-        matches!(&self.inner.read().await.0, EStatus::Candidate)
+        matches!(self.inner.borrow().0, EStatus::Candidate)
     }
 
     pub async fn is_leader(&self) -> bool {
-        matches!(&self.inner.read().await.0, EStatus::Leader)
+        matches!(self.inner.borrow().0, EStatus::Leader)
     }
 
     pub async fn is_follower(&self) -> bool {
-        matches!(&self.inner.read().await.0, EStatus::Follower)
+        matches!(self.inner.borrow().0, EStatus::Follower)
     }
 }
@@ -3,25 +3,86 @@
 // LICENSE file in the root directory of this source tree.
 use super::EStatus;
 use crate::{common::error::ErrorResult, state::Url, Node};
+use tracing::trace;
 
 impl Node {
-    pub(crate) async fn switch_to_candidate(&self) -> ErrorResult<()> {
+    /// The switches return the hook events of the change, the caller fires
+    /// them with `notify_status` once it released the election state.
+    pub(crate) async fn switch_to_candidate(&self) -> ErrorResult<Vec<StatusEvent>> {
         self.p_status.switch_to_candidate().await?;
-        self.hook.switch_status(EStatus::Candidate);
-        Ok(())
+        Ok(vec![StatusEvent::Status(EStatus::Candidate)])
     }
 
-    pub(crate) async fn switch_to_leader(&self) -> ErrorResult<()> {
+    pub(crate) async fn switch_to_leader(&self) -> ErrorResult<Vec<StatusEvent>> {
         self.p_status.switch_to_leader().await?;
-        self.hook.switch_status(EStatus::Leader);
-        Ok(())
+        Ok(vec![
+            StatusEvent::Status(EStatus::Leader),
+            StatusEvent::Leader(self.node_url()),
+        ])
     }
 
-    pub(crate) async fn switch_to_follower(&self, leader: Url) -> ErrorResult<()> {
+    /// Follow the `leader`, a follower only updates the leader it knows. The
+    /// proposals of a deposed leader fail.
+    pub(crate) async fn switch_to_follower(&self, leader: Url) -> ErrorResult<Vec<StatusEvent>> {
+        let changed = self.p_status.get_leader().await.as_ref() != Some(&leader);
+        let mut events = vec![];
         if !self.p_status.is_follower().await {
-            self.p_status.switch_to_follower(leader).await?;
-            self.hook.switch_status(EStatus::Follower);
+            self.p_status
+                .switch_to_follower(Some(leader.clone()))
+                .await?;
+            self.fail_proposals().await;
+            events.push(StatusEvent::Status(EStatus::Follower));
+        } else if changed {
+            self.p_status
+                .switch_to_follower(Some(leader.clone()))
+                .await?;
+        }
+        if changed {
+            events.push(StatusEvent::Leader(leader.to_string()));
+        }
+        Ok(events)
+    }
+
+    /// Compare the election `term` received in a response with the local
+    /// one. If it's newer, persist it and step down. Return true if the node
+    /// stepped down.
+    pub(crate) async fn observe_term(&self, term: usize) -> ErrorResult<bool> {
+        let mut election = self.election.write().await;
+        if !election.update_term(term) {
+            return Ok(false);
+        }
+        trace!("newer term {term} observed, step down");
+        self.persist_hard_state(&election).await?;
+        std::mem::drop(election);
+        let events = self.step_down().await?;
+        self.notify_status(events).await;
+        Ok(true)
+    }
+
+    /// Turn into a follower after seeing a newer election term, the leader
+    /// of that term is still unknown. The pending proposals fail.
+    pub(crate) async fn step_down(&self) -> ErrorResult<Vec<StatusEvent>> {
+        if self.p_status.is_follower().await {
+            return Ok(vec![]);
+        }
+        self.p_status.switch_to_follower(None).await?;
+        self.fail_proposals().await;
+        Ok(vec![StatusEvent::Status(EStatus::Follower)])
+    }
+
+    /// Call the hook for each status event, in order
+    pub(crate) async fn notify_status(&self, events: Vec<StatusEvent>) {
+        for event in events {
+            match event {
+                StatusEvent::Status(status) => self.hook.switch_status(status).await,
+                StatusEvent::Leader(leader) => self.hook.leader_change(&leader).await,
+            }
         }
-        Ok(())
     }
 }
+
+/// Hook event of a status change, see `Node::notify_status`
+pub(crate) enum StatusEvent {
+    Status(EStatus),
+    Leader(String),
+}
@@ -0,0 +1,98 @@
+// License:
+// This source code is licensed under the GPLv3 license, you can found the
+// LICENSE file in the root directory of this source tree.
+
+//! Storage of the log entries. The raft logic lives in
+//! [Entries](crate::log_entry::Entries), a store only keeps the terms by
+//! index and the commit index.
+//!
+//! Two stores are shipped with the library, the volatile [MemoryLogStore]
+//! and the write-ahead log [FileLogStore](super::FileLogStore). You can give
+//! your own implementation to a node with `Node::with_log_store`.
+
+use crate::log_entry::Term;
+use std::{collections::BTreeMap, io};
+
+pub trait LogStore: Send + Sync {
+    /// Insert the term at the index `term.index`. The caller always truncates
+    /// the conflicting entries before, so the term is the new last entry.
+    fn insert(&mut self, term: &Term) -> io::Result<()>;
+    /// Remove all entries from `index` included.
+    fn truncate_from(&mut self, index: usize) -> io::Result<()>;
+    /// Find the entry at `index`
+    fn find(&self, index: usize) -> Option<Term>;
+    /// Entries in the store between `from` and `to` included.
+    fn range(&self, from: usize, to: usize) -> Vec<Term>;
+    fn commit_index(&self) -> usize;
+    fn set_commit_index(&mut self, index: usize) -> io::Result<()>;
+    /// Index of the last entry, at least the commit index
+    fn last_index(&self) -> usize;
+    /// Drop all entries up to `last_included.index` included, they are now
+    /// in a snapshot.
+    fn compact(&mut self, last_included: &Term) -> io::Result<()>;
+    /// Last term included in the latest snapshot if the log has been
+    /// compacted.
+    fn compacted(&self) -> Option<Term>;
+}
+
+/// Volatile store, everything is lost on restart.
+#[derive(Debug, Default)]
+pub struct MemoryLogStore {
+    inner: BTreeMap<usize, Term>,
+    latest: usize,
+    commit_index: usize,
+    compacted: Option<Term>,
+}
+
+impl LogStore for MemoryLogStore {
+    fn insert(&mut self, term: &Term) -> io::Result<()> {
+        self.latest = term.index;
+        self.inner.insert(term.index, term.clone());
+        Ok(())
+    }
+
+    fn truncate_from(&mut self, index: usize) -> io::Result<()> {
+        self.inner.split_off(&index);
+        self.latest = self.latest.min(index.saturating_sub(1));
+        Ok(())
+    }
+
+    fn find(&self, index: usize) -> Option<Term> {
+        self.inner.get(&index).cloned()
+    }
+
+    fn range(&self, from: usize, to: usize) -> Vec<Term> {
+        if from > to {
+            return vec![];
+        }
+        self.inner
+            .range(from..=to)
+            .map(|(_, t)| t.clone())
+            .collect()
+    }
+
+    fn commit_index(&self) -> usize {
+        self.commit_index
+    }
+
+    fn set_commit_index(&mut self, index: usize) -> io::Result<()> {
+        self.commit_index = index;
+        Ok(())
+    }
+
+    fn last_index(&self) -> usize {
+        self.latest.max(self.commit_index)
+    }
+
+    fn compact(&mut self, last_included: &Term) -> io::Result<()> {
+        self.inner = self.inner.split_off(&(last_included.index + 1));
+        self.latest = self.latest.max(last_included.index);
+        self.commit_index = self.commit_index.max(last_included.index);
+        self.compacted = Some(last_included.clone());
+        Ok(())
+    }
+
+    fn compacted(&self) -> Option<Term> {
+        self.compacted.clone()
+    }
+}
@@ -0,0 +1,192 @@
+// License:
+// This source code is licensed under the GPLv3 license, you can found the
+// LICENSE file in the root directory of this source tree.
+
+//! Durable storage of the node. Raft requires a node to remember, across
+//! restarts, the current term, who it voted for and how far the log has been
+//! acknowledged. Otherwise a restarted node can vote twice in the same
+//! election.
+//!
+//! The state is written in `data_dir/hard_state.json` (see the settings) and
+//! reloaded when the node initialize. When `data_dir` isn't set the storage is
+//! a no-op and the node behaves as a pure in-memory node.
+//!
+//! The log entries are stored in a [LogStore], by default a [FileLogStore]
+//! in `data_dir/wal` if `data_dir` is set, a [MemoryLogStore] otherwise.
+//!
+//! The latest snapshot of the state machine is written in
+//! `data_dir/snapshot`, the log is compacted up to the snapshot.
+
+use crate::{
+    common::{
+        config::Settings,
+        error::{throw, Error, ErrorResult},
+    },
+    log_entry::Term,
+    membership::Configuration,
+};
+use serde::{Deserialize, Serialize};
+use std::{
+    fs::{self, File},
+    io::Write,
+    path::{Path, PathBuf},
+};
+
+mod log_store;
+mod node;
+mod wal;
+
+pub use log_store::{LogStore, MemoryLogStore};
+pub use wal::FileLogStore;
+
+const HARD_STATE_FILE: &str = "hard_state.json";
+const WAL_DIR: &str = "wal";
+const SNAPSHOT_FILE: &str = "snapshot";
+
+/// State that has to be on stable storage before the node responds to RPCs.
+#[derive(Debug, Default, Clone, Deserialize, Serialize)]
+pub struct HardState {
+    /// Latest election term seen by the node
+    pub current_term: usize,
+    /// Vote given in the current term, same as `Node::election`
+    pub vote_for: Option<String>,
+    /// Latest committed index
+    pub commit_index: usize,
+    /// Latest committed configuration with the index of its entry
+    #[serde(default)]
+    pub configuration: Option<(usize, Configuration)>,
+}
+
+/// Snapshot of the state machine produced by the hook, with the last term it
+/// includes.
+#[derive(Debug, Clone)]
+pub struct Snapshot {
+    pub last_included: Term,
+    pub data: Vec<u8>,
+}
+
+/// Read and write the `HardState` and the latest `Snapshot` in the data
+/// directory.
+#[derive(Debug, Default)]
+pub struct HardStateStorage {
+    dir: Option<PathBuf>,
+}
+
+impl HardStateStorage {
+    pub fn new(settings: &Settings) -> Self {
+        Self {
+            dir: settings.data_dir.as_ref().map(PathBuf::from),
+        }
+    }
+
+    /// Load the previous state if any. Return `None` on a fresh start.
+    pub fn load(&self) -> ErrorResult<Option<HardState>> {
+        let path = match &self.dir {
+            Some(dir) => dir.join(HARD_STATE_FILE),
+            None => return Ok(None),
+        };
+        if !path.exists() {
+            return Ok(None);
+        }
+        let content = match fs::read(&path) {
+            Ok(content) => content,
+            Err(err) => throw!(Error::CannotLoadState(format!("{:?}: {}", path, err))),
+        };
+        match serde_json::from_slice(&content) {
+            Ok(state) => Ok(Some(state)),
+            Err(err) => throw!(Error::CannotLoadState(format!("{:?}: {}", path, err))),
+        }
+    }
+
+    /// Load the latest snapshot if any. The file is framed as
+    /// `[length of the term: u32 LE][last included term json][data]`
+    pub fn load_snapshot(&self) -> ErrorResult<Option<Snapshot>> {
+        let path = match &self.dir {
+            Some(dir) => dir.join(SNAPSHOT_FILE),
+            None => return Ok(None),
+        };
+        if !path.exists() {
+            return Ok(None);
+        }
+        let content = match fs::read(&path) {
+            Ok(content) => content,
+            Err(err) => throw!(Error::CannotLoadState(format!("{:?}: {}", path, err))),
+        };
+        match parse_snapshot(&content) {
+            Some(snapshot) => Ok(Some(snapshot)),
+            None => throw!(Error::CannotLoadState(format!("{:?}: corrupted", path))),
+        }
+    }
+
+    /// Write the snapshot on disk, see `load_snapshot` for the format.
+    pub fn save_snapshot(&self, snapshot: &Snapshot) -> ErrorResult<()> {
+        let dir = match &self.dir {
+            Some(dir) => dir,
+            None => return Ok(()),
+        };
+        let term = match serde_json::to_vec(&snapshot.last_included) {
+            Ok(term) => term,
+            Err(err) => throw!(Error::CannotPersistState(err.to_string())),
+        };
+        let mut content = (term.len() as u32).to_le_bytes().to_vec();
+        content.extend(term);
+        content.extend(&snapshot.data);
+        if let Err(err) = write_synced(dir, SNAPSHOT_FILE, &content) {
+            throw!(Error::CannotPersistState(format!("{:?}: {}", dir, err)))
+        }
+        Ok(())
+    }
+
+    /// Write the state on disk.
+    pub fn save(&self, state: &HardState) -> ErrorResult<()> {
+        let dir = match &self.dir {
+            Some(dir) => dir,
+            None => return Ok(()),
+        };
+        let content = match serde_json::to_vec(state) {
+            Ok(content) => content,
+            Err(err) => throw!(Error::CannotPersistState(err.to_string())),
+        };
+        if let Err(err) = write_synced(dir, HARD_STATE_FILE, &content) {
+            throw!(Error::CannotPersistState(format!("{:?}: {}", dir, err)))
+        }
+        Ok(())
+    }
+}
+
+/// Open the log store described in the settings.
+///
+/// # Panic
+/// Panic if the write-ahead log in `data_dir` can't be opened, a node
+/// shouldn't start without its log.
+pub(crate) fn open_log_store(settings: &Settings) -> Box<dyn LogStore> {
+    match &settings.data_dir {
+        Some(dir) => Box::new(
+            FileLogStore::open(PathBuf::from(dir).join(WAL_DIR))
+                .expect("unable to open the write-ahead log"),
+        ),
+        None => Box::new(MemoryLogStore::default()),
+    }
+}
+
+fn parse_snapshot(content: &[u8]) -> Option<Snapshot> {
+    let len = u32::from_le_bytes(content.get(..4)?.try_into().ok()?) as usize;
+    let last_included = serde_json::from_slice(content.get(4..4 + len)?).ok()?;
+    Some(Snapshot {
+        last_included,
+        data: content.get(4 + len..)?.to_vec(),
+    })
+}
+
+/// Write `content` in `dir/name`. The content is written in a temporary file,
+/// synced, and then renamed so a crash never leaves a partial file.
+fn write_synced(dir: &Path, name: &str, content: &[u8]) -> std::io::Result<()> {
+    fs::create_dir_all(dir)?;
+    let tmp = dir.join(format!("{}.tmp", name));
+    let mut file = File::create(&tmp)?;
+    file.write_all(content)?;
+    file.sync_all()?;
+    fs::rename(&tmp, dir.join(name))?;
+    // Sync the directory so the rename is durable too.
+    File::open(dir)?.sync_all()
+}
@@ -0,0 +1,93 @@
+// License:
+// This source code is licensed under the GPLv3 license, you can found the
+// LICENSE file in the root directory of this source tree.
+use super::HardState;
+use crate::{
+    common::error::{throw, Error, ErrorResult},
+    membership::Membership,
+    node::Election,
+    Node,
+};
+use tracing::trace;
+
+impl Node {
+    /// Reload the hard state and the snapshot saved by a previous run of the
+    /// node, if any. The configurations are tracked again from the latest
+    /// committed one.
+    ///
+    /// The hook restores the snapshot, then the committed entries that
+    /// follow it are committed again, the state machine of the hook is
+    /// where the node stopped.
+    ///
+    /// # Error
+    /// `HookFailure` if the hook fails to restore the snapshot or to commit
+    /// an entry again.
+    pub(crate) async fn load_hard_state(&self) -> ErrorResult<()> {
+        let snapshot = self.storage.load_snapshot()?;
+        let state = self.storage.load()?;
+        if let Some(state) = &state {
+            *self.election.write().await = Election {
+                current_term: state.current_term,
+                vote_for: state.vote_for.clone(),
+            };
+        }
+        let _committing = self.committing.lock().await;
+        let (entries, changes) = {
+            let mut logs = self.logs.lock().await;
+            if let Some(state) = state {
+                trace!(
+                    "restore state, term {} commit index {}",
+                    state.current_term,
+                    state.commit_index
+                );
+                logs.restore(state.commit_index)?;
+                if let Some((index, configuration)) = state.configuration {
+                    *self.membership.write().await = Membership::new(index, configuration);
+                }
+            }
+            let from = self.membership.read().await.committed_index + 1;
+            let changes = self.track_configurations(&logs, from).await;
+            let applied = snapshot.as_ref().map_or(0, |s| s.last_included.index);
+            (logs.range(applied + 1, logs.commit_index()), changes)
+        };
+        self.notify_members(changes).await;
+
+        if let Some(snapshot) = &snapshot {
+            trace!("restore snapshot up to {}", snapshot.last_included.index);
+            let index = snapshot.last_included.index;
+            if let Err(err) = self.hook.restore_snapshot(index, &snapshot.data).await {
+                self.hook_failed("restore_snapshot", &err);
+                throw!(Error::HookFailure(err))
+            }
+            self.applied.send_replace(index);
+        }
+        for term in entries {
+            trace!("commit term {} again", term.index);
+            if let Err(err) = self.hook.commit_term(&term).await {
+                self.hook_failed("commit_term", &err);
+                throw!(Error::HookFailure(err))
+            }
+            self.applied.send_replace(term.index);
+        }
+        *self.snapshot.write().await = snapshot;
+        Ok(())
+    }
+
+    /// Persist the hard state with the given `election` state. Must be
+    /// called before replying to a vote request or to an append term
+    /// request.
+    ///
+    /// The election state is given by the caller because it usually holds
+    /// the `election` lock.
+    pub(crate) async fn persist_hard_state(&self, election: &Election) -> ErrorResult<()> {
+        let commit_index = self.logs.lock().await.commit_index();
+        let membership = self.membership.read().await;
+        let state = HardState {
+            current_term: election.current_term,
+            vote_for: election.vote_for.clone(),
+            commit_index,
+            configuration: Some((membership.committed_index, membership.committed.clone())),
+        };
+        self.storage.save(&state)
+    }
+}
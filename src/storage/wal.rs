@@ -0,0 +1,306 @@
+// License:
+// This source code is licensed under the GPLv3 license, you can found the
+// LICENSE file in the root directory of this source tree.
+
+//! File backed [LogStore] implemented as a segmented write-ahead log.
+//!
+//! Every modification of the store is appended as a record in the active
+//! segment and synced before the call returns. A segment is a file named
+//! `{sequence}.wal`, a new one is created when the active segment is bigger
+//! than the segment size.
+//!
+//! A record is framed as `[payload length: u32 LE][crc32: u32 LE][payload]`
+//! where the payload is a JSON serialized [Record]. On open, the segments are
+//! replayed in order to rebuild the store. A torn record at the end of the
+//! last segment (crash during a write) is dropped and the file truncated.
+//!
+//! On compaction, the remaining entries are rewritten in a new segment and
+//! the previous segments are deleted.
+
+use super::LogStore;
+use crate::log_entry::Term;
+use serde::{Deserialize, Serialize};
+use std::{
+    collections::BTreeMap,
+    fs::{self, File, OpenOptions},
+    io::{self, Write},
+    path::{Path, PathBuf},
+};
+use tracing::{debug, warn};
+
+const DEFAULT_SEGMENT_SIZE: u64 = 4 * 1024 * 1024;
+const HEADER_SIZE: usize = 8;
+const SEGMENT_EXTENSION: &str = "wal";
+
+#[derive(Debug, Deserialize, Serialize)]
+enum Record {
+    Insert(Term),
+    Truncate(usize),
+    Commit(usize),
+    Compact(Term),
+}
+
+pub struct FileLogStore {
+    dir: PathBuf,
+    segment_size: u64,
+    /// Sequence number of the active segment
+    sequence: u64,
+    /// Active segment, opened in append mode
+    file: File,
+    /// Size of the active segment
+    written: u64,
+    inner: BTreeMap<usize, Term>,
+    latest: usize,
+    commit_index: usize,
+    compacted: Option<Term>,
+}
+
+impl FileLogStore {
+    /// Open the write-ahead log in `dir`, replay the existing segments or
+    /// create the first one.
+    pub fn open<P: AsRef<Path>>(dir: P) -> io::Result<Self> {
+        let dir = dir.as_ref().to_path_buf();
+        fs::create_dir_all(&dir)?;
+        let sequences = list_segments(&dir)?;
+        let mut store = FileLogStore {
+            file: open_segment(&dir, sequences.last().copied().unwrap_or(0))?,
+            sequence: sequences.last().copied().unwrap_or(0),
+            dir,
+            segment_size: DEFAULT_SEGMENT_SIZE,
+            written: 0,
+            inner: BTreeMap::new(),
+            latest: 0,
+            commit_index: 0,
+            compacted: None,
+        };
+        for (i, sequence) in sequences.iter().enumerate() {
+            let is_last = i + 1 == sequences.len();
+            store.replay(*sequence, is_last)?;
+        }
+        sync_dir(&store.dir)?;
+        debug!(
+            "wal opened with {} segments, last index {} commit index {}",
+            sequences.len(),
+            store.latest,
+            store.commit_index
+        );
+        Ok(store)
+    }
+
+    /// Set the size in bytes from which a new segment is created.
+    pub fn with_segment_size(mut self, segment_size: u64) -> Self {
+        self.segment_size = segment_size;
+        self
+    }
+
+    fn replay(&mut self, sequence: u64, is_last: bool) -> io::Result<()> {
+        let path = segment_path(&self.dir, sequence);
+        let bytes = fs::read(&path)?;
+        let mut pos = 0;
+        while pos < bytes.len() {
+            match read_record(&bytes[pos..]) {
+                Some((record, len)) => {
+                    self.apply(record);
+                    pos += len;
+                }
+                None if is_last => {
+                    warn!("drop a torn record at the end of {:?}", path);
+                    let file = OpenOptions::new().write(true).open(&path)?;
+                    file.set_len(pos as u64)?;
+                    file.sync_all()?;
+                    break;
+                }
+                None => {
+                    return Err(io::Error::new(
+                        io::ErrorKind::InvalidData,
+                        format!("corrupted record in {:?} at {}", path, pos),
+                    ))
+                }
+            }
+        }
+        if is_last {
+            self.written = pos as u64;
+        }
+        Ok(())
+    }
+
+    fn apply(&mut self, record: Record) {
+        match record {
+            Record::Insert(term) => {
+                self.latest = term.index;
+                self.inner.insert(term.index, term);
+            }
+            Record::Truncate(index) => {
+                self.inner.split_off(&index);
+                self.latest = self.latest.min(index.saturating_sub(1));
+            }
+            Record::Commit(index) => self.commit_index = index,
+            Record::Compact(last_included) => {
+                self.inner = self.inner.split_off(&(last_included.index + 1));
+                self.latest = self.latest.max(last_included.index);
+                self.commit_index = self.commit_index.max(last_included.index);
+                self.compacted = Some(last_included);
+            }
+        }
+    }
+
+    /// Write a record in the active segment and sync it.
+    fn write(&mut self, record: &Record) -> io::Result<()> {
+        let buf = frame(record)?;
+        if self.written > 0 && self.written + buf.len() as u64 > self.segment_size {
+            self.rotate()?;
+        }
+        self.file.write_all(&buf)?;
+        self.file.sync_data()?;
+        self.written += buf.len() as u64;
+        Ok(())
+    }
+
+    fn rotate(&mut self) -> io::Result<()> {
+        self.sequence += 1;
+        self.file = open_segment(&self.dir, self.sequence)?;
+        self.written = 0;
+        sync_dir(&self.dir)
+    }
+}
+
+/// Frame a record, see the module documentation.
+fn frame(record: &Record) -> io::Result<Vec<u8>> {
+    let payload = serde_json::to_vec(record)?;
+    let mut buf = Vec::with_capacity(HEADER_SIZE + payload.len());
+    buf.extend_from_slice(&(payload.len() as u32).to_le_bytes());
+    buf.extend_from_slice(&crc32fast::hash(&payload).to_le_bytes());
+    buf.extend_from_slice(&payload);
+    Ok(buf)
+}
+
+impl LogStore for FileLogStore {
+    fn insert(&mut self, term: &Term) -> io::Result<()> {
+        self.write(&Record::Insert(term.clone()))?;
+        self.apply(Record::Insert(term.clone()));
+        Ok(())
+    }
+
+    fn truncate_from(&mut self, index: usize) -> io::Result<()> {
+        if index > self.latest {
+            return Ok(());
+        }
+        self.write(&Record::Truncate(index))?;
+        self.apply(Record::Truncate(index));
+        Ok(())
+    }
+
+    fn find(&self, index: usize) -> Option<Term> {
+        self.inner.get(&index).cloned()
+    }
+
+    fn range(&self, from: usize, to: usize) -> Vec<Term> {
+        if from > to {
+            return vec![];
+        }
+        self.inner
+            .range(from..=to)
+            .map(|(_, t)| t.clone())
+            .collect()
+    }
+
+    fn commit_index(&self) -> usize {
+        self.commit_index
+    }
+
+    fn set_commit_index(&mut self, index: usize) -> io::Result<()> {
+        self.write(&Record::Commit(index))?;
+        self.apply(Record::Commit(index));
+        Ok(())
+    }
+
+    fn last_index(&self) -> usize {
+        self.latest.max(self.commit_index)
+    }
+
+    /// Rewrite the state after the compaction in a new segment. The segment
+    /// is written in a temporary file and renamed, so a crash leaves either
+    /// the old segments or the new one complete. The memory is updated once
+    /// the new segment is durable, older segments are deleted after.
+    fn compact(&mut self, last_included: &Term) -> io::Result<()> {
+        let commit_index = self.commit_index.max(last_included.index);
+        let mut buf = frame(&Record::Compact(last_included.clone()))?;
+        buf.extend(frame(&Record::Commit(commit_index))?);
+        for term in self.inner.range(last_included.index + 1..).map(|(_, t)| t) {
+            buf.extend(frame(&Record::Insert(term.clone()))?);
+        }
+
+        let previous = list_segments(&self.dir)?;
+        let sequence = self.sequence + 1;
+        let path = segment_path(&self.dir, sequence);
+        let tmp = path.with_extension("tmp");
+        let mut file = File::create(&tmp)?;
+        file.write_all(&buf)?;
+        file.sync_all()?;
+        fs::rename(&tmp, &path)?;
+        sync_dir(&self.dir)?;
+        self.file = open_segment(&self.dir, sequence)?;
+        self.sequence = sequence;
+        self.written = buf.len() as u64;
+        self.apply(Record::Compact(last_included.clone()));
+        for sequence in previous {
+            fs::remove_file(segment_path(&self.dir, sequence))?;
+        }
+        sync_dir(&self.dir)
+    }
+
+    fn compacted(&self) -> Option<Term> {
+        self.compacted.clone()
+    }
+}
+
+/// Parse the record at the beginning of `bytes`, return the record and its
+/// framed length. `None` if the record is incomplete or corrupted.
+fn read_record(bytes: &[u8]) -> Option<(Record, usize)> {
+    if bytes.len() < HEADER_SIZE {
+        return None;
+    }
+    let len = u32::from_le_bytes(bytes[0..4].try_into().ok()?) as usize;
+    let crc = u32::from_le_bytes(bytes[4..8].try_into().ok()?);
+    let payload = bytes.get(HEADER_SIZE..HEADER_SIZE + len)?;
+    if crc32fast::hash(payload) != crc {
+        return None;
+    }
+    let record = serde_json::from_slice(payload).ok()?;
+    Some((record, HEADER_SIZE + len))
+}
+
+fn segment_path(dir: &Path, sequence: u64) -> PathBuf {
+    dir.join(format!("{:020}.{}", sequence, SEGMENT_EXTENSION))
+}
+
+fn open_segment(dir: &Path, sequence: u64) -> io::Result<File> {
+    OpenOptions::new()
+        .create(true)
+        .append(true)
+        .open(segment_path(dir, sequence))
+}
+
+/// Sorted sequence numbers of the segments in `dir`
+fn list_segments(dir: &Path) -> io::Result<Vec<u64>> {
+    let mut sequences = vec![];
+    for entry in fs::read_dir(dir)?.flatten() {
+        let path = entry.path();
+        if path.extension().and_then(|e| e.to_str()) != Some(SEGMENT_EXTENSION) {
+            continue;
+        }
+        if let Some(sequence) = path
+            .file_stem()
+            .and_then(|s| s.to_str())
+            .and_then(|s| s.parse().ok())
+        {
+            sequences.push(sequence);
+        }
+    }
+    sequences.sort_unstable();
+    Ok(sequences)
+}
+
+fn sync_dir(dir: &Path) -> io::Result<()> {
+    File::open(dir)?.sync_all()
+}
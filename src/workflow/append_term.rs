@@ -10,9 +10,11 @@
 use crate::{
     api::io_msg::{AppendTermInput, AppendTermResult},
     common::error::ErrorResult,
+    log_entry::{Entries, Term},
     node::Node,
 };
-use tracing::{debug, trace, trace_span, warn};
+use tokio::time::Instant;
+use tracing::{debug, trace, trace_span};
 
 macro_rules! log {
     ($($rest:tt)*) => {
@@ -26,11 +28,12 @@ macro_rules! log {
 impl Node {
     /// Reception of a append_term request.
     ///
-    /// - check inputs, if it's enough updated, if we have the previous term.
-    /// - increment the heartbeat timeout
+    /// - check the election term of the leader, update the local one
+    /// - increment the heartbeat timeout and follow the leader
+    /// - check if we have the previous term
     /// - call hook pre_append_term
-    /// - set status to follower if input term > local term
-    /// - update local log entries, commit if commit index updated
+    /// - update local log entries, commit in the background if commit index
+    ///   updated
     pub async fn receive_append_term(
         &self,
         input: AppendTermInput,
@@ -38,9 +41,11 @@ impl Node {
         let span = trace_span!("receive_append_term");
         let _enter = span.enter();
         trace!(
-            "received new term {}: '{}'",
-            input.term.id,
-            input.term.content,
+            "received {} entries after {} from {} in term {}",
+            input.entries.len(),
+            input.prev_log_index,
+            input.leader_id,
+            input.term,
         );
         debug!("received new term {:#?}", input,);
         self.internal_receive_append_term(input).await
@@ -51,197 +56,177 @@ impl Node {
     /// Specification
     /// 1. Reply false if term < currentTerm (§5.1)
     /// 2. Reply false if log doesn’t contain an entry at prevLogIndex
-    /// whose term matches prevLogTerm (§5.3)
+    ///    whose term matches prevLogTerm (§5.3)
     /// 3. If an existing entry conflicts with a new one (same index
-    /// but different terms), delete the existing entry and all that
-    /// follow it (§5.3)
+    ///    but different terms), delete the existing entry and all that
+    ///    follow it (§5.3)
     /// 4. Append any new entries not already in the log
     /// 5. If leaderCommit > commitIndex, set commitIndex =
-    /// min(leaderCommit, index of last new entry
+    ///    min(leaderCommit, index of last new entry
     async fn internal_receive_append_term(
         &self,
         mut input: AppendTermInput,
     ) -> ErrorResult<AppendTermResult> {
-        input.entries.sort_by_key(|e| e.id);
-
-        let checks = self.check_input(&input).await;
-        if let Err(res) = checks {
-            log!("request rejected by checks");
-            return Ok(res);
-        }
-        self.reset_timeout().await;
-
-        if input.prev_term.id == 1 {
-            trace!("pre/append root term");
-            if let Some(index) = self.hook.pre_append_term(&input.prev_term) {
-                if index < input.prev_term.id {
-                    log!("root term rejected by checks pre append term");
-                    return Ok(AppendTermResult {
-                        current_term: self.logs.lock().await.current_term(),
-                        success: false,
-                    });
-                }
-                self.logs.lock().await.insert(&input.prev_term);
-                self.hook.append_term(&input.prev_term);
-            } else {
-                panic!("request rejected in pre append term");
+        input.entries.sort_by_key(|e| e.index);
+
+        let (current_term, events) = {
+            let mut election = self.election.write().await;
+            if input.term < election.current_term {
+                log!("term older than local state");
+                let logs = self.logs.lock().await;
+                return Ok(result(election.current_term, false, &logs));
             }
-        } else {
-            // Pre/Append entries (from prev term to term excluded)
-            if !input.entries.is_empty() {
-                for term in &input.entries {
-                    if term.id <= self.logs.lock().await.commit_index() {
-                        // ignore committed term
-                        continue;
-                    }
-                    // pre append term send the last index I don't have
-                    // (the first missing term).
-                    //
-                    // - if term from input == last I don't have => OK
-                    // - return that index - 1 (the last I have / current term) otherwise
-                    if let Some(index) = self.hook.pre_append_term(term) {
-                        if index < term.id {
-                            trace!(
-                                "term (entries) {} rejected by checks pre append term",
-                                index
-                            );
-                            return Ok(AppendTermResult {
-                                current_term: self.logs.lock().await.current_term(),
-                                success: false,
-                            });
-                        }
-                        self.logs.lock().await.insert(term);
-                        self.hook.append_term(term);
-                    } else {
-                        // todo: throw an internal error
-                        panic!("request rejected in pre append term");
-                    }
-                }
+            if election.update_term(input.term) {
+                self.persist_hard_state(&election).await?;
             }
+            *self.leader_contact.lock().unwrap() = Some(Instant::now());
+            // Follow the leader before releasing the election state, nobody
+            // sees the node leading in a term it doesn't own
+            let events = self
+                .switch_to_follower(input.leader_id.clone().into())
+                .await
+                .unwrap_or_default();
+            (election.current_term, events)
+        };
+        self.notify_status(events).await;
+        self.reset_timeout().await;
 
-            if let Some(index) = self.hook.pre_append_term(&input.term) {
-                if index < input.term.id {
+        // The entries that aren't in the logs yet, the hook checks them
+        // without holding the logs
+        let new_entries: Vec<&Term> = {
+            let logs = self.logs.lock().await;
+            if let Err(res) = check_input(&input, current_term, &logs) {
+                log!("request rejected by checks");
+                return Ok(res);
+            }
+            input
+                .entries
+                .iter()
+                // ignore the committed terms and the terms already in the log
+                .filter(|term| {
+                    term.index > logs.commit_index() && logs.term_at(term.index) != Some(term.term)
+                })
+                .collect()
+        };
+
+        let mut accepted = 0;
+        for term in new_entries.iter() {
+            // pre append term send the last index I don't have
+            // (the first missing term).
+            //
+            // - if term from input == last I don't have => OK
+            // - return that index - 1 (the last I have / current term) otherwise
+            match self.hook.pre_append_term(term).await {
+                Ok(index) if index < term.index => {
                     log!("term {} rejected by checks pre append term", index);
-                    return Ok(AppendTermResult {
-                        current_term: self.logs.lock().await.current_term(),
-                        success: false,
-                    });
+                    break;
+                }
+                Ok(_) => {}
+                Err(err) => {
+                    self.hook_failed("pre_append_term", &err);
+                    break;
                 }
-                self.logs.lock().await.insert(&input.term);
-                self.hook.append_term(&input.term);
-            } else {
-                panic!("request rejected in pre append term");
             }
+            // The leader sends the term again if the hook fails
+            if let Err(err) = self.hook.append_term(term).await {
+                self.hook_failed("append_term", &err);
+                break;
+            }
+            accepted += 1;
+        }
+        let rejected = accepted < new_entries.len();
+
+        // The logs may have changed meanwhile, a newer leader may have
+        // replaced the entries of that one
+        let election = self.election.read().await;
+        let mut logs = self.logs.lock().await;
+        if election.current_term != current_term {
+            log!("term changed while appending the entries");
+            return Ok(result(election.current_term, false, &logs));
+        }
+        if let Err(res) = check_input(&input, current_term, &logs) {
+            log!("request rejected by checks");
+            return Ok(res);
+        }
+        std::mem::drop(election);
+        let mut first_inserted = None;
+        for term in new_entries.into_iter().take(accepted) {
+            if term.index <= logs.commit_index() || logs.term_at(term.index) == Some(term.term) {
+                continue;
+            }
+            // Insert truncates the conflicting entries that follow
+            logs.insert(term)?;
+            first_inserted.get_or_insert(term.index);
+        }
+        let changes = match first_inserted {
+            // The configurations of the removed entries aren't used anymore
+            Some(index) => self.track_configurations(&logs, index).await,
+            None => vec![],
+        };
+        let rejected = rejected.then(|| result(current_term, false, &logs));
+        std::mem::drop(logs);
+        self.notify_members(changes).await;
+        if let Some(res) = rejected {
+            return Ok(res);
         }
-        log!("request {} has passed checks", input.term.id);
+        let last_new_index = input
+            .entries
+            .last()
+            .map_or(input.prev_log_index, |term| term.index);
+        log!("request up to {} has passed checks", last_new_index);
 
         // Finally commit the entries up to leader_commit_index,
-        // stopping at the latest entry we have in cache
-        self.commit_entries(input.leader_commit_index).await;
+        // stopping at the latest entry of the request. The leader doesn't
+        // wait for the hook to apply them.
+        self.commit_in_background(input.leader_commit_index.min(last_new_index));
 
-        // Since leader care about that the last term (term in the input)
-        // is the biggest term we should know at the end of AppendEntries.
-        // We can be almost sure that current term IS the input term here.
-        let current_term = self.logs.lock().await.current_term();
-        let current_term_id = current_term.id;
+        // What we acknowledge to the leader has to survive a restart.
+        self.persist_hard_state(&*self.election.read().await)
+            .await?;
 
-        trace!("append term success. latest term: {:#?}", current_term);
-        Ok(AppendTermResult {
-            current_term,
-            success: current_term_id <= input.term.id,
-        })
+        let logs = self.logs.lock().await;
+        trace!("append term success. latest index: {}", logs.last_index());
+        Ok(result(current_term, true, &logs))
     }
+}
 
-    /// Check if the current term is at least equals to the term local.
-    ///
-    /// Also send an error and inform the leader about his last current_term
-    /// to adapt his own `next_indexes` table and ensure the logs consistency
-    /// in the next call.
-    async fn check_input(&self, input: &AppendTermInput) -> Result<(), AppendTermResult> {
-        let mut logs_guard = self.logs.lock().await;
-        let current_term = logs_guard.current_term();
-        if input.term.id < current_term.id {
-            log!("term id older than local state");
-            return Err(AppendTermResult {
-                current_term,
-                success: false,
-            });
-        }
-
-        if input.leader_commit_index < logs_guard.commit_index() {
-            log!("leader commit index invalid");
-            return Err(AppendTermResult {
-                current_term,
-                success: false,
-            });
-        }
-
-        // We need the message to have all the entries between `term`
-        // and `prev_term`.
-        if input.prev_term.id > input.term.id {
-            return Err(AppendTermResult {
-                current_term,
-                success: false,
-            });
-        }
-        if input.term.id == input.prev_term.id && input.entries.is_empty()
-            || input.entries.len() == input.term.id - input.prev_term.id - 1
-        {
-            // Input entries contains a correct number of terms. Now
-            // we check if terms ids are monotones.
-            let ids = input.prev_term.id + 1..=input.term.id;
-            for (e, expected_id) in input.entries.iter().zip(ids) {
-                if e.id != expected_id {
-                    log!("entry missing, jump from {} to {}", e.id, expected_id);
-                    return Err(AppendTermResult {
-                        current_term,
-                        success: false,
-                    });
-                }
-            }
-        } else {
+/// Check if the log contains an entry at `prev_log_index` whose term
+/// matches `prev_log_term`.
+///
+/// Also send an error and inform the leader about our last log entry to
+/// adapt his own `next_indexes` table and ensure the logs consistency in the
+/// next call.
+fn check_input(
+    input: &AppendTermInput,
+    current_term: usize,
+    logs: &Entries,
+) -> Result<(), AppendTermResult> {
+    if input.prev_log_index <= logs.commit_index() {
+        // committed entries are the same in every log
+        return Ok(());
+    }
+    match logs.term_at(input.prev_log_index) {
+        Some(term) if term == input.prev_log_term => Ok(()),
+        Some(term) => {
             log!(
-                "entries received are len {}, and we expected {} - {} = {}",
-                input.entries.len(),
-                input.term.id,
-                input.prev_term.id,
-                input.term.id - input.prev_term.id
+                "previous term {} conflicts with local term {}",
+                input.prev_log_term,
+                term
             );
-            return Err(AppendTermResult {
-                current_term,
-                success: false,
-            });
+            Err(result(current_term, false, logs))
         }
-
-        // 2. Reply false if log doesn’t contain an entry at prevLogIndex
-        // whose term matches prevLogTerm (§5.3)
-        if let Some(local_term) = logs_guard.find(input.prev_term.id) {
-            // found in cache
-            if local_term != input.prev_term {
-                // 3. If an existing entry conflicts with a new one (same index
-                // but different terms), delete the existing entry and all that
-                // follow it (§5.3)
-                logs_guard.insert(&input.prev_term);
-                self.hook.append_term(&input.prev_term);
-            }
-        } else if input.prev_term.id <= logs_guard.commit_index() {
-            // has been committed
-            // todo: add a hook here like "check terms validity" to verify
-            // if it match correctly with local terms
-        } else if input.prev_term.id == 1 {
-            // its also OK to receive a root term once.
-            // todo: accept once
-        } else {
-            warn!("unable to find the previous term");
-            return Err(AppendTermResult {
-                current_term,
-                success: false,
-            });
+        None => {
+            log!("unable to find the previous term {}", input.prev_log_index);
+            Err(result(current_term, false, logs))
         }
+    }
+}
 
-        let _ = self
-            .switch_to_follower(input.leader_id.clone().into())
-            .await;
-        Ok(())
+fn result(current_term: usize, success: bool, logs: &Entries) -> AppendTermResult {
+    AppendTermResult {
+        current_term,
+        success,
+        last_log_index: logs.last_index(),
+        last_log_term: logs.last_term(),
     }
 }
@@ -6,11 +6,16 @@
 //! candidature workflow.
 //!
 //! The file contain the candidature implementation. When a node stop to be a
-//! follower, he increment his own logs and send `request_votes` request to
-//! the other potential candidates in the network.
+//! follower, he first sends `pre_vote` requests to know if a majority would
+//! vote for him. A node isolated from the network never gets them, so it
+//! doesn't increment its term and doesn't disrupt the cluster when it comes
+//! back. A node asked by the leader to take its lead skips the pre vote
+//! round, see [crate::workflow::transfer]. Then he increments his election term, votes for himself and send
+//! `request_votes` request to the other potential candidates in the network.
 //!
 //! - if majority reached, start to be a leader
 //! - if alone in the network, start to be a leader
+//! - if a node answers with a newer term, turn into a follower
 //!
 //! When the candidature process finish, if the node is still a candidate, wait
 //! a random time and restart a candidature.
@@ -22,19 +27,17 @@
 //! [crate::workflow::append_term], at the end of the candidature, start the
 //! follower workflow [crate::workflow::follower].
 
-#[cfg(not(test))]
-use crate::api::client;
 use crate::{
-    api::io_msg::RequestVoteInput,
+    api::io_msg::{RequestVoteInput, RequestVoteResult},
     common::{error::ErrorResult, Url},
-    log_entry::LogEntry,
     node::Node,
-    Settings,
 };
+use std::{collections::BTreeSet, sync::atomic::Ordering};
 use tracing::{debug, trace, warn};
 
 impl Node {
-    /// - On conversion to candidate, start election:
+    /// - On conversion to candidate, check that a majority would grant us
+    ///   its vote with a pre vote round, then start election:
     /// - Increment currentTerm
     /// - Vote for self
     /// - Reset election timer
@@ -44,121 +47,157 @@ impl Node {
     ///   follower
     /// - If election timeout elapses: start new election
     pub async fn run_candidate(&self) -> ErrorResult<()> {
-        let (commit_index, mut last_term) = {
-            let mut logs = self.logs.lock().await;
-            let commit_index = logs.last_index(); // todo: wrong names
-            if let Some((_, id)) = &*self.vote_for.read().await {
-                if commit_index <= *id {
-                    return Ok(());
+        while self.p_status.is_candidate().await {
+            if !self.is_voter().await {
+                trace!("not a voter of the cluster, stop the candidature");
+                let events = self.step_down().await?;
+                self.notify_status(events).await;
+                break;
+            }
+            let forced = self.forced_election.swap(false, Ordering::SeqCst);
+            if forced {
+                trace!("election forced by the leader, skip the pre vote");
+            }
+            if !forced && !self.request_pre_votes().await? {
+                trace!("pre vote refused");
+            } else {
+                let term = self.start_election().await?;
+                if self.request_votes(term).await? {
+                    trace!("candidature won for term {term}");
+                    // Hold the election state, the term can't change while
+                    // we take the lead
+                    let election = self.election.read().await;
+                    if election.current_term == term && self.p_status.is_candidate().await {
+                        let events = self.switch_to_leader().await?;
+                        let noop = self.append_noop(term).await?;
+                        std::mem::drop(election);
+                        self.notify_status(events).await;
+                        if let Err(err) = self.hook.append_term(&noop).await {
+                            self.leader_hook_failed("append_term", &err).await;
+                        }
+                    }
+                    break;
                 }
             }
-            (commit_index, logs.append("candidature".into()))
-        };
+            if !self.p_status.is_candidate().await {
+                break;
+            }
+            let dur = self.randomized_timeout();
+            debug!("wait {:?} before a new timeout", dur);
+            tokio::time::sleep(dur).await;
+        }
+        Ok(())
+    }
 
+    /// Increment the election term and vote for the local node. The new
+    /// state is persisted before sending any vote request.
+    async fn start_election(&self) -> ErrorResult<usize> {
+        let mut election = self.election.write().await;
+        let mut next = election.clone();
+        next.current_term += 1;
+        next.vote_for = Some(self.node_url());
+        self.persist_hard_state(&next).await?;
+        *election = next;
         self.next_indexes.write().await.clear();
-        self.hook.append_term(&last_term);
-        *self.vote_for.write().await = Some((self.settings.node_id.clone(), commit_index));
-
-        while self
-            .start_candidature(commit_index, last_term.clone())
-            .await
-        {
-            last_term = self.logs.lock().await.append("candidature".into());
-            self.hook.append_term(&last_term);
-            *self.vote_for.write().await = Some((self.settings.node_id.clone(), commit_index));
-        }
+        trace!("start election for term {}", election.current_term);
+        Ok(election.current_term)
+    }
 
-        Ok(())
+    /// Send a pre vote request to each node for the next election term,
+    /// return true if a majority of the cluster, the local node included,
+    /// would vote for us.
+    async fn request_pre_votes(&self) -> ErrorResult<bool> {
+        let term = self.current_term().await + 1;
+        let input = self.vote_input(term).await;
+        self.collect_votes(input, true).await
     }
 
-    async fn start_candidature(&self, commit_index: usize, last_term: LogEntry) -> bool {
-        let res = self.async_calls_candidature(commit_index, last_term).await;
-        // clean vote
-        *self.vote_for.write().await = None;
+    /// Send a vote request to each node, return true if a majority of the
+    /// cluster, the local node included, voted for us.
+    async fn request_votes(&self, term: usize) -> ErrorResult<bool> {
+        let input = self.vote_input(term).await;
+        self.collect_votes(input, false).await
+    }
 
-        trace!("candidature finished!");
-        if res {
-            self.switch_to_leader().await.unwrap();
-            return false;
-        }
-        if self.p_status.is_follower().await {
-            return false;
+    async fn vote_input(&self, term: usize) -> RequestVoteInput {
+        let logs = self.logs.lock().await;
+        RequestVoteInput {
+            term,
+            candidate_id: self.node_url(),
+            last_log_index: logs.last_index(),
+            last_log_term: logs.last_term(),
         }
-        let dur = self.settings.get_randomized_timeout();
-        debug!("wait {:?} before a new timeout", dur);
-        tokio::time::sleep(dur).await;
-        self.p_status.is_candidate().await
     }
 
-    async fn async_calls_candidature(&self, commit_index: usize, last_term: LogEntry) -> bool {
+    /// Send the vote requests, or the pre vote requests, until a majority
+    /// of the active configuration is reached. Stop if we aren't a
+    /// candidate anymore.
+    async fn collect_votes(&self, input: RequestVoteInput, pre_vote: bool) -> ErrorResult<bool> {
         let nodes = self.node_list.read().await.clone();
-        if nodes.is_empty() {
-            trace!("no other nodes, will turn into a leader by default");
-            return true;
+        let configuration = self.membership.read().await.active().clone();
+        let mut granted = BTreeSet::from([self.node_url()]);
+        if configuration.has_quorum(|node| granted.contains(node)) {
+            trace!("no other voters, will turn into a leader by default");
+            return Ok(true);
         }
-        let len = nodes.len();
-        let mut granted_vote_count = 0;
         for node in nodes {
-            if self.p_status.is_follower().await {
-                return false;
+            if !self.p_status.is_candidate().await {
+                return Ok(false);
             }
-            // todo: manage all warning, (return an error and stop the node)
-            call_candidature(
-                &node.into(),
-                &self.settings,
-                &last_term,
-                commit_index,
-                &mut granted_vote_count,
-            )
-            .await
-        }
-
-        trace!("candidature finished with score {}", granted_vote_count);
-        if let Some((vote, _)) = &*self.vote_for.read().await {
-            if *vote == format!("{}:{}", self.settings.addr, self.settings.port) {
-                return granted_vote_count + 1 > (len / 2); // todo use quorum from settings
+            let res = match self
+                .call_candidature(&(&node).into(), &input, pre_vote)
+                .await
+            {
+                Some(res) => res,
+                None => continue,
+            };
+            if !pre_vote {
+                let (node, granted) = (node.clone(), res.vote_granted);
+                self.observe(move |hook| async move { hook.receive_vote(&node, granted).await });
+            }
+            if self.observe_term(res.current_term).await? {
+                trace!("candidature aborted, newer term {}", res.current_term);
+                return Ok(false);
+            }
+            if res.vote_granted {
+                granted.insert(node);
+            }
+            if configuration.has_quorum(|node| granted.contains(node)) {
+                return Ok(true);
             }
         }
-        granted_vote_count > (len / 2) // todo use quorum from settings
+
+        trace!("candidature finished with votes of {:?}", granted);
+        Ok(false)
     }
-}
 
-/// Make the post request and manage a unitary result
-async fn call_candidature(
-    target: &Url,
-    settings: &Settings,
-    last_term: &LogEntry,
-    commit_index: usize,
-    granted_vote_count: &mut usize,
-) {
-    // todo: we may want in case of fail make a hook
-    #[cfg(not(test))]
-    match client::post_request_vote(
-        target,
-        settings,
-        RequestVoteInput {
-            candidate_id: settings.node_id.clone(),
-            term: last_term.clone(),
-            last_term: commit_index,
-        },
-    )
-    .await
-    {
-        Ok(res) => {
-            debug!("vote request response received {:#?}", res);
-            if res.vote_granted {
-                *granted_vote_count += 1;
+    /// Make the vote request, return `None` if the node didn't answer.
+    async fn call_candidature(
+        &self,
+        target: &Url,
+        input: &RequestVoteInput,
+        pre_vote: bool,
+    ) -> Option<RequestVoteResult> {
+        // todo: we may want in case of fail make a hook
+        let result = if pre_vote {
+            self.transport.pre_vote(target, input.clone()).await
+        } else {
+            self.transport.request_vote(target, input.clone()).await
+        };
+        match result {
+            Ok(res) => {
+                debug!("vote request response received {:#?}", res);
+                Some(res)
+            }
+            Err(err) => {
+                warn!(
+                    "failed to request vote to {},\n{:indent$}",
+                    target,
+                    *err,
+                    indent = 2
+                );
+                None
             }
-        }
-        Err(err) => {
-            warn!(
-                "failed to request vote to {},\n{:indent$}",
-                target,
-                *err,
-                indent = 2
-            );
         }
     }
-    #[cfg(test)]
-    todo!("define unit test behaviour")
 }
@@ -6,7 +6,7 @@
 //! timeout if node's settings say it's not a pure follower (can be candidate)
 //! If the node is a follower follower, doesn't start any timeout.
 
-use crate::{common::error::ErrorResult, node::Node};
+use crate::{common::error::ErrorResult, node::Node, state::EStatus};
 use tracing::{debug, trace};
 
 impl Node {
@@ -15,13 +15,14 @@ impl Node {
         trace!("start follower workflow");
         if self.settings.follower {
             trace!("run until ctrl-c");
-            tokio::select! { _ = tokio::signal::ctrl_c() => {} };
+            tokio::select! {
+                _ = tokio::signal::ctrl_c() => {}
+                _ = self.leave_the_voters() => {}
+            };
             Ok(())
         } else {
             self.reset_timeout().await;
-            while self.p_status.is_follower().await {
-                self.p_status.wait();
-            }
+            self.p_status.wait_while(EStatus::Follower).await;
             Ok(())
         }
     }
@@ -29,8 +30,11 @@ impl Node {
     pub async fn reset_timeout(&self) {
         let p_heartbeat = self.heartbeat.clone();
         let p_status = self.p_status.clone();
+        let membership = self.membership.clone();
+        let hook = self.hook.clone();
+        let local = self.node_url();
         let (send, mut recv) = tokio::sync::oneshot::channel::<()>();
-        let dur = self.settings.get_randomized_timeout();
+        let dur = self.randomized_timeout();
 
         let mut heartbeat = self.heartbeat.lock().await;
         if heartbeat.is_some() {
@@ -48,7 +52,14 @@ impl Node {
                 _ = &mut sleep => {
                     debug!("branch heartbeat timeout reached");
                     p_heartbeat.lock().await.take();
-                    let _ = p_status.switch_to_candidate().await;
+                    if let Some(leader) = p_status.get_leader().await {
+                        hook.lost_connection(&leader.to_string()).await;
+                    }
+                    if membership.read().await.active().is_voter(&local) {
+                        let _ = p_status.switch_to_candidate().await;
+                    } else {
+                        debug!("not a voter of the cluster, keep following");
+                    }
                 }
             }
         });
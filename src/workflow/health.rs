@@ -0,0 +1,105 @@
+// License:
+// This source code is licensed under the GPLv3 license, you can found the
+// LICENSE file in the root directory of this source tree.
+
+//! # FAILURE DETECTOR
+//!
+//! The leader records the result of each call to a peer, see
+//! [crate::health]. A suspect peer is skipped by the sending sessions until
+//! its backoff is over, and a member suspect for longer than `evict_after`
+//! is removed from the cluster with `Node::remove_member`.
+
+use crate::{health::PeerHealth, node::Node};
+use std::{
+    collections::{BTreeMap, BTreeSet},
+    time::Duration,
+};
+use tokio::time::Instant;
+use tracing::{debug, trace, warn};
+
+impl Node {
+    /// Health of the peers seen by the node since it took the lead, empty
+    /// if the node isn't the leader.
+    pub fn peer_health(&self) -> BTreeMap<String, PeerHealth> {
+        self.health.lock().unwrap().clone()
+    }
+
+    /// Forget the health of the nodes that aren't `peers` anymore
+    pub(super) fn retain_health(&self, peers: &BTreeSet<String>) {
+        self.health
+            .lock()
+            .unwrap()
+            .retain(|node, _| peers.contains(node));
+    }
+
+    /// Clear the health of the peers, called when the node takes the lead
+    pub(super) fn reset_health(&self) {
+        self.health.lock().unwrap().clear();
+    }
+
+    /// False if the `node` is suspect and its backoff isn't over
+    pub(super) fn should_contact(&self, node: &str) -> bool {
+        let health = self.health.lock().unwrap();
+        health
+            .get(node)
+            .is_none_or(|health| health.should_contact(Instant::now()))
+    }
+
+    /// The `node` answered a call after `rtt`
+    pub(super) fn contact_succeeded(&self, node: &str, rtt: Duration) {
+        let mut health = self.health.lock().unwrap();
+        let peer = health.entry(node.to_string()).or_default();
+        if peer.succeed(Instant::now(), rtt) {
+            debug!("{node} answers again");
+        }
+    }
+
+    /// A call to the `node` failed, call the `lost_connection` hook if the
+    /// node becomes suspect
+    pub(super) async fn contact_failed(&self, node: &str) {
+        let suspect = {
+            let mut health = self.health.lock().unwrap();
+            let peer = health.entry(node.to_string()).or_default();
+            peer.fail(Instant::now(), &self.settings)
+        };
+        if suspect {
+            warn!("lost the connection with {node}");
+            self.hook.lost_connection(node).await;
+        }
+    }
+
+    /// Propose the removal of a member suspect since longer than
+    /// `evict_after`. One member at a time, and only if no other change of
+    /// the membership is in progress.
+    pub(super) async fn evict_suspects(&self) {
+        let membership = self.membership.read().await;
+        if membership.is_changing() {
+            return;
+        }
+        let now = Instant::now();
+        let suspect = self
+            .health
+            .lock()
+            .unwrap()
+            .iter()
+            .find(|(node, health)| {
+                let active = membership.active();
+                health.should_evict(now, &self.settings)
+                    && (active.is_voter(node) || active.is_learner(node))
+            })
+            .map(|(node, _)| node.clone());
+        drop(membership);
+        let suspect = match suspect {
+            Some(suspect) => suspect,
+            None => return,
+        };
+        warn!("{suspect} is down for too long, remove it from the cluster");
+        let node = self.clone();
+        tokio::spawn(async move {
+            match node.remove_member(suspect.clone()).await {
+                Ok(index) => trace!("{suspect} evicted at {index}"),
+                Err(err) => warn!("failed to evict {suspect}: {:?}", err),
+            }
+        });
+    }
+}
@@ -4,22 +4,18 @@
 
 use crate::{
     api::io_msg::UpdateNodeResult,
-    common::{
-        error::{throw, Error, ErrorResult, WarnResult},
-        Url,
-    },
+    common::error::{throw, Error, ErrorResult},
+    membership::{Configuration, Membership},
     node::{Node, NodeInfo},
-    Settings,
 };
 
-#[cfg(not(test))]
-use crate::api::{client, server};
 use tracing::{trace, warn};
 
 impl Node {
     /// First workflow described in `init.md` specification. The following workflow
     /// is called only once time when the node start.
     ///
+    /// - Reload the hard state from the stable storage.
     /// - Start a server in a tokio task (then run in background).
     /// - Manage the case where there is no known nodes in the user settings.
     /// - Try to connect to each nodes taken from the `nodes` variable in the
@@ -35,16 +31,18 @@ impl Node {
         if !self.p_status.is_pending().await {
             throw!(Error::WrongStatus)
         }
+        self.load_hard_state().await?;
         let node_clone = self.clone();
-        #[cfg(not(test))] // no server in unit test
-        tokio::spawn(async move { server::new(node_clone).await });
+        let transport = self.transport.clone();
+        tokio::spawn(async move { transport.serve(node_clone).await });
         if self.settings.nodes.is_empty() {
             eprintln!("warn: No nodes known, may be a configuration error");
         }
         if !self.connect_to_leader().await? {
             // No connection possible, turn into a follower.
             if !self.settings.follower {
-                self.switch_to_candidate().await?;
+                let events = self.switch_to_candidate().await?;
+                self.notify_status(events).await;
             }
         }
         Ok(())
@@ -54,20 +52,51 @@ impl Node {
     /// done by a `update_node` call. Take a `UpdateNodeInput` containing the
     /// calling node and if he want to be a follower.
     ///
-    /// If you are a leader, you add the follower into a pool that is managed in
-    /// the workflow `On heartbeat timeout` in `leader_workflow`.
+    /// If you are a leader, you add the node into a pool that is managed in
+    /// the leader workflow, it becomes a learner with the next membership
+    /// change, then a voter once it caught up unless it's a pure follower.
+    /// A pure follower listed in the voters leaves them for the learners.
+    /// See [crate::workflow::membership]. The `pre_add_connection` hook can
+    /// refuse a node that isn't a member yet.
     ///
-    /// Whatever your status, if the script `update-node` succeed it returns
+    /// Whatever your status, if the `update_node` hook succeed it returns
     /// an `UpdateNodeResult` and none otherwise.
     pub async fn receive_connection_request(&self, input: NodeInfo) -> Option<UpdateNodeResult> {
         trace!("receive connection request from {}", input.addr);
-        if !self.hook.update_node() {
+        if let Err(err) = self.hook.update_node().await {
+            self.hook_failed("update_node", &err);
             return None;
         }
         if self.p_status.is_leader().await {
-            // self.node_list.write().await.remove(&input.addr);
+            let (joined, member) = {
+                let membership = self.membership.read().await;
+                let active = membership.active();
+                (
+                    active.has_joined(&input.addr, input.learner),
+                    active.members().contains(&input.addr),
+                )
+            };
+            let waiting = self
+                .waiting_nodes
+                .lock()
+                .await
+                .iter()
+                .any(|node| node.addr == input.addr);
+            if !joined && !waiting {
+                if !member {
+                    if let Err(err) = self.hook.pre_add_connection(&input.addr).await {
+                        self.hook_failed("pre_add_connection", &err);
+                        return None;
+                    }
+                }
+                let mut waiting_nodes = self.waiting_nodes.lock().await;
+                if !waiting_nodes.iter().any(|node| node.addr == input.addr) {
+                    trace!("{} waits to join the cluster", input.addr);
+                    waiting_nodes.push_back(input);
+                }
+            }
             return Some(UpdateNodeResult {
-                leader_id: format!("{}:{}", self.settings.addr, self.settings.port),
+                leader_id: self.node_url(),
                 node_list: self.get_node_list().await,
             });
         };
@@ -93,8 +122,10 @@ impl Node {
         let mut success = false;
         let mut to_leader = false;
         for url in self.settings.nodes.iter() {
+            let learner = self.settings.follower;
             match self
-                .send_init_update_node(&url.into(), &self.settings, self.uuid)
+                .transport
+                .update_node(&url.into(), self.uuid, learner)
                 .await
             {
                 Ok(result) => {
@@ -130,8 +161,10 @@ impl Node {
                 Some(leader) => leader,
                 _ => return Ok(false),
             };
+            let learner = self.settings.follower;
             match self
-                .send_init_update_node(&leader.clone(), &self.settings, self.uuid)
+                .transport
+                .update_node(&leader, self.uuid, learner)
                 .await
             {
                 Ok(result) => self.update(result).await,
@@ -150,22 +183,28 @@ impl Node {
         Ok(true)
     }
 
-    #[cfg(not(test))] // Mocked in unit tests
-    async fn send_init_update_node(
-        &self,
-        target: &Url,
-        settings: &Settings,
-        uuid: [u8; 16],
-    ) -> WarnResult<UpdateNodeResult> {
-        client::post_update_node(target, settings, uuid).await
-    }
-
     async fn update(&self, result: UpdateNodeResult) {
         trace!("update leader {}", result.leader_id);
-        // self.node_list.write().await.extend(result.node_list);
-        self.p_status
-            .switch_to_follower(result.leader_id.into())
-            .await
-            .unwrap();
+        let leader = if result.leader_id.is_empty() {
+            None
+        } else {
+            Some(result.leader_id.into())
+        };
+        let changes = {
+            let logs = self.logs.lock().await;
+            if leader.is_some() && logs.last_index() == 0 && !result.node_list.is_empty() {
+                // A new node joins a running cluster, it follows the voters
+                // of the leader until it receives the configuration entries
+                *self.membership.write().await = Membership::new(
+                    0,
+                    Configuration::new(result.node_list.into_iter().collect()),
+                );
+                self.track_configurations(&logs, 1).await
+            } else {
+                vec![]
+            }
+        };
+        self.notify_members(changes).await;
+        self.p_status.switch_to_follower(leader).await.unwrap();
     }
 }
@@ -0,0 +1,124 @@
+// License:
+// This source code is licensed under the GPLv3 license, you can found the
+// LICENSE file in the root directory of this source tree.
+
+//! # INSTALL SNAPSHOT
+//!
+//! Implementation of the workflow when node receive a `install_snapshot`
+//! request. The leader sends its latest snapshot when the terms a node
+//! needs have been compacted.
+
+use crate::{
+    api::io_msg::{InstallSnapshotInput, InstallSnapshotResult},
+    common::error::ErrorResult,
+    membership::Membership,
+    node::Node,
+    storage::Snapshot,
+};
+use tokio::time::Instant;
+use tracing::trace;
+
+impl Node {
+    /// Reception of a install_snapshot request.
+    ///
+    /// 1. Reply false if term < currentTerm
+    /// 2. Reset the heartbeat timeout and follow the leader
+    /// 3. Reply true if the snapshot is already committed locally
+    /// 4. Restore the state machine with the hook
+    /// 5. Save the snapshot, keep the terms following the snapshot if the
+    ///    logs match, discard the whole logs otherwise
+    ///
+    /// The installation runs in a task of its own, it goes on if the leader
+    /// stops waiting for the answer. The next request of the leader finds
+    /// the snapshot installed.
+    pub async fn receive_install_snapshot(
+        &self,
+        input: InstallSnapshotInput,
+    ) -> ErrorResult<InstallSnapshotResult> {
+        trace!(
+            "receive snapshot up to {} from {}",
+            input.last_included.index,
+            input.leader_id
+        );
+        let node = self.clone();
+        tokio::spawn(async move { node.internal_receive_install_snapshot(input).await })
+            .await
+            .unwrap_or_else(|err| std::panic::resume_unwind(err.into_panic()))
+    }
+
+    /// internal implementation of receive install snapshot
+    async fn internal_receive_install_snapshot(
+        &self,
+        input: InstallSnapshotInput,
+    ) -> ErrorResult<InstallSnapshotResult> {
+        let (current_term, events) = {
+            let mut election = self.election.write().await;
+            if input.term < election.current_term {
+                trace!("snapshot rejected, term older than local state");
+                return Ok(InstallSnapshotResult {
+                    current_term: election.current_term,
+                    success: false,
+                });
+            }
+            if election.update_term(input.term) {
+                self.persist_hard_state(&election).await?;
+            }
+            *self.leader_contact.lock().unwrap() = Some(Instant::now());
+            // Follow the leader before releasing the election state, nobody
+            // sees the node leading in a term it doesn't own
+            let events = self
+                .switch_to_follower(input.leader_id.clone().into())
+                .await
+                .unwrap_or_default();
+            (election.current_term, events)
+        };
+        self.notify_status(events).await;
+        self.reset_timeout().await;
+
+        // The snapshot replaces the committed entries, no commit meanwhile
+        let _committing = self.committing.lock().await;
+        if input.last_included.index <= self.logs.lock().await.commit_index() {
+            trace!("snapshot already committed");
+            return Ok(InstallSnapshotResult {
+                current_term,
+                success: true,
+            });
+        }
+        if let Err(err) = self
+            .hook
+            .restore_snapshot(input.last_included.index, &input.data)
+            .await
+        {
+            self.hook_failed("restore_snapshot", &err);
+            return Ok(InstallSnapshotResult {
+                current_term,
+                success: false,
+            });
+        }
+        let snapshot = Snapshot {
+            last_included: input.last_included,
+            data: input.data,
+        };
+        self.storage.save_snapshot(&snapshot)?;
+        let mut logs = self.logs.lock().await;
+        logs.install_snapshot(&snapshot.last_included)?;
+        let index = snapshot.last_included.index;
+        if !input.configuration.voters.is_empty() {
+            *self.membership.write().await = Membership::new(index, input.configuration);
+        }
+        let changes = self.track_configurations(&logs, index + 1).await;
+        self.applied.send_replace(logs.commit_index());
+        // The proposed entries up to the snapshot are committed
+        self.resolve_proposals(&logs).await;
+        std::mem::drop(logs);
+        self.notify_members(changes).await;
+        *self.snapshot.write().await = Some(snapshot);
+        self.persist_hard_state(&*self.election.read().await)
+            .await?;
+
+        Ok(InstallSnapshotResult {
+            current_term,
+            success: true,
+        })
+    }
+}
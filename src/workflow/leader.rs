@@ -2,27 +2,24 @@
 // This source code is licensed under the GPLv3 license, you can found the
 // LICENSE file in the root directory of this source tree.
 
-#[cfg(not(test))]
-use crate::api::client;
 use crate::{
-    api::io_msg::AppendTermResult,
-    common::{error::ErrorResult, Url},
-    log_entry::{Entries, Term},
+    api::io_msg::{AppendTermResult, InstallSnapshotInput},
+    common::{
+        error::{ErrorResult, HookError},
+        Url,
+    },
+    log_entry::Term,
     node::Node,
-    state::Status,
-    Hook,
+    storage::Snapshot,
 };
 
-use std::{
-    collections::{HashSet, VecDeque},
-    sync::Arc,
-};
-use tokio::sync::{Mutex, RwLock};
-use tracing::{debug, trace, warn};
+use std::collections::BTreeSet;
+use tokio::time::Instant;
+use tracing::{debug, error, trace, warn};
 
 /// Local enum used to trace how `post_new_append_term` worked
 /// See also `Node::manage_append_term_result`
-enum ReactResult {
+pub(super) enum ReactResult {
     /// Retry the latest call
     Retry,
     /// Break the call loop
@@ -39,56 +36,93 @@ impl Node {
     /// - Run a loop to send a new term to other nodes each `send_term_period`
     ///
     /// Run the loops until someone else take the lead or handle ctrl_c.
-    /// Leader understand if someone took the lead if another node is more
-    /// updated.
+    /// Leader understand if someone took the lead if another node answers
+    /// with a newer election term.
     ///
     /// Look at the Raft documentation for more information.
     pub async fn run_leader(&self) -> ErrorResult<()> {
-        self.start_loop_term_preparation();
+        let term = self.current_term().await;
+        self.reset_health();
+        self.start_loop_term_preparation(term);
         loop {
             if !self.p_status.is_leader().await {
                 trace!("stop lead");
                 break;
             }
-            let _ = self.internal_run_leader().await?;
+            if let ReactResult::Continue = self.internal_run_leader(term).await? {
+                tokio::time::sleep(self.settings.get_send_term_sleep_duration()).await;
+            }
         }
         Ok(())
     }
 
     /// Send methods called in the leader send loop.
     ///
-    /// Sends new terms for each nodes in the network
-    async fn internal_run_leader(&self) -> ErrorResult<ReactResult> {
+    /// Sends new terms for each nodes in the network, except the suspect
+    /// nodes until their backoff is over.
+    async fn internal_run_leader(&self, term: usize) -> ErrorResult<ReactResult> {
         let nodes = self.node_list.read().await.clone();
+        self.retain_health(&nodes);
         let mut fail_count = 0;
+        let mut reached = BTreeSet::from([self.node_url()]);
+        let round = self.rounds.borrow().started + 1;
+        self.rounds.send_modify(|rounds| rounds.started = round);
         trace!("start a sending session as leader");
         for node in nodes.iter() {
+            if !self.should_contact(node) {
+                trace!("{node} is suspect, skip it");
+                continue;
+            }
+            let failed = fail_count;
             if let ReactResult::Break = self
-                .post_new_append_term(node.into(), &mut fail_count)
+                .post_new_append_term(node.into(), term, &mut fail_count)
                 .await?
             {
                 return Ok(ReactResult::Break);
             }
+            if fail_count == failed {
+                reached.insert(node.clone());
+            }
         }
 
         // Increment the commit term after the calls
-        self.increment_commit_term().await;
+        self.increment_commit_term(term).await?;
+        self.advance_membership(term).await?;
 
-        if fail_count > (self.node_list.read().await.len() / 2) {
-            warn!("quorum is unreachable, switch to candidate");
-            self.switch_to_candidate().await?;
+        let removed = {
+            let membership = self.membership.read().await;
+            !membership.is_changing() && !membership.committed.is_voter(&self.node_url())
+        };
+        if removed {
+            warn!("removed from the cluster, step down");
+            let events = self.step_down().await?;
+            self.notify_status(events).await;
             return Ok(ReactResult::Break);
         }
 
+        let configuration = self.membership.read().await.active().clone();
+        if !configuration.has_quorum(|node| reached.contains(node)) {
+            warn!("quorum is unreachable, step down");
+            let events = self.step_down().await?;
+            self.notify_status(events).await;
+            return Ok(ReactResult::Break);
+        }
+
+        // A majority answered in our term, confirm the lead for the reads
+        // waiting that session
+        self.rounds.send_modify(|rounds| rounds.confirmed = round);
+        self.evict_suspects().await;
+
         Ok(ReactResult::Continue)
     }
 
     /// Compute and post a new [AppendTermInput](crate::api::io_msg::AppendTermInput)
     /// to the `target`. Manage internally the result.
     /// See `manage_append_term_result`.
-    async fn post_new_append_term(
+    pub(super) async fn post_new_append_term(
         &self,
         target: Url,
+        term: usize,
         fail_count: &mut usize,
     ) -> ErrorResult<ReactResult> {
         let mut retry = 100;
@@ -97,11 +131,35 @@ impl Node {
                 return Ok(ReactResult::Break);
             }
             let url = target.clone();
-            let append_term_input = self.create_term_input(&url).await;
-            #[cfg(not(test))]
-            match client::post_append_term(&url, &self.settings, append_term_input).await {
+            if let Some(snapshot) = self.snapshot_for(&url).await {
+                // The node doesn't count in the session until it installed
+                // the snapshot, the others get their terms meanwhile
+                self.send_snapshot_in_background(url, term, snapshot);
+                *fail_count += 1;
+                return Ok(ReactResult::Continue);
+            }
+            let append_term_input = match self.create_term_input(&url, term).await {
+                Ok(input) => input,
+                Err(err) => {
+                    // The leader can't send the terms it lost
+                    self.leader_hook_failed("retreive_term", &err).await;
+                    return Ok(ReactResult::Break);
+                }
+            };
+            let sent = (
+                append_term_input.prev_log_index,
+                append_term_input.entries.len(),
+            );
+            let start = Instant::now();
+            match self.transport.append_term(&url, append_term_input).await {
                 Ok(result) => {
-                    let react = self.manage_append_term_result(url, result).await?;
+                    self.contact_succeeded(&url.to_string(), start.elapsed());
+                    let (node, (prev_log_index, count)) = (url.to_string(), sent);
+                    let success = result.success;
+                    self.observe(move |hook| async move {
+                        hook.send_term(&node, prev_log_index + count, success).await
+                    });
+                    let react = self.manage_append_term_result(url, sent, result).await?;
                     match react {
                         ReactResult::Retry => {
                             warn!("retry call to {}", target);
@@ -117,17 +175,97 @@ impl Node {
                 }
                 Err(p_warn) => {
                     warn!("{}", *p_warn);
-                    // Not sure if we want to ban node, call a hook instead
+                    self.contact_failed(&url.to_string()).await;
                     *fail_count += 1;
                     return Ok(ReactResult::Continue);
                 }
             }
-            #[cfg(test)]
-            todo!("implement unit test behaviour")
         }
     }
 
-    /// Manage a result of a `post_append_term` call.
+    /// Latest snapshot if the `target` needs terms that have been compacted.
+    async fn snapshot_for(&self, target: &Url) -> Option<Snapshot> {
+        let compacted = self.logs.lock().await.compacted()?;
+        let next_index = self.next_indexes.read().await.get(target)?.unwrap();
+        if next_index >= compacted.index {
+            return None;
+        }
+        self.snapshot.read().await.clone()
+    }
+
+    /// Send the snapshot to the `target` in a task of its own, restoring a
+    /// large state takes longer than a sending session. Nothing is sent if
+    /// the previous snapshot sent to the `target` isn't answered yet.
+    fn send_snapshot_in_background(&self, target: Url, term: usize, snapshot: Snapshot) {
+        if !self.installing.lock().unwrap().insert(target.to_string()) {
+            trace!("{target} is installing the snapshot");
+            return;
+        }
+        let node = self.clone();
+        tokio::spawn(async move {
+            if let Err(err) = node.send_snapshot(&target, term, snapshot).await {
+                warn!("failed to send the snapshot to {target}: {:?}", err);
+            }
+            node.installing.lock().unwrap().remove(&target.to_string());
+        });
+    }
+
+    /// Send the snapshot to the `target`.
+    ///
+    /// # Result
+    ///
+    /// - retry means the snapshot is installed, the next session sends the
+    ///   following terms.
+    /// - continue means the target failed to install the snapshot.
+    /// - break means that we're now a follower.
+    async fn send_snapshot(
+        &self,
+        target: &Url,
+        term: usize,
+        snapshot: Snapshot,
+    ) -> ErrorResult<ReactResult> {
+        use crate::node::NextIndex::Validated;
+
+        trace!(
+            "send snapshot up to {} to {}",
+            snapshot.last_included.index,
+            target
+        );
+        let input = InstallSnapshotInput {
+            term,
+            leader_id: self.node_url(),
+            last_included: snapshot.last_included.clone(),
+            data: snapshot.data,
+            configuration: self.membership.read().await.committed.clone(),
+        };
+        let start = Instant::now();
+        match self.transport.install_snapshot(target, input).await {
+            Ok(result) => {
+                self.contact_succeeded(&target.to_string(), start.elapsed());
+                if self.observe_term(result.current_term).await? {
+                    trace!("{target} has a newer term {}", result.current_term);
+                    return Ok(ReactResult::Break);
+                }
+                if !result.success {
+                    warn!("{target} failed to install the snapshot");
+                    return Ok(ReactResult::Continue);
+                }
+                self.next_indexes
+                    .write()
+                    .await
+                    .insert(target.clone(), Validated(snapshot.last_included.index));
+                Ok(ReactResult::Retry)
+            }
+            Err(p_warn) => {
+                warn!("{}", *p_warn);
+                self.contact_failed(&target.to_string()).await;
+                Ok(ReactResult::Continue)
+            }
+        }
+    }
+
+    /// Manage a result of a `post_append_term` call. `sent` is the previous
+    /// log index and the number of entries of the request.
     ///
     /// If RPC request or response contains term T > currentTerm:
     /// set currentTerm = T, convert to follower.
@@ -135,8 +273,8 @@ impl Node {
     /// If last log index >= nextIndex for a follower: send
     /// append_term post request with log entries starting at nextIndex
     /// - If successful: update nextIndex and matchIndex for follower
-    /// - If fails because of log inconsistency: use the last entry returned
-    ///   by the distant node and retry.
+    /// - If fails because of log inconsistency: decrement nextIndex, or jump
+    ///   to the last entry of the distant node if it's before, and retry.
     ///
     /// # Result
     ///
@@ -149,127 +287,80 @@ impl Node {
     async fn manage_append_term_result(
         &self,
         target: Url,
+        sent: (usize, usize),
         result: AppendTermResult,
     ) -> ErrorResult<ReactResult> {
         use crate::node::NextIndex::{Pending, Validated};
 
-        {
-            let mut next_indexes_guard = self.next_indexes.write().await;
-            if result.current_term.id > self.logs.lock().await.last_index() {
-                trace!(
-                    "{target} became leader with term {}",
-                    result.current_term.id
-                );
-                self.switch_to_follower(target).await?;
-                return Ok(ReactResult::Break);
-            }
-
-            if result.current_term.id > 0 {
-                if let Some(local_term) = self.leader_retreive_term(result.current_term.id).await {
-                    if local_term == result.current_term {
-                        debug!(
-                            "node return a current term {:#?} validated",
-                            result.current_term
-                        );
-                        next_indexes_guard
-                            .insert(target.clone(), Validated(result.current_term.id));
-                    } else {
-                        debug!(
-                            "node return a unmatched current term {:#?}",
-                            result.current_term
-                        );
-                        next_indexes_guard
-                            .insert(target.clone(), Pending(result.current_term.id - 1));
-                    }
-                } else {
-                    warn!("leader can't find a term");
-                }
-            } else {
-                next_indexes_guard.insert(target.clone(), Validated(1));
-            }
+        if self.observe_term(result.current_term).await? {
+            trace!("{target} has a newer term {}", result.current_term);
+            return Ok(ReactResult::Break);
         }
 
+        let (prev_log_index, len) = sent;
+        let mut next_indexes_guard = self.next_indexes.write().await;
         if result.success {
             trace!("successfully sent term to {}", target);
+            next_indexes_guard.insert(target, Validated(prev_log_index + len));
             Ok(ReactResult::Continue)
         } else {
-            trace!("retry to send to {} after {:#?}", target, result);
+            let index = prev_log_index.saturating_sub(1).min(result.last_log_index);
+            debug!("logs of {target} don't match at {prev_log_index}, retry from {index}");
+            next_indexes_guard.insert(target, Pending(index));
             Ok(ReactResult::Retry)
         }
     }
 
-    async fn leader_retreive_term(&self, index: usize) -> Option<Term> {
-        if let Some(term) = self.logs.lock().await.find(index) {
-            return Some(term);
-        }
-        if let Some(term) = self.hook.retreive_term(index) {
-            return Some(term);
-        }
-        None
-    }
-
-    async fn increment_commit_term(&self) {
-        let nodes = self.node_list.read().await;
-        let len = nodes.len();
-        if nodes.is_empty() {
-            trace!("pass commit phase with no nodes");
-            return;
-        }
-        std::mem::drop(nodes);
-
-        let mut votes = Vec::<(usize, usize)>::new();
-        let mut set = HashSet::<usize>::new();
-
-        for index in self
-            .next_indexes
-            .read()
-            .await
-            .iter()
-            .map(|(_, v)| v.validated())
-        {
-            if !set.contains(&index) {
-                set.insert(index);
-                votes.push((index, 0));
-                votes.sort_by_key(|(index, _)| *index);
-            }
-            for (i, n) in votes.iter_mut() {
-                if *i <= index {
-                    *n += 1
-                } else {
-                    break;
+    /// Commit the greatest index stored by a majority of the active
+    /// configuration, the leader included if it's a voter.
+    ///
+    /// Only an entry of the election `term` of the leader is committed that
+    /// way, the previous entries are committed with it (§5.4.2). An entry
+    /// of a previous term stored by a majority can still be replaced by the
+    /// next leader.
+    async fn increment_commit_term(&self, term: usize) -> ErrorResult<()> {
+        let local = self.node_url();
+        let last_index = self.logs.lock().await.last_index();
+        let configuration = self.membership.read().await.active().clone();
+        let index = {
+            let next_indexes = self.next_indexes.read().await;
+            configuration.quorum_index(|node| {
+                if node == local {
+                    return last_index;
                 }
-            }
-        }
-
-        let mut max_term_id = 0;
-        debug!("check latest commit: votes {:?}", votes);
-        for (i, n) in votes {
-            // todo: take quorum from settings
-            if n >= (len / 2) {
-                max_term_id = i;
-            }
+                next_indexes
+                    .get(&node.to_string().into())
+                    .map_or(0, |index| index.validated())
+            })
+        };
+        debug!("check latest commit: voters {:?}", configuration);
+        trace!("index stored by a majority {index}");
+        if self.logs.lock().await.term_at(index) != Some(term) {
+            trace!("entry {index} isn't of the term {term}, wait");
+            return Ok(());
         }
+        self.commit_entries(index).await
+    }
 
-        trace!("better rated term {max_term_id}");
-        self.commit_entries(max_term_id).await;
+    /// Append the empty entry that starts the lead of the election `term`,
+    /// the entries of the previous leaders are committed with it. The
+    /// caller holds the `election` state.
+    pub(crate) async fn append_noop(&self, term: usize) -> ErrorResult<Term> {
+        let entry = self.logs.lock().await.append_noop(term)?;
+        trace!("no-op appended at {}", entry.index);
+        Ok(entry)
     }
 
     /// Start a loop that prepare terms in parallel. Fill the local `logs`
-    /// parameter of the node
-    fn start_loop_term_preparation(&self) {
-        let p_logs = self.logs.clone();
-        let p_status = self.p_status.clone();
+    /// parameter of the node with terms of the election `term`
+    fn start_loop_term_preparation(&self, term: usize) {
+        let node = self.clone();
         let prep_term_period = self.settings.get_prepare_term_sleep_duration();
-        let waiting_nodes = self.waiting_nodes.clone();
-        let hook = self.hook.clone();
-        let nodes = self.node_list.clone();
-        // todo: remove unwraps and handle errors
         tokio::spawn(async move {
             loop {
-                let should_break =
-                    internal_term_preparation(&p_logs, &p_status, &waiting_nodes, &nodes, &hook)
-                        .await;
-                if should_break {
+                // The logs don't grow while the lead is transferred
+                let transferring = node.transfer.lock().unwrap().is_some();
+                if !transferring && node.term_preparation(term).await {
                     break;
                 }
                 let sleep = tokio::time::sleep(prep_term_period);
@@ -281,69 +372,57 @@ impl Node {
             }
         });
     }
-}
 
-#[cfg(test)]
-pub async fn _term_preparation(
-    p_logs: &Arc<Mutex<Entries>>,
-    p_status: &Status,
-    waiting_nodes: &Arc<Mutex<VecDeque<String>>>,
-    nodes: &Arc<RwLock<HashSet<String>>>,
-    hook: &Arc<Box<dyn Hook>>,
-) -> bool {
-    internal_term_preparation(p_logs, p_status, waiting_nodes, nodes, hook).await
-}
-
-async fn internal_term_preparation(
-    p_logs: &Arc<Mutex<Entries>>,
-    p_status: &Status,
-    waiting_nodes: &Arc<Mutex<VecDeque<String>>>,
-    nodes: &Arc<RwLock<HashSet<String>>>,
-    hook: &Arc<Box<dyn Hook>>,
-) -> bool {
-    if !p_status.is_leader().await {
-        // prepare term only if we are a Leader
-        return true;
-    }
-    if nodes.read().await.is_empty() {
-        // prepare term only if there is someone listening :-)
-        let mut waiting_nodes_guard = waiting_nodes.lock().await;
-        if !waiting_nodes_guard.is_empty() {
-            // create a term for the waiting node
-            trace!("starter connect term");
-            let term = p_logs
-                .lock()
-                .await
-                .append(conn_term_preparation(&mut waiting_nodes_guard, hook));
-            hook.append_term(&term);
+    /// Prepare a term in the election `term`. Return true to stop the
+    /// preparation, if the node isn't the leader of that term anymore.
+    ///
+    /// A rejected or failed `prepare_term` skips the term, a failure of
+    /// `append_term` makes the leader step down.
+    async fn term_preparation(&self, term: usize) -> bool {
+        if !self.p_status.is_leader().await || self.election.read().await.current_term != term {
+            // prepare term only if we are the Leader of the term
+            return true;
         }
-        return false;
+        if self.node_list.read().await.is_empty() {
+            // prepare term only if there is someone listening :-)
+            return false;
+        }
+        trace!("start term preparation");
+        let term_content = match self.hook.prepare_term().await {
+            Ok(content) => content,
+            Err(HookError::Rejected(reason)) => {
+                // Nothing to prepare this time
+                trace!("no term prepared, {reason}");
+                return false;
+            }
+            Err(err) => {
+                self.hook_failed("prepare_term", &err);
+                return false;
+            }
+        };
+        // The lead may have been lost while the hook prepared the content
+        let election = self.election.read().await;
+        if !self.p_status.is_leader().await || election.current_term != term {
+            return true;
+        }
+        let appended = self.logs.lock().await.append(term, term_content);
+        std::mem::drop(election);
+        let appended = match appended {
+            Ok(appended) => appended,
+            Err(err) => {
+                error!("stop term preparation, {:?}", err);
+                return true;
+            }
+        };
+        if let Err(err) = self.hook.append_term(&appended).await {
+            self.leader_hook_failed("append_term", &err).await;
+            return true;
+        }
+        false
     }
-    trace!("start term preparation");
-    let mut waiting_nodes_guard = waiting_nodes.lock().await;
-    let term_content = if waiting_nodes_guard.is_empty() || rand::random() {
-        trace!("hook term handling");
-        hook.prepare_term()
-    } else {
-        conn_term_preparation(&mut waiting_nodes_guard, hook)
-    };
-    let term = p_logs.lock().await.append(term_content);
-    hook.append_term(&term);
-    false
 }
 
-fn conn_term_preparation(
-    waiting_nodes: &mut VecDeque<String>,
-    hook: &Arc<Box<dyn Hook>>,
-) -> String {
-    // todo: checkout multiple waiting nodes accordingly to
-    //       some user settings to define
-    let p = waiting_nodes.pop_front();
-    if let Some(n) = p {
-        trace!("creation of a connect term");
-        format!("conn:{}", n)
-    } else {
-        warn!("unexpected hook term handling");
-        hook.prepare_term()
-    }
+#[cfg(test)]
+pub async fn _term_preparation(node: &Node, term: usize) -> bool {
+    node.term_preparation(term).await
 }
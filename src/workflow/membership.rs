@@ -0,0 +1,434 @@
+// License:
+// This source code is licensed under the GPLv3 license, you can found the
+// LICENSE file in the root directory of this source tree.
+
+//! # MEMBERSHIP
+//!
+//! Change of the voters with a joint consensus, see [crate::membership].
+//!
+//! 1. The leader appends a joint configuration C_old,new. The nodes use a
+//!    configuration as soon as it's in their logs, the decisions need a
+//!    majority of both the old and the new voters from now.
+//! 2. When C_old,new is committed, the leader appends C_new. The old
+//!    voters aren't needed anymore.
+//!
+//! A node that connects to the leader waits in `waiting_nodes`, the leader
+//! adds it to the learners when no other change is in progress. The learner
+//! receives the terms without voting, and joins the voters when it has all
+//! the entries of the leader, unless it's a pure follower. A pure follower
+//! listed in the `nodes` of the settings starts as a voter, it asks the
+//! leader to move it to the learners with a joint consensus.
+//!
+//! The removed nodes receive the terms until C_new is committed, the hook of
+//! every node is notified when C_new is in its logs. A removed node doesn't
+//! start candidatures anymore, and a leader that removed itself steps down
+//! when C_new is committed.
+
+use crate::{
+    common::error::{ErrorResult, MembershipError, ProposeError},
+    log_entry::{Entries, EntryKind, Term},
+    membership::{Configuration, Membership},
+    node::{NextIndex::Validated, Node},
+    state::EStatus,
+    CommittedIndex,
+};
+use std::collections::BTreeSet;
+use tracing::{debug, trace};
+
+impl Node {
+    /// Replace the voters of the cluster by `voters`. Resolve with the index
+    /// of the new configuration when it's committed.
+    ///
+    /// # Error
+    /// - `NotLeader` if the node isn't the leader, with the leader it knows
+    /// - `ChangeInProgress` if the previous change isn't committed yet
+    /// - `EmptyConfiguration` if there is no voters
+    /// - `LeadershipLost` if the node lost the lead before the commit of the
+    ///   change, the next leader finishes it if the joint configuration is
+    ///   committed
+    /// - `Failed` if the entry can't be appended to the logs
+    pub async fn change_membership(
+        &self,
+        voters: BTreeSet<String>,
+    ) -> Result<CommittedIndex, MembershipError> {
+        if voters.is_empty() {
+            return Err(MembershipError::EmptyConfiguration);
+        }
+        let mut applied = self.applied.subscribe();
+        let (recv, joint_index) = {
+            let election = self.election.read().await;
+            if !self.p_status.is_leader().await {
+                return Err(MembershipError::NotLeader(self.p_status.get_leader().await));
+            }
+            let mut logs = self.logs.lock().await;
+            let mut membership = self.membership.write().await;
+            if membership.is_changing() || self.transfer.lock().unwrap().is_some() {
+                return Err(MembershipError::ChangeInProgress);
+            }
+            let joint = membership.active().joint(voters.clone());
+            let (entry, changes) = self
+                .append_configuration(&mut logs, &mut membership, election.current_term, joint)
+                .await
+                .map_err(MembershipError::Failed)?;
+            let recv = self.register_proposal(&entry).await;
+            drop(membership);
+            drop(logs);
+            drop(election);
+            self.configuration_appended(&entry, changes).await;
+            (recv, entry.index)
+        };
+        match recv.await {
+            Ok(Ok(_)) => trace!("joint configuration committed at {joint_index}"),
+            Ok(Err(ProposeError::Failed(err))) => return Err(MembershipError::Failed(err)),
+            _ => return Err(MembershipError::LeadershipLost),
+        }
+
+        // The leader loop appends the new configuration
+        let committed = async {
+            loop {
+                {
+                    let membership = self.membership.read().await;
+                    let committed = &membership.committed;
+                    if membership.committed_index > joint_index
+                        && !committed.is_joint()
+                        && committed.voters == voters
+                    {
+                        break membership.committed_index;
+                    }
+                }
+                let _ = applied.changed().await;
+            }
+        };
+        tokio::select! {
+            biased;
+            index = committed => Ok(index),
+            _ = self.p_status.wait_while(EStatus::Leader) => Err(MembershipError::LeadershipLost),
+        }
+    }
+
+    /// Remove the voter or the learner `node` from the cluster. Resolve with
+    /// the index of the new configuration when it's committed.
+    ///
+    /// # Error
+    /// Same as `Node::change_membership`, and `UnknownNode` if the node
+    /// isn't a member.
+    pub async fn remove_member(&self, node: String) -> Result<CommittedIndex, MembershipError> {
+        if !self.p_status.is_leader().await {
+            return Err(MembershipError::NotLeader(self.p_status.get_leader().await));
+        }
+        let active = self.membership.read().await.active().clone();
+        if active.is_learner(&node) {
+            trace!("remove {node} from the learners");
+            let mut configuration = active;
+            configuration.learners.remove(&node);
+            return self.change_learners(configuration).await;
+        }
+        let mut voters = active.voters;
+        if !voters.remove(&node) {
+            return Err(MembershipError::UnknownNode(node));
+        }
+        trace!("remove {node} from the voters");
+        self.change_membership(voters).await
+    }
+
+    /// Promote the learner `node` to the voters. Resolve with the index of
+    /// the new configuration when it's committed.
+    ///
+    /// # Error
+    /// Same as `Node::change_membership`, `UnknownNode` if the node isn't a
+    /// learner and `NotCaughtUp` if it doesn't have all the committed
+    /// entries yet.
+    pub async fn promote_learner(&self, node: String) -> Result<CommittedIndex, MembershipError> {
+        if !self.p_status.is_leader().await {
+            return Err(MembershipError::NotLeader(self.p_status.get_leader().await));
+        }
+        let active = self.membership.read().await.active().clone();
+        if !active.is_learner(&node) {
+            return Err(MembershipError::UnknownNode(node));
+        }
+        let commit_index = self.logs.lock().await.commit_index();
+        if !self.is_caught_up(&node, commit_index).await {
+            return Err(MembershipError::NotCaughtUp(node));
+        }
+        trace!("promote {node} to the voters");
+        let mut voters = active.voters;
+        voters.insert(node);
+        self.change_membership(voters).await
+    }
+
+    /// Replace the active configuration by `configuration`, the voters are
+    /// the same. The learners don't count in the quorums, the change doesn't
+    /// need a joint consensus.
+    async fn change_learners(
+        &self,
+        configuration: Configuration,
+    ) -> Result<CommittedIndex, MembershipError> {
+        let recv = {
+            let election = self.election.read().await;
+            if !self.p_status.is_leader().await {
+                return Err(MembershipError::NotLeader(self.p_status.get_leader().await));
+            }
+            let mut logs = self.logs.lock().await;
+            let mut membership = self.membership.write().await;
+            if membership.is_changing() || self.transfer.lock().unwrap().is_some() {
+                return Err(MembershipError::ChangeInProgress);
+            }
+            let (entry, changes) = self
+                .append_configuration(
+                    &mut logs,
+                    &mut membership,
+                    election.current_term,
+                    configuration,
+                )
+                .await
+                .map_err(MembershipError::Failed)?;
+            let recv = self.register_proposal(&entry).await;
+            drop(membership);
+            drop(logs);
+            drop(election);
+            self.configuration_appended(&entry, changes).await;
+            recv
+        };
+        match recv.await {
+            Ok(Ok(index)) => Ok(index),
+            Ok(Err(ProposeError::Failed(err))) => Err(MembershipError::Failed(err)),
+            _ => Err(MembershipError::LeadershipLost),
+        }
+    }
+
+    /// True if the leader knows the `node` has all the entries committed up
+    /// to `commit_index`
+    async fn is_caught_up(&self, node: &str, commit_index: usize) -> bool {
+        let next_indexes = self.next_indexes.read().await;
+        matches!(
+            next_indexes.get(&node.to_string().into()),
+            Some(Validated(index)) if *index >= commit_index
+        )
+    }
+
+    /// Run by a pure follower, ask the leader to move the node to the
+    /// learners until it isn't a voter anymore. A pure follower promoted
+    /// later stays a voter. Never returns.
+    pub(crate) async fn leave_the_voters(&self) {
+        while self.is_voter().await {
+            if let Some(leader) = self.p_status.get_leader().await {
+                trace!("ask {leader} to leave the voters");
+                if let Err(warn) = self.transport.update_node(&leader, self.uuid, true).await {
+                    debug!("failed to reach the leader {leader}, {:?}", warn);
+                }
+            }
+            tokio::time::sleep(self.randomized_timeout()).await;
+        }
+        trace!("not a voter of the cluster");
+        std::future::pending().await
+    }
+
+    /// True if the local node votes in the active configuration
+    pub(crate) async fn is_voter(&self) -> bool {
+        let membership = self.membership.read().await;
+        membership.active().is_voter(&self.node_url())
+    }
+
+    /// Called by the leader at each sending session of the election `term`.
+    /// Append C_new when the joint configuration is committed, or make
+    /// progress with the waiting nodes if no change is in progress.
+    pub(crate) async fn advance_membership(&self, term: usize) -> ErrorResult<()> {
+        let election = self.election.read().await;
+        if election.current_term != term
+            || !self.p_status.is_leader().await
+            || self.transfer.lock().unwrap().is_some()
+        {
+            return Ok(());
+        }
+        let mut logs = self.logs.lock().await;
+        let mut membership = self.membership.write().await;
+        let configuration = if membership.committed.is_joint() && membership.pending.is_empty() {
+            membership.committed.leave_joint()
+        } else if membership.is_changing() {
+            return Ok(());
+        } else {
+            match self
+                .join_waiting_node(logs.commit_index(), &membership)
+                .await
+            {
+                Some(configuration) => configuration,
+                None => return Ok(()),
+            }
+        };
+        let (entry, changes) = self
+            .append_configuration(&mut logs, &mut membership, term, configuration)
+            .await?;
+        drop(membership);
+        drop(logs);
+        drop(election);
+        self.configuration_appended(&entry, changes).await;
+        Ok(())
+    }
+
+    /// Next configuration for the nodes waiting to join the cluster. A new
+    /// node is added to the learners, a learner that caught up with
+    /// `commit_index` is promoted to the voters unless it's a pure follower.
+    async fn join_waiting_node(
+        &self,
+        commit_index: usize,
+        membership: &Membership,
+    ) -> Option<Configuration> {
+        let active = membership.active();
+        let mut waiting_nodes = self.waiting_nodes.lock().await;
+        waiting_nodes.retain(|node| !active.has_joined(&node.addr, node.learner));
+        if let Some(node) = waiting_nodes
+            .iter()
+            .find(|node| node.learner && active.is_voter(&node.addr))
+        {
+            let mut voters = active.voters.clone();
+            voters.remove(&node.addr);
+            if !voters.is_empty() {
+                trace!("move {} from the voters to the learners", node.addr);
+                let mut configuration = active.joint(voters);
+                configuration.learners.insert(node.addr.clone());
+                return Some(configuration);
+            }
+        }
+        if let Some(node) = waiting_nodes
+            .iter()
+            .find(|node| !active.is_learner(&node.addr) && !active.is_voter(&node.addr))
+        {
+            trace!("add {} to the learners", node.addr);
+            let mut configuration = active.clone();
+            configuration.learners.insert(node.addr.clone());
+            return Some(configuration);
+        }
+        for (i, node) in waiting_nodes.iter().enumerate() {
+            if self.is_caught_up(&node.addr, commit_index).await {
+                trace!("promote {} to the voters", node.addr);
+                let mut voters = active.voters.clone();
+                voters.insert(node.addr.clone());
+                waiting_nodes.remove(i);
+                return Some(active.joint(voters));
+            }
+        }
+        None
+    }
+
+    /// Append a configuration entry as leader, the configuration is used
+    /// right away. Return the entry and the changes of the members, given
+    /// to `configuration_appended` once the locks are released.
+    async fn append_configuration(
+        &self,
+        logs: &mut Entries,
+        membership: &mut Membership,
+        term: usize,
+        configuration: Configuration,
+    ) -> ErrorResult<(Term, Vec<MemberChange>)> {
+        let entry = logs.append_configuration(term, configuration.clone())?;
+        debug!(
+            "configuration {:?} appended at {}",
+            configuration, entry.index
+        );
+        let previous = membership.active().clone();
+        membership.append(entry.index, configuration);
+        let changes = member_changes(&previous, membership.active());
+        self.sync_peers(membership).await;
+        Ok((entry, changes))
+    }
+
+    /// Call the hook for a configuration `entry` appended by the leader,
+    /// without holding the logs and the membership
+    async fn configuration_appended(&self, entry: &Term, changes: Vec<MemberChange>) {
+        self.notify_members(changes).await;
+        if let Err(err) = self.hook.append_term(entry).await {
+            self.leader_hook_failed("append_term", &err).await;
+        }
+    }
+
+    /// Track the configuration entries of the `logs` from the index `from`,
+    /// the configurations of the entries removed from the logs are
+    /// forgotten. Return the changes of the members, the caller gives them
+    /// to `notify_members` once it released the logs.
+    pub(crate) async fn track_configurations(
+        &self,
+        logs: &Entries,
+        from: usize,
+    ) -> Vec<MemberChange> {
+        let mut membership = self.membership.write().await;
+        membership.truncate(from);
+        let mut changes = vec![];
+        for entry in logs.range(from, logs.last_index()) {
+            if let EntryKind::Configuration(configuration) = entry.kind {
+                let previous = membership.active().clone();
+                membership.append(entry.index, configuration);
+                changes.extend(member_changes(&previous, membership.active()));
+            }
+        }
+        membership.commit(logs.commit_index());
+        self.sync_peers(&membership).await;
+        changes
+    }
+
+    /// The entries up to `commit_index` are committed, the nodes removed by
+    /// a committed configuration don't receive the terms anymore.
+    pub(crate) async fn commit_configurations(&self, commit_index: usize) {
+        let mut membership = self.membership.write().await;
+        let committed_index = membership.committed_index;
+        membership.commit(commit_index);
+        if membership.committed_index != committed_index {
+            trace!("configuration committed at {}", membership.committed_index);
+            self.sync_peers(&membership).await;
+        }
+    }
+
+    /// Call the hook for each node added or removed, in order
+    pub(crate) async fn notify_members(&self, changes: Vec<MemberChange>) {
+        for change in changes {
+            match change {
+                MemberChange::Added(node) => {
+                    debug!("{node} added to the cluster");
+                    self.hook.post_add_connection(&node).await;
+                }
+                MemberChange::Removed(node) => {
+                    debug!("{node} removed from the cluster");
+                    self.hook.remove_connection(&node).await;
+                }
+            }
+        }
+    }
+
+    /// Update the `node_list` with the members of the active configuration,
+    /// and of the committed one until the change is committed
+    async fn sync_peers(&self, membership: &Membership) {
+        let mut peers = membership.active().members();
+        peers.extend(membership.committed.members());
+        peers.remove(&self.node_url());
+        let mut node_list = self.node_list.write().await;
+        if *node_list != peers {
+            debug!("members of the cluster {:?}", peers);
+            *node_list = peers;
+        }
+    }
+}
+
+/// Node added to or removed from the cluster, see `Node::notify_members`
+pub(crate) enum MemberChange {
+    Added(String),
+    Removed(String),
+}
+
+/// Nodes added and removed when the `previous` configuration is replaced by
+/// the `active` one. The voters are added or removed when the joint
+/// consensus ends.
+fn member_changes(previous: &Configuration, active: &Configuration) -> Vec<MemberChange> {
+    if active.is_joint() {
+        return vec![];
+    }
+    // The new voters of a joint configuration aren't added yet
+    let mut before = previous.voters.clone();
+    before.extend(previous.learners.iter().cloned());
+    let active = active.members();
+    let previous_members = previous.members();
+    let added = active.difference(&before).cloned().map(MemberChange::Added);
+    let removed = previous_members
+        .difference(&active)
+        .cloned()
+        .map(MemberChange::Removed);
+    added.chain(removed).collect()
+}
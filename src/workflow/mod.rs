@@ -8,10 +8,16 @@
 pub mod append_term;
 pub mod candidate;
 pub mod follower;
+pub mod health;
 pub mod init;
+pub mod install_snapshot;
 pub mod leader;
+pub mod membership;
+pub mod propose;
+pub mod read_index;
 pub mod request_vote;
 mod tools;
+pub mod transfer;
 
 #[cfg(test)]
 mod test;
@@ -0,0 +1,123 @@
+// License:
+// This source code is licensed under the GPLv3 license, you can found the
+// LICENSE file in the root directory of this source tree.
+
+//! # PROPOSE
+//!
+//! Entry point of the clients to put a content in the logs. The leader
+//! appends the content to its logs, the entry is replicated with the next
+//! append term requests, and the proposal resolves when the entry is
+//! committed.
+
+use crate::{
+    common::error::ProposeError,
+    log_entry::{Entries, Term},
+    node::Node,
+};
+use tokio::sync::oneshot;
+use tracing::trace;
+
+/// Index of a committed entry in the logs
+pub type CommittedIndex = usize;
+
+/// Proposal waiting for the commit of its entry
+pub(crate) struct Proposal {
+    /// Election term of the proposed entry
+    term: usize,
+    sender: oneshot::Sender<Result<CommittedIndex, ProposeError>>,
+}
+
+impl Node {
+    /// Propose a `content` to the cluster. Resolve with the index of the
+    /// entry when it's committed.
+    ///
+    /// # Error
+    /// - `NotLeader` if the node isn't the leader, with the leader it knows
+    /// - `LeadershipLost` if the node lost the lead before the commit of the
+    ///   entry, the next leader commits it or replaces it
+    /// - `TransferInProgress` if the leader is transferring its lead
+    /// - `Failed` if the entry can't be appended to the logs
+    ///
+    /// The leader steps down if the `append_term` hook fails, the proposal
+    /// resolves like the proposals of a leader that lost the lead.
+    pub async fn propose(
+        &self,
+        content: impl Into<Vec<u8>>,
+    ) -> Result<CommittedIndex, ProposeError> {
+        let recv = {
+            // Hold the election state, the entry is created in the term we
+            // lead
+            let election = self.election.read().await;
+            if !self.p_status.is_leader().await {
+                return Err(ProposeError::NotLeader(self.p_status.get_leader().await));
+            }
+            if self.transfer.lock().unwrap().is_some() {
+                return Err(ProposeError::TransferInProgress);
+            }
+            let mut logs = self.logs.lock().await;
+            let term = logs
+                .append(election.current_term, content.into())
+                .map_err(ProposeError::Failed)?;
+            trace!("proposal appended at {}", term.index);
+            let recv = self.register_proposal(&term).await;
+            std::mem::drop(logs);
+            std::mem::drop(election);
+            if let Err(err) = self.hook.append_term(&term).await {
+                self.leader_hook_failed("append_term", &err).await;
+            }
+            recv
+        };
+        recv.await.unwrap_or(Err(ProposeError::LeadershipLost))
+    }
+
+    /// Wait for the commit of an `entry` the leader just appended, the
+    /// receiver resolves like a proposal.
+    pub(crate) async fn register_proposal(
+        &self,
+        entry: &Term,
+    ) -> oneshot::Receiver<Result<CommittedIndex, ProposeError>> {
+        let (sender, recv) = oneshot::channel();
+        self.proposals.lock().await.insert(
+            entry.index,
+            Proposal {
+                term: entry.term,
+                sender,
+            },
+        );
+        // The node may have stepped down meanwhile, after failing the
+        // proposals
+        if !self.p_status.is_leader().await {
+            self.fail_proposals().await;
+        }
+        recv
+    }
+
+    /// Resolve the proposals up to the commit index of the `logs`. A
+    /// proposal succeed if the committed entry is the proposed one, or if
+    /// it has been compacted in a snapshot.
+    pub(crate) async fn resolve_proposals(&self, logs: &Entries) {
+        let compacted = logs.compacted().map_or(0, |term| term.index);
+        let mut proposals = self.proposals.lock().await;
+        let pending = proposals.split_off(&(logs.commit_index() + 1));
+        let resolved = std::mem::replace(&mut *proposals, pending);
+        for (index, proposal) in resolved {
+            let result = match logs.find(index) {
+                Some(term) if term.term == proposal.term => Ok(index),
+                None if index <= compacted => Ok(index),
+                _ => Err(ProposeError::LeadershipLost),
+            };
+            trace!("proposal at {index} resolved, {:?}", result);
+            let _ = proposal.sender.send(result);
+        }
+    }
+
+    /// Fail the pending proposals when the node loses the lead, the leader
+    /// may never commit again.
+    pub(crate) async fn fail_proposals(&self) {
+        let proposals = std::mem::take(&mut *self.proposals.lock().await);
+        for (index, proposal) in proposals {
+            trace!("proposal at {index} failed, the lead is lost");
+            let _ = proposal.sender.send(Err(ProposeError::LeadershipLost));
+        }
+    }
+}
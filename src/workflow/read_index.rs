@@ -0,0 +1,74 @@
+// License:
+// This source code is licensed under the GPLv3 license, you can found the
+// LICENSE file in the root directory of this source tree.
+
+//! # READ INDEX
+//!
+//! Linearizable reads. Before serving a read, the leader checks that it's
+//! still the leader of the cluster, a deposed leader could serve stale data
+//! otherwise.
+//!
+//! 1. The leader waits for the commit of an entry in its own term, its
+//!    commit index is up to date after it. That commit index is the read
+//!    index.
+//! 2. It waits for a sending session that started after the call and was
+//!    acknowledged by a majority of the cluster.
+//! 3. It waits until the entries up to the read index are applied, then the
+//!    application can read its state machine.
+
+use crate::{common::error::ReadIndexError, node::Node, state::EStatus, CommittedIndex};
+use tracing::trace;
+
+/// Sending sessions of the leader, see `Node::internal_run_leader`
+#[derive(Clone, Copy, Debug, Default)]
+pub(crate) struct Rounds {
+    /// Latest session started
+    pub started: usize,
+    /// Latest session acknowledged by a majority of the cluster
+    pub confirmed: usize,
+}
+
+impl Node {
+    /// Wait until the node can serve a linearizable read, return the read
+    /// index. Every entry committed before the call is applied when it
+    /// returns.
+    ///
+    /// # Error
+    /// - `NotLeader` if the node isn't the leader, with the leader it knows
+    /// - `LeadershipLost` if the node isn't the leader anymore before the
+    ///   read is confirmed
+    pub async fn read_index(&self) -> Result<CommittedIndex, ReadIndexError> {
+        let mut rounds = self.rounds.subscribe();
+        let mut applied = self.applied.subscribe();
+        let (term, round) = {
+            let election = self.election.read().await;
+            if !self.p_status.is_leader().await {
+                return Err(ReadIndexError::NotLeader(self.p_status.get_leader().await));
+            }
+            (election.current_term, self.rounds.borrow().started + 1)
+        };
+        let read = async {
+            let read_index = loop {
+                {
+                    let logs = self.logs.lock().await;
+                    let commit_index = logs.commit_index();
+                    if logs.term_at(commit_index) == Some(term) {
+                        break commit_index;
+                    }
+                }
+                trace!("read index waits a commit in the term {term}");
+                let _ = applied.changed().await;
+            };
+            let _ = rounds.wait_for(|rounds| rounds.confirmed >= round).await;
+            let _ = applied.wait_for(|index| *index >= read_index).await;
+            read_index
+        };
+        tokio::select! {
+            read_index = read => {
+                trace!("read index {read_index} confirmed");
+                Ok(read_index)
+            }
+            _ = self.p_status.wait_while(EStatus::Leader) => Err(ReadIndexError::LeadershipLost),
+        }
+    }
+}
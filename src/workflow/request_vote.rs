@@ -9,42 +9,132 @@
 
 use crate::{
     api::io_msg::{RequestVoteInput, RequestVoteResult},
+    state::StatusEvent,
     Node,
 };
 
-use tracing::{debug, trace};
+use std::time::Duration;
+use tracing::{debug, error, trace};
 
 impl Node {
     /// Node reaction on receive a vote request.
+    ///
+    /// 1. Reply false if term < currentTerm (§5.1)
+    /// 2. If term > currentTerm, update currentTerm, forget the previous
+    ///    vote and step down (§5.1)
+    /// 3. If votedFor is null or candidateId, and candidate’s log is at
+    ///    least as up-to-date as receiver’s log, grant vote (§5.2, §5.4)
+    /// 4. Notify the `request_vote` hook of the answer, without waiting for
+    ///    it
     pub async fn receive_request_vote(&self, input: RequestVoteInput) -> RequestVoteResult {
         trace!("receive a vote request {:#?}", input);
-        let current_term = self.logs.lock().await.current_term();
-        // todo: hook receive request vote
-        if input.term.id < current_term.id {
+        let candidate = input.candidate_id.clone();
+        let (result, events) = self.internal_receive_request_vote(input).await;
+        self.notify_status(events).await;
+        let granted = result.vote_granted;
+        self.observe(move |hook| async move { hook.request_vote(&candidate, granted).await });
+        result
+    }
+
+    /// Answer the vote request, return the hook events of the status change
+    /// to fire once the election state is released
+    async fn internal_receive_request_vote(
+        &self,
+        input: RequestVoteInput,
+    ) -> (RequestVoteResult, Vec<StatusEvent>) {
+        let mut election = self.election.write().await;
+        if input.term < election.current_term {
             debug!("refuse candidates because term < current");
-            return RequestVoteResult {
-                current_term,
+            let result = RequestVoteResult {
+                current_term: election.current_term,
                 vote_granted: false,
             };
+            return (result, vec![]);
+        }
+        let previous = election.clone();
+        let mut events = vec![];
+        if election.update_term(input.term) {
+            debug!("newer term {} from a candidate", input.term);
+            match self.step_down().await {
+                Ok(stepped_down) => events = stepped_down,
+                Err(err) => error!("unable to step down: {:?}", err),
+            }
         }
-        let mut opt_vote = self.vote_for.write().await;
 
-        let vote_granted = if let Some(vote) = opt_vote.clone() {
-            debug!("compare to previous vote");
-            input.last_term > vote.1 || input.candidate_id == vote.0
-        } else {
-            debug!("compare to previous vote");
-            input.last_term >= self.logs.lock().await.commit_index()
-        };
+        let vote_granted = self.is_up_to_date(&input).await
+            && match &election.vote_for {
+                Some(vote) => *vote == input.candidate_id,
+                None => true,
+            };
 
         debug!("vote granted: {vote_granted}");
         if vote_granted {
-            *opt_vote = Some((input.candidate_id, input.last_term));
+            election.vote_for = Some(input.candidate_id);
+        }
+        if *election != previous {
+            // The vote has to be on the stable storage before we answer,
+            // otherwise we may vote twice after a restart.
+            if let Err(err) = self.persist_hard_state(&election).await {
+                error!("refuse vote, unable to persist it: {:?}", err);
+                *election = previous;
+                let result = RequestVoteResult {
+                    current_term: election.current_term,
+                    vote_granted: false,
+                };
+                return (result, events);
+            }
+        }
+        if vote_granted {
             self.reset_timeout().await
         }
+        let result = RequestVoteResult {
+            current_term: election.current_term,
+            vote_granted,
+        };
+        (result, events)
+    }
+
+    /// Node reaction on receive a pre vote request. The candidate asks if we
+    /// would vote for it before incrementing its term, nothing changes
+    /// locally (§9.6 of the raft thesis).
+    ///
+    /// 1. Reply false if the proposed term < currentTerm
+    /// 2. Reply false if we are the leader, or if we heard from a leader
+    ///    less than `timeout_min` ago
+    /// 3. Grant if the candidate’s log is at least as up-to-date as
+    ///    receiver’s log
+    pub async fn receive_pre_vote(&self, input: RequestVoteInput) -> RequestVoteResult {
+        trace!("receive a pre vote request {:#?}", input);
+        let election = self.election.read().await;
+        let refuse = RequestVoteResult {
+            current_term: election.current_term,
+            vote_granted: false,
+        };
+        if input.term < election.current_term {
+            debug!("refuse pre vote because term < current");
+            return refuse;
+        }
+        let min_timeout = Duration::from_millis(self.settings.timeout_min as u64);
+        let leader_alive = match *self.leader_contact.lock().unwrap() {
+            Some(contact) => contact.elapsed() < min_timeout,
+            None => false,
+        };
+        if leader_alive || self.p_status.is_leader().await {
+            debug!("refuse pre vote, the leader is alive");
+            return refuse;
+        }
+        let vote_granted = self.is_up_to_date(&input).await;
+        debug!("pre vote granted: {vote_granted}");
         RequestVoteResult {
-            current_term: self.logs.lock().await.current_term(),
+            current_term: election.current_term,
             vote_granted,
         }
     }
+
+    /// §5.4.1, the log with the later last term is more up-to-date, if the
+    /// logs end with the same term, the longer log is.
+    async fn is_up_to_date(&self, input: &RequestVoteInput) -> bool {
+        let logs = self.logs.lock().await;
+        (input.last_log_term, input.last_log_index) >= (logs.last_term(), logs.last_index())
+    }
 }
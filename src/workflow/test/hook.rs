@@ -1,41 +1,136 @@
-use crate::{state::EStatus, Hook, Term};
+use crate::{
+    common::error::{HookError, HookResult},
+    state::EStatus,
+    AsyncHook, Term,
+};
+use async_trait::async_trait;
 
-use std::collections::VecDeque;
+use std::collections::{HashMap, VecDeque};
 use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+pub type RestoredSnapshots = Arc<Mutex<Vec<(usize, Vec<u8>)>>>;
+
+/// Errors returned by the hook, by name of the call
+pub type HookFailures = Arc<Mutex<HashMap<&'static str, HookError>>>;
 
 #[derive(Default)]
 pub struct TestHook {
     pub pre_append_terms: Arc<Mutex<VecDeque<usize>>>,
+    /// Snapshots restored by the node
+    pub restored: RestoredSnapshots,
+    /// Time the hook takes to restore a snapshot
+    pub restore_delay: Duration,
+    /// Nodes removed from the cluster
+    pub removed: Arc<Mutex<Vec<String>>>,
+    /// Nodes the node lost the connection with
+    pub lost: Arc<Mutex<Vec<String>>>,
+    /// Indexes of the committed terms
+    pub committed: Arc<Mutex<Vec<usize>>>,
+    /// The calls fail while they're in the map
+    pub failures: HookFailures,
+    /// Lifecycle events with their arguments, like `leader_change 127.0.0.1:4001`
+    pub events: Arc<Mutex<Vec<String>>>,
+}
+
+impl TestHook {
+    fn check(&self, call: &str) -> HookResult<()> {
+        match self.failures.lock().unwrap().get(call) {
+            Some(err) => Err(err.clone()),
+            None => Ok(()),
+        }
+    }
+
+    fn event(&self, event: String) {
+        self.events.lock().unwrap().push(event);
+    }
 }
 
-impl Hook for TestHook {
-    fn update_node(&self) -> bool {
-        true
+#[async_trait]
+impl AsyncHook for TestHook {
+    async fn update_node(&self) -> HookResult<()> {
+        self.check("update_node")
     }
 
-    fn pre_append_term(&self, _term: &Term) -> Option<usize> {
-        (&mut *self.pre_append_terms.lock().unwrap()).pop_back()
+    /// Pop the next prepared answer, accept the term if there is none
+    async fn pre_append_term(&self, term: &Term) -> HookResult<usize> {
+        self.check("pre_append_term")?;
+        Ok(self
+            .pre_append_terms
+            .lock()
+            .unwrap()
+            .pop_back()
+            .unwrap_or(term.index))
     }
 
-    fn append_term(&self, _term: &Term) -> bool {
-        true
+    async fn append_term(&self, _term: &Term) -> HookResult<()> {
+        self.check("append_term")
     }
 
-    fn commit_term(&self, _term: &Term) -> bool {
-        true
+    async fn commit_term(&self, term: &Term) -> HookResult<()> {
+        self.check("commit_term")?;
+        self.committed.lock().unwrap().push(term.index);
+        Ok(())
     }
 
-    fn prepare_term(&self) -> String {
-        String::default()
+    async fn prepare_term(&self) -> HookResult<Vec<u8>> {
+        self.check("prepare_term")?;
+        Ok(Vec::new())
+    }
+
+    async fn retreive_term(&self, _index: usize) -> HookResult<Term> {
+        Err(HookError::Failed("no term stored".into()))
+    }
+
+    async fn retreive_terms(&self, _from: usize, _to: usize) -> HookResult<Vec<Term>> {
+        Err(HookError::Failed("no term stored".into()))
+    }
+
+    async fn switch_status(&self, _status: EStatus) {}
+
+    async fn snapshot(&self, index: usize) -> HookResult<Option<Vec<u8>>> {
+        self.check("snapshot")?;
+        Ok(Some(format!("state at {index}").into_bytes()))
     }
 
-    fn retreive_term(&self, index: usize) -> Option<Term> {
-        None
+    async fn restore_snapshot(&self, index: usize, data: &[u8]) -> HookResult<()> {
+        self.check("restore_snapshot")?;
+        tokio::time::sleep(self.restore_delay).await;
+        self.restored.lock().unwrap().push((index, data.to_vec()));
+        Ok(())
     }
 
-    fn retreive_terms(&self, from: usize, to: usize) -> Option<Vec<Term>> {
-        None
+    async fn remove_connection(&self, node: &str) {
+        self.removed.lock().unwrap().push(node.to_string());
     }
 
-    fn switch_status(&self, status: EStatus) {}
+    async fn lost_connection(&self, node: &str) {
+        self.lost.lock().unwrap().push(node.to_string());
+    }
+
+    async fn leader_change(&self, leader: &str) {
+        self.event(format!("leader_change {leader}"));
+    }
+
+    async fn pre_add_connection(&self, node: &str) -> HookResult<()> {
+        self.check("pre_add_connection")?;
+        self.event(format!("pre_add_connection {node}"));
+        Ok(())
+    }
+
+    async fn post_add_connection(&self, node: &str) {
+        self.event(format!("post_add_connection {node}"));
+    }
+
+    async fn request_vote(&self, candidate: &str, granted: bool) {
+        self.event(format!("request_vote {candidate} {granted}"));
+    }
+
+    async fn receive_vote(&self, node: &str, granted: bool) {
+        self.event(format!("receive_vote {node} {granted}"));
+    }
+
+    async fn send_term(&self, node: &str, _last_index: usize, success: bool) {
+        self.event(format!("send_term {node} {success}"));
+    }
 }
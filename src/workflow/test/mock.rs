@@ -1,19 +1,26 @@
-use crate::{
-    api::io_msg::UpdateNodeResult,
-    common::{error::WarnResult, Url},
-    Node, Settings,
-};
+use crate::node::{generate_uuid, Node};
+use std::time::Duration;
 
-impl Node {
-    pub async fn send_init_update_node(
-        &self,
-        target: &Url,
-        settings: &Settings,
-        uuid: [u8; 16],
-    ) -> WarnResult<UpdateNodeResult> {
-        Ok(UpdateNodeResult {
-            leader_id: String::default(),
-            node_list: Vec::<String>::default(),
-        })
-    }
+/// Unique directory in the temporary folder, not created
+pub fn temp_data_dir() -> String {
+    let name: String = generate_uuid(&mut rand::thread_rng())
+        .iter()
+        .map(|b| format!("{b:02x}"))
+        .collect();
+    std::env::temp_dir()
+        .join(format!("hook-raft-{name}"))
+        .to_str()
+        .unwrap()
+        .to_string()
+}
+
+/// Wait until the `node` applied the entries up to `index`, and took the
+/// snapshot that may follow. A follower commits after its answer.
+pub async fn wait_applied(node: &Node, index: usize) {
+    let mut applied = node.applied.subscribe();
+    tokio::time::timeout(Duration::from_secs(5), applied.wait_for(|i| *i >= index))
+        .await
+        .expect("entries not applied in time")
+        .unwrap();
+    drop(node.committing.lock().await);
 }
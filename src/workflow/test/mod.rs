@@ -4,6 +4,23 @@
 
 mod hook;
 mod mock;
+mod simulation;
 mod tests_append_term;
+mod tests_coprocess;
+mod tests_health;
+mod tests_hook;
 mod tests_init;
-//mod tests_send_term;
+mod tests_learner;
+mod tests_log_store;
+mod tests_membership;
+mod tests_persistence;
+mod tests_propose;
+mod tests_read_index;
+mod tests_request_vote;
+mod tests_scripts;
+mod tests_send_term;
+mod tests_simulation;
+mod tests_snapshot;
+mod tests_state_machine;
+mod tests_transfer;
+mod tests_webhook;
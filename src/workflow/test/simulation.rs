@@ -0,0 +1,479 @@
+// License:
+// This source code is licensed under the GPLv3 license, you can found the
+// LICENSE file in the root directory of this source tree.
+
+//! Deterministic simulation of a cluster. The nodes run on a paused tokio
+//! clock and talk through a [SimNetwork] that drops, delays and partitions
+//! the messages. Every random decision, in the nodes or in the network, is
+//! drawn from generators derived from the simulation seed, so a failing
+//! seed replays the same run.
+//!
+//! Each message is delayed independently, two messages sent in a row can
+//! arrive in any order.
+//!
+//! The invariants of raft are checked after each step of the simulation:
+//! - election safety, at most one leader per election term
+//! - log matching, two logs that contain an entry with the same index and
+//!   term are identical up to that entry
+
+use crate::{
+    api::{
+        io_msg::{
+            AppendTermInput, AppendTermResult, InstallSnapshotInput, InstallSnapshotResult,
+            RequestVoteInput, RequestVoteResult, TimeoutNowInput, TimeoutNowResult,
+            UpdateNodeResult,
+        },
+        InMemoryNetwork, InMemoryTransport, Transport,
+    },
+    common::{
+        config::Settings,
+        error::{throw, ErrorResult, WarnResult, Warning},
+        Url,
+    },
+    log_entry::Term,
+    node::Node,
+    workflow::test::hook::{HookFailures, TestHook},
+};
+use async_trait::async_trait;
+use rand::{rngs::StdRng, Rng, SeedableRng};
+use std::{
+    collections::{BTreeMap, HashMap},
+    future::Future,
+    sync::{Arc, Mutex},
+    time::Duration,
+};
+use tokio::task::JoinHandle;
+
+/// Virtual time between two checks of the invariants
+const STEP: Duration = Duration::from_millis(10);
+
+/// Run `future` to completion in a fresh runtime with a paused clock. The
+/// clock jumps to the next timer each time the nodes are idle. All the
+/// tasks of the simulation are dropped with the runtime.
+pub fn simulate<F: Future>(future: F) -> F::Output {
+    tokio::runtime::Builder::new_current_thread()
+        .enable_all()
+        .start_paused(true)
+        .build()
+        .unwrap()
+        .block_on(future)
+}
+
+struct Faults {
+    rng: StdRng,
+    /// Probability to lose a message
+    drop_rate: f64,
+    /// Range of the delay of a message in milliseconds
+    delay: (u64, u64),
+    /// Group of each node, the nodes of different groups can't talk. The
+    /// nodes without group are in the group 0.
+    groups: HashMap<Url, usize>,
+}
+
+/// In memory network with faults injection, see the module documentation.
+#[derive(Clone)]
+pub struct SimNetwork {
+    network: InMemoryNetwork,
+    faults: Arc<Mutex<Faults>>,
+}
+
+impl SimNetwork {
+    pub fn new(seed: u64) -> Self {
+        Self {
+            network: InMemoryNetwork::new(),
+            faults: Arc::new(Mutex::new(Faults {
+                rng: StdRng::seed_from_u64(seed),
+                drop_rate: 0.,
+                delay: (0, 2),
+                groups: HashMap::new(),
+            })),
+        }
+    }
+
+    pub fn transport(&self, addr: &Url) -> SimTransport {
+        SimTransport {
+            inner: self.network.transport(addr.clone()),
+            addr: addr.clone(),
+            network: self.clone(),
+        }
+    }
+
+    pub fn set_drop_rate(&self, drop_rate: f64) {
+        self.faults.lock().unwrap().drop_rate = drop_rate;
+    }
+
+    pub fn set_delay(&self, min: u64, max: u64) {
+        self.faults.lock().unwrap().delay = (min, max);
+    }
+
+    /// Split the network, a node only reaches the nodes of its group. The
+    /// nodes that aren't in `groups` stay together.
+    pub fn partition(&self, groups: &[Vec<Url>]) {
+        let mut faults = self.faults.lock().unwrap();
+        faults.groups.clear();
+        for (i, group) in groups.iter().enumerate() {
+            for url in group {
+                faults.groups.insert(url.clone(), i + 1);
+            }
+        }
+    }
+
+    /// Remove the partitions
+    pub fn heal(&self) {
+        self.faults.lock().unwrap().groups.clear();
+    }
+
+    /// Carry a message from `from` to `to`. Wait for the delay of the
+    /// message, fail if it is lost on the way.
+    async fn route(&self, from: &Url, to: &Url) -> WarnResult<()> {
+        let (lost, delay) = {
+            let mut faults = self.faults.lock().unwrap();
+            let group = |url: &Url| faults.groups.get(url).copied().unwrap_or(0);
+            let partitioned = group(from) != group(to);
+            let drop_rate = faults.drop_rate;
+            let (min, max) = faults.delay;
+            let lost = faults.rng.gen_bool(drop_rate) || partitioned;
+            (lost, faults.rng.gen_range(min..=max))
+        };
+        tokio::time::sleep(Duration::from_millis(delay)).await;
+        if lost {
+            throw!(Warning::Timeout("message lost"))
+        }
+        Ok(())
+    }
+}
+
+/// Transport of a node in a [SimNetwork]. Both the request and the
+/// response go through the faults of the network.
+pub struct SimTransport {
+    inner: InMemoryTransport,
+    addr: Url,
+    network: SimNetwork,
+}
+
+#[async_trait]
+impl Transport for SimTransport {
+    async fn update_node(
+        &self,
+        target: &Url,
+        uuid: [u8; 16],
+        learner: bool,
+    ) -> WarnResult<UpdateNodeResult> {
+        self.network.route(&self.addr, target).await?;
+        let result = self.inner.update_node(target, uuid, learner).await?;
+        self.network.route(target, &self.addr).await?;
+        Ok(result)
+    }
+
+    async fn append_term(
+        &self,
+        target: &Url,
+        input: AppendTermInput,
+    ) -> WarnResult<AppendTermResult> {
+        self.network.route(&self.addr, target).await?;
+        let result = self.inner.append_term(target, input).await?;
+        self.network.route(target, &self.addr).await?;
+        Ok(result)
+    }
+
+    async fn request_vote(
+        &self,
+        target: &Url,
+        input: RequestVoteInput,
+    ) -> WarnResult<RequestVoteResult> {
+        self.network.route(&self.addr, target).await?;
+        let result = self.inner.request_vote(target, input).await?;
+        self.network.route(target, &self.addr).await?;
+        Ok(result)
+    }
+
+    async fn pre_vote(
+        &self,
+        target: &Url,
+        input: RequestVoteInput,
+    ) -> WarnResult<RequestVoteResult> {
+        self.network.route(&self.addr, target).await?;
+        let result = self.inner.pre_vote(target, input).await?;
+        self.network.route(target, &self.addr).await?;
+        Ok(result)
+    }
+
+    async fn install_snapshot(
+        &self,
+        target: &Url,
+        input: InstallSnapshotInput,
+    ) -> WarnResult<InstallSnapshotResult> {
+        self.network.route(&self.addr, target).await?;
+        let result = self.inner.install_snapshot(target, input).await?;
+        self.network.route(target, &self.addr).await?;
+        Ok(result)
+    }
+
+    async fn timeout_now(
+        &self,
+        target: &Url,
+        input: TimeoutNowInput,
+    ) -> WarnResult<TimeoutNowResult> {
+        self.network.route(&self.addr, target).await?;
+        let result = self.inner.timeout_now(target, input).await?;
+        self.network.route(target, &self.addr).await?;
+        Ok(result)
+    }
+
+    async fn serve(&self, node: Node) -> ErrorResult<()> {
+        self.inner.serve(node).await
+    }
+}
+
+/// Cluster of nodes running in a [SimNetwork]. Must be started inside
+/// [simulate].
+pub struct Simulation {
+    pub network: SimNetwork,
+    pub nodes: Vec<Node>,
+    /// Nodes removed from the cluster, seen by the hook of each node
+    pub removed: Vec<Arc<Mutex<Vec<String>>>>,
+    /// Nodes each node lost the connection with, seen by its hook
+    pub lost: Vec<Arc<Mutex<Vec<String>>>>,
+    /// Errors returned by the hook of each node
+    pub failures: Vec<HookFailures>,
+    /// Lifecycle events seen by the hook of each node
+    pub events: Vec<Arc<Mutex<Vec<String>>>>,
+    /// Settings of the nodes, except the addresses
+    settings: Settings,
+    /// Main loops of the nodes, they never stop in a simulation
+    handles: Vec<JoinHandle<ErrorResult<()>>>,
+    /// Leader seen in each election term
+    leaders: HashMap<usize, String>,
+    /// Entries seen committed, by index
+    committed: BTreeMap<usize, Term>,
+    /// Commit index of each node at the latest check
+    checked: Vec<usize>,
+    /// Seeds of the nodes that join the cluster later
+    rng: StdRng,
+}
+
+impl Simulation {
+    /// Spawn a cluster of `size` nodes, every random generator is derived
+    /// from `seed`.
+    pub fn start(size: usize, seed: u64) -> Self {
+        Self::start_with_settings(size, seed, Settings::default())
+    }
+
+    /// Same as `Simulation::start`, the nodes use the `settings` with their
+    /// own addresses.
+    pub fn start_with_settings(size: usize, seed: u64, settings: Settings) -> Self {
+        Self::start_with_followers(size, 0, seed, settings)
+    }
+
+    /// Same as `Simulation::start_with_settings`, the last `followers` nodes
+    /// are pure followers. Every node is in the `nodes` of the settings of
+    /// the others.
+    pub fn start_with_followers(
+        size: usize,
+        followers: usize,
+        seed: u64,
+        settings: Settings,
+    ) -> Self {
+        let mut rng = StdRng::seed_from_u64(seed);
+        let network = SimNetwork::new(rng.gen());
+        let urls: Vec<String> = (1..=size)
+            .map(|i| format!("127.0.0.1:{}", 4000 + i))
+            .collect();
+        let mut sim = Self {
+            network,
+            nodes: vec![],
+            removed: vec![],
+            lost: vec![],
+            failures: vec![],
+            events: vec![],
+            settings,
+            handles: vec![],
+            leaders: HashMap::new(),
+            committed: BTreeMap::new(),
+            checked: vec![],
+            rng,
+        };
+        for (i, url) in urls.iter().enumerate() {
+            let nodes = urls.iter().filter(|u| *u != url).cloned().collect();
+            let settings = Settings {
+                follower: i >= size - followers,
+                ..sim.settings.clone()
+            };
+            sim.spawn_node(url, nodes, settings);
+        }
+        sim
+    }
+
+    /// Spawn a new node that connects to the running cluster, return its
+    /// position in `nodes`
+    pub fn join(&mut self) -> usize {
+        self.join_with(false)
+    }
+
+    /// Spawn a new pure follower that connects to the running cluster, it
+    /// stays a learner
+    pub fn join_as_learner(&mut self) -> usize {
+        self.join_with(true)
+    }
+
+    fn join_with(&mut self, follower: bool) -> usize {
+        let url = format!("127.0.0.1:{}", 4001 + self.nodes.len());
+        let nodes = self.nodes.iter().map(|node| node.node_url()).collect();
+        let settings = Settings {
+            follower,
+            ..self.settings.clone()
+        };
+        self.spawn_node(&url, nodes, settings);
+        self.nodes.len() - 1
+    }
+
+    fn spawn_node(&mut self, url: &str, nodes: Vec<String>, settings: Settings) {
+        let settings = Settings {
+            port: Url::from(url.to_string()).get_port(),
+            nodes,
+            ..settings
+        };
+        let hook = TestHook::default();
+        self.removed.push(hook.removed.clone());
+        self.lost.push(hook.lost.clone());
+        self.failures.push(hook.failures.clone());
+        self.events.push(hook.events.clone());
+        self.checked.push(0);
+        let node = Node::new_with_settings_async(settings, hook)
+            .with_seed(self.rng.gen())
+            .with_transport(self.network.transport(&url.to_string().into()));
+        self.handles.push(node.clone().spawn());
+        self.nodes.push(node);
+    }
+
+    /// True if the `node` stopped after a fatal error of its hook
+    pub fn halted(&self, node: usize) -> bool {
+        self.handles[node].is_finished() && self.nodes[node].halt.borrow().is_some()
+    }
+
+    pub fn url(&self, node: usize) -> Url {
+        self.nodes[node].node_url().into()
+    }
+
+    /// Let the cluster run for `duration` of virtual time, check the
+    /// invariants at each step.
+    pub async fn run(&mut self, duration: Duration) {
+        let mut elapsed = Duration::ZERO;
+        while elapsed < duration {
+            tokio::time::sleep(STEP).await;
+            elapsed += STEP;
+            for (i, node) in self.nodes.iter().enumerate() {
+                assert!(
+                    !self.handles[i].is_finished() || self.halted(i),
+                    "{} stopped",
+                    node.node_url()
+                );
+            }
+            self.check_election_safety().await;
+            self.check_log_matching().await;
+            self.check_state_machine_safety().await;
+        }
+    }
+
+    /// Node leading the latest election term, if any
+    pub async fn leader(&self) -> Option<usize> {
+        let mut leader = None;
+        let mut latest_term = 0;
+        for (i, node) in self.nodes.iter().enumerate() {
+            let term = node.current_term().await;
+            if node.p_status.is_leader().await && term >= latest_term {
+                leader = Some(i);
+                latest_term = term;
+            }
+        }
+        leader
+    }
+
+    /// Smallest commit index of the cluster
+    pub async fn commit_index(&self) -> usize {
+        let mut committed = usize::MAX;
+        for node in self.nodes.iter() {
+            committed = committed.min(node.logs.lock().await.commit_index());
+        }
+        committed
+    }
+
+    /// Record the leader of each term, panic if two nodes lead the same term.
+    async fn check_election_safety(&mut self) {
+        for node in self.nodes.iter() {
+            // The status of a node never changes with its term, read both
+            // under the election lock
+            let election = node.election.read().await;
+            if !node.p_status.is_leader().await {
+                continue;
+            }
+            let leader = self
+                .leaders
+                .entry(election.current_term)
+                .or_insert_with(|| node.node_url());
+            assert_eq!(
+                *leader,
+                node.node_url(),
+                "two leaders in the term {}",
+                election.current_term
+            );
+        }
+    }
+
+    /// Record the committed entries, panic if a node commits another entry
+    /// at the same index.
+    async fn check_state_machine_safety(&mut self) {
+        for (i, node) in self.nodes.iter().enumerate() {
+            let logs = node.logs.lock().await;
+            let compacted = logs.compacted().map_or(0, |term| term.index);
+            let from = self.checked[i].max(compacted) + 1;
+            self.checked[i] = logs.commit_index();
+            for entry in logs.range(from, logs.commit_index()) {
+                let committed = self
+                    .committed
+                    .entry(entry.index)
+                    .or_insert_with(|| entry.clone());
+                assert_eq!(
+                    *committed,
+                    entry,
+                    "{} committed another entry at {}",
+                    node.node_url(),
+                    entry.index
+                );
+            }
+        }
+    }
+
+    /// Compare the logs of each pair of nodes, panic if they contain an
+    /// entry with the same index and term but differ before.
+    async fn check_log_matching(&self) {
+        let mut logs: Vec<BTreeMap<usize, Term>> = vec![];
+        for node in self.nodes.iter() {
+            let entries = node.logs.lock().await;
+            logs.push(
+                entries
+                    .range(1, entries.last_index())
+                    .into_iter()
+                    .map(|term| (term.index, term))
+                    .collect(),
+            );
+        }
+        for (i, a) in logs.iter().enumerate() {
+            for b in logs.iter().skip(i + 1) {
+                let matching = a
+                    .iter()
+                    .rev()
+                    .find(|(index, term)| b.get(index).map(|t| t.term) == Some(term.term));
+                let last = match matching {
+                    Some((index, _)) => *index,
+                    None => continue,
+                };
+                for (index, term) in a.range(..=last) {
+                    if let Some(other) = b.get(index) {
+                        assert_eq!(term, other, "logs don't match at {index}");
+                    }
+                }
+            }
+        }
+    }
+}
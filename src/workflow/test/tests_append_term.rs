@@ -3,16 +3,10 @@
 // LICENSE file in the root directory of this source tree.
 
 use crate::{
-    api::io_msg::AppendTermInput,
-    common::{config::Settings, scripts::DefaultHook},
-    log_entry::{Entries, Term},
-    node::Node,
-    state::Status,
-    workflow::test::hook::TestHook,
+    api::io_msg::AppendTermInput, common::config::Settings, log_entry::Term, node::Node,
+    state::Status, workflow::test::hook::TestHook,
 };
 use std::sync::{Arc, Mutex as StdMutex};
-use tokio::sync::Mutex;
-use tracing_test::traced_test;
 
 /*
 internal implementation of receive append term
@@ -38,10 +32,10 @@ fn get_simple_follower(leader_url: String) -> Node {
     };
     Node {
         p_status: Status::follower(leader_url.into()),
-        ..Node::new_with_settings(
+        ..Node::new_with_settings_async(
             settings,
             TestHook {
-                pre_append_terms: Arc::new(StdMutex::new(vec![1].into())),
+                pre_append_terms: Arc::new(StdMutex::new(vec![usize::MAX; 10].into())),
                 ..TestHook::default()
             },
         )
@@ -52,22 +46,25 @@ fn get_simple_follower(leader_url: String) -> Node {
 async fn tests_append_term() {
     let leader_url = String::from("10.10.10.10:1212");
     let node = get_simple_follower(leader_url.clone());
-    let _ = node
+    let res = node
         .receive_append_term(AppendTermInput {
-            term: Term::_new(1, "1st term"),
+            term: 1,
             leader_id: leader_url,
-            prev_term: Term::_new(1, "1st term"),
-            entries: vec![],
+            prev_log_index: 0,
+            prev_log_term: 0,
+            entries: vec![Term::_new(1, 1, "1st term")],
             leader_commit_index: 0,
         })
         .await
         .unwrap();
-
-    let curr_term: Term = node.logs.lock().await.current_term();
+    assert!(res.success);
 
     // The node is a follower so it should have accepted the term
-    // and current term id is 1.
-    assert_eq!(curr_term.id, 1)
+    // and the election term is now 1.
+    let logs = node.logs.lock().await;
+    assert_eq!(logs.last_index(), 1);
+    assert_eq!(logs.last_term(), 1);
+    assert_eq!(node.election.read().await.current_term, 1);
 }
 
 #[tokio::test]
@@ -82,17 +79,33 @@ async fn case_one_false() {
     // Setup the node with some terms. Response should be ok.
     let res1 = node
         .receive_append_term(AppendTermInput {
-            term: Term::_new(3, "3rd term"),
+            term: 3,
             leader_id: leader_url.clone(),
-            prev_term: Term::_new(1, "1st term"),
-            entries: vec![Term::_new(2, "2nd term")],
+            prev_log_index: 0,
+            prev_log_term: 0,
+            entries: vec![],
             leader_commit_index: 0,
         })
         .await
         .unwrap();
-
-    //assert_eq!(res1.current_term, 3);
+    assert_eq!(res1.current_term, 3);
     assert!(res1.success);
+
+    // An old leader is rejected
+    let res2 = node
+        .receive_append_term(AppendTermInput {
+            term: 2,
+            leader_id: leader_url.clone(),
+            prev_log_index: 0,
+            prev_log_term: 0,
+            entries: vec![Term::_new(1, 2, "old term")],
+            leader_commit_index: 0,
+        })
+        .await
+        .unwrap();
+    assert_eq!(res2.current_term, 3);
+    assert!(!res2.success);
+    assert_eq!(node.logs.lock().await.last_index(), 0);
 }
 
 #[tokio::test]
@@ -112,14 +125,51 @@ async fn case_one_false_setup_failure() {
 
     let res1 = node
         .receive_append_term(AppendTermInput {
-            term: Term::_new(3, "3rd term"),
+            term: 1,
             leader_id: leader_url.clone(),
-            prev_term: Term::_new(1, "1st term"),
-            entries: vec![/* missing Term 2 */],
+            prev_log_index: 2,
+            prev_log_term: 1,
+            entries: vec![/* missing Term 1 and 2 */ Term::_new(3, 1, "3rd term")],
+            leader_commit_index: 0,
+        })
+        .await
+        .unwrap();
+
+    assert!(!res1.success);
+    assert_eq!(res1.last_log_index, 0);
+}
+
+#[tokio::test]
+async fn case_three_conflict() {
+    /* ****
+    3. If an existing entry conflicts with a new one (same index
+       but different terms), delete the existing entry and all that
+       follow it (§5.3)
+    **** */
+    let leader_url = String::from("10.10.10.10:1212");
+    let node = get_simple_follower(leader_url.clone());
+    {
+        let mut logs = node.logs.lock().await;
+        for i in 1..=3 {
+            logs.append(1, format!("term {i}").into()).unwrap();
+        }
+    }
+
+    let res = node
+        .receive_append_term(AppendTermInput {
+            term: 2,
+            leader_id: leader_url,
+            prev_log_index: 1,
+            prev_log_term: 1,
+            entries: vec![Term::_new(2, 2, "new 2nd term")],
             leader_commit_index: 0,
         })
         .await
         .unwrap();
 
-    assert!(!res1.success)
+    assert!(res.success);
+    let logs = node.logs.lock().await;
+    assert_eq!(logs.last_index(), 2);
+    assert_eq!(logs.last_term(), 2);
+    assert_eq!(logs.find(2).unwrap().content, b"new 2nd term");
 }
@@ -0,0 +1,81 @@
+// License:
+// This source code is licensed under the GPLv3 license, you can found the
+// LICENSE file in the root directory of this source tree.
+use crate::{
+    common::error::HookError, log_entry::Term, workflow::test::mock::temp_data_dir, AsyncHook,
+    CoprocessHook,
+};
+use std::{fs, os::unix::fs::PermissionsExt, path::Path, time::Duration};
+
+/// Helper that records its pid at start, then answers the requests. It
+/// exits on `append_term`, hangs on `pre_append_term` and only restores the
+/// `state` snapshot.
+const HELPER: &str = r#"#!/bin/sh
+echo $$ >> "$1"
+while read -r line; do
+  id=$(echo "$line" | sed 's/^{"id":\([0-9]*\).*/\1/')
+  case "$line" in
+    *'"method":"prepare_term"'*|*'"method":"snapshot"'*)
+      echo "{\"id\":$id,\"result\":\"cHJlcGFyZWQ=\"}" ;;
+    *'"method":"restore_snapshot"'*'"data":"c3RhdGU="'*) echo "{\"id\":$id,\"result\":null}" ;;
+    *'"method":"restore_snapshot"'*)
+      echo "{\"id\":$id,\"error\":{\"kind\":\"rejected\",\"message\":\"unknown\"}}" ;;
+    *'"method":"commit_term"'*)
+      echo "{\"id\":$id,\"error\":{\"kind\":\"rejected\",\"message\":\"not yet\"}}" ;;
+    *'"method":"append_term"'*) exit 1 ;;
+    *'"method":"pre_append_term"'*) sleep 5 ;;
+    *) echo "{\"id\":$id,\"result\":null}" ;;
+  esac
+done
+"#;
+
+fn starts(pids: &Path) -> usize {
+    fs::read_to_string(pids).unwrap().lines().count()
+}
+
+#[tokio::test]
+async fn exchange_with_a_helper_process() {
+    let dir = temp_data_dir();
+    fs::create_dir_all(&dir).unwrap();
+    let script = Path::new(&dir).join("helper");
+    fs::write(&script, HELPER).unwrap();
+    fs::set_permissions(&script, fs::Permissions::from_mode(0o755)).unwrap();
+    let pids = Path::new(&dir).join("pids");
+    let hook = CoprocessHook::new(script.to_str().unwrap())
+        .with_args(vec![pids.to_str().unwrap().to_string()])
+        .with_timeout(Duration::from_millis(500));
+
+    // One process answers all the calls
+    assert_eq!(hook.prepare_term().await.unwrap(), b"prepared");
+    assert_eq!(hook.prepare_term().await.unwrap(), b"prepared");
+    assert!(hook.update_node().await.is_ok());
+    assert_eq!(
+        hook.commit_term(&Term::_new(1, 1, "")).await,
+        Err(HookError::Rejected("not yet".into()))
+    );
+    // The snapshots are base64 strings
+    assert_eq!(hook.snapshot(3).await.unwrap().unwrap(), b"prepared");
+    assert_eq!(hook.restore_snapshot(3, b"state").await, Ok(()));
+    assert_eq!(
+        hook.restore_snapshot(3, b"other").await,
+        Err(HookError::Rejected("unknown".into()))
+    );
+    assert_eq!(starts(&pids), 1);
+
+    // The helper restarts after a crash
+    assert!(matches!(
+        hook.append_term(&Term::_new(1, 1, "")).await,
+        Err(HookError::Failed(_))
+    ));
+    assert_eq!(hook.prepare_term().await.unwrap(), b"prepared");
+    assert_eq!(starts(&pids), 2);
+
+    // And after a call that times out
+    assert!(matches!(
+        hook.pre_append_term(&Term::_new(1, 1, "")).await,
+        Err(HookError::Failed(_))
+    ));
+    assert_eq!(hook.prepare_term().await.unwrap(), b"prepared");
+    assert_eq!(starts(&pids), 3);
+    fs::remove_dir_all(&dir).unwrap();
+}
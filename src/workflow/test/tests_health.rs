@@ -0,0 +1,123 @@
+// License:
+// This source code is licensed under the GPLv3 license, you can found the
+// LICENSE file in the root directory of this source tree.
+use crate::{
+    common::config::Settings,
+    health::PeerHealth,
+    workflow::test::simulation::{simulate, Simulation},
+};
+use std::time::Duration;
+use tokio::time::Instant;
+
+#[test]
+fn suspect_and_back_off() {
+    let settings = Settings {
+        suspect_after: 2,
+        send_term_period: 50,
+        max_backoff: 150,
+        ..Default::default()
+    };
+    let now = Instant::now();
+    let mut health = PeerHealth::default();
+    assert!(!health.fail(now, &settings));
+    assert!(!health.is_suspect());
+    assert!(health.should_contact(now));
+
+    // Suspect from the second failure, the delay doubles up to the maximum
+    assert!(health.fail(now, &settings));
+    assert!(health.is_suspect());
+    assert_eq!(health.retry_at, Some(now + Duration::from_millis(50)));
+    assert!(!health.should_contact(now));
+    assert!(!health.fail(now, &settings));
+    assert_eq!(health.retry_at, Some(now + Duration::from_millis(100)));
+    assert!(!health.fail(now, &settings));
+    assert_eq!(health.retry_at, Some(now + Duration::from_millis(150)));
+    assert!(health.should_contact(now + Duration::from_millis(150)));
+
+    // Only suspect peers are evicted, after the grace period
+    assert!(!health.should_evict(now + Duration::from_secs(60), &settings));
+    let settings = Settings {
+        evict_after: Some(1000),
+        ..settings
+    };
+    assert!(!health.should_evict(now + Duration::from_millis(999), &settings));
+    assert!(health.should_evict(now + Duration::from_millis(1000), &settings));
+
+    // An answer clears the suspicion
+    assert!(health.succeed(now, Duration::from_millis(8)));
+    assert!(!health.is_suspect());
+    assert_eq!(health.failures, 0);
+    assert_eq!(health.last_contact, Some(now));
+    assert!(health.should_contact(now));
+    assert!(!health.succeed(now, Duration::from_millis(16)));
+    assert_eq!(health.rtt, Some(Duration::from_millis(9)));
+}
+
+#[test]
+fn detect_a_dead_follower() {
+    simulate(async {
+        let mut sim = Simulation::start(5, 8);
+        sim.run(Duration::from_secs(2)).await;
+        let leader = sim.leader().await.expect("no leader elected");
+        let dead = (leader + 1) % 5;
+        let dead_url = sim.url(dead).to_string();
+        let committed = sim.nodes[leader].logs.lock().await.commit_index();
+
+        sim.network.partition(&[vec![sim.url(dead)]]);
+        sim.run(Duration::from_secs(3)).await;
+        assert_eq!(sim.leader().await, Some(leader));
+        assert_eq!(*sim.lost[leader].lock().unwrap(), vec![dead_url.clone()]);
+        let leader_url = sim.url(leader).to_string();
+        assert!(sim.lost[dead].lock().unwrap().contains(&leader_url));
+        let health = sim.nodes[leader].peer_health();
+        let dead_health = &health[&dead_url];
+        assert!(dead_health.is_suspect());
+        // The leader backs off, it doesn't call the dead node at each session
+        assert!(dead_health.failures < 10, "{} calls", dead_health.failures);
+        // The leader keeps committing without it, and doesn't remove it
+        assert!(sim.nodes[leader].logs.lock().await.commit_index() > committed);
+        assert!(sim.nodes[leader]
+            .membership
+            .read()
+            .await
+            .active()
+            .is_voter(&dead_url));
+        for (node, health) in health.iter().filter(|(node, _)| **node != dead_url) {
+            assert!(!health.is_suspect(), "{node} is suspect");
+            assert!(health.rtt.is_some());
+        }
+
+        // The node is healthy again once the leader reaches it
+        sim.network.heal();
+        sim.run(Duration::from_secs(2)).await;
+        let health = sim.nodes[leader].peer_health();
+        assert!(!health[&dead_url].is_suspect());
+        assert!(health[&dead_url].last_contact.is_some());
+    });
+}
+
+#[test]
+fn evict_a_dead_follower() {
+    simulate(async {
+        let settings = Settings {
+            evict_after: Some(1000),
+            ..Default::default()
+        };
+        let mut sim = Simulation::start_with_settings(5, 9, settings);
+        sim.run(Duration::from_secs(2)).await;
+        let leader = sim.leader().await.expect("no leader elected");
+        let dead = (leader + 2) % 5;
+        let dead_url = sim.url(dead).to_string();
+
+        sim.network.partition(&[vec![sim.url(dead)]]);
+        sim.run(Duration::from_secs(4)).await;
+        assert_eq!(sim.leader().await, Some(leader));
+        for (i, node) in sim.nodes.iter().enumerate().filter(|(i, _)| *i != dead) {
+            let membership = node.membership.read().await;
+            assert!(!membership.is_changing(), "{i} is changing");
+            assert!(!membership.committed.is_voter(&dead_url), "{i}");
+            assert_eq!(*sim.removed[i].lock().unwrap(), vec![dead_url.clone()]);
+        }
+        assert!(!sim.nodes[leader].peer_health().contains_key(&dead_url));
+    });
+}
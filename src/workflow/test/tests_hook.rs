@@ -0,0 +1,352 @@
+// License:
+// This source code is licensed under the GPLv3 license, you can found the
+// LICENSE file in the root directory of this source tree.
+use crate::{
+    api::io_msg::{AppendTermInput, RequestVoteInput},
+    common::error::{HookError, HookResult},
+    log_entry::Term,
+    node::{Node, NodeInfo},
+    state::{EStatus, Status},
+    workflow::test::{
+        hook::TestHook,
+        mock::wait_applied,
+        simulation::{simulate, Simulation},
+    },
+    AsyncHook, BlockingHook, Hook, Settings,
+};
+use std::{
+    sync::{
+        atomic::{AtomicUsize, Ordering},
+        Arc, Mutex,
+    },
+    time::Duration,
+};
+
+/// Synchronous hook that takes its time to commit, to follow the status and
+/// to observe the votes
+#[derive(Default)]
+struct SlowHook {
+    committed: Arc<Mutex<Vec<usize>>>,
+}
+
+impl Hook for SlowHook {
+    fn update_node(&self) -> HookResult<()> {
+        Ok(())
+    }
+
+    fn pre_append_term(&self, term: &Term) -> HookResult<usize> {
+        Ok(term.index)
+    }
+
+    fn append_term(&self, _term: &Term) -> HookResult<()> {
+        Ok(())
+    }
+
+    fn commit_term(&self, term: &Term) -> HookResult<()> {
+        std::thread::sleep(Duration::from_millis(200));
+        self.committed.lock().unwrap().push(term.index);
+        Ok(())
+    }
+
+    fn prepare_term(&self) -> HookResult<Vec<u8>> {
+        Ok(b"prepared".to_vec())
+    }
+
+    fn retreive_term(&self, index: usize) -> HookResult<Term> {
+        Ok(Term::_new(index, 1, "retrieved"))
+    }
+
+    fn retreive_terms(&self, _from: usize, _to: usize) -> HookResult<Vec<Term>> {
+        Err(HookError::Failed("not stored".to_string()))
+    }
+
+    fn switch_status(&self, _status: EStatus) {
+        std::thread::sleep(Duration::from_millis(200));
+    }
+
+    fn request_vote(&self, _candidate: &str, _granted: bool) {
+        std::thread::sleep(Duration::from_millis(200));
+    }
+}
+
+#[tokio::test]
+async fn a_blocking_hook_does_not_stall_the_runtime() {
+    let committed = Arc::new(Mutex::new(vec![]));
+    let hook = BlockingHook::new(SlowHook {
+        committed: committed.clone(),
+    });
+
+    // The single thread of the runtime keeps running the other tasks
+    let ticks = Arc::new(AtomicUsize::new(0));
+    let p_ticks = ticks.clone();
+    let ticker = tokio::spawn(async move {
+        loop {
+            tokio::time::sleep(Duration::from_millis(10)).await;
+            p_ticks.fetch_add(1, Ordering::SeqCst);
+        }
+    });
+    assert!(hook.commit_term(&Term::_new(3, 1, "")).await.is_ok());
+    ticker.abort();
+    assert!(ticks.load(Ordering::SeqCst) >= 5);
+    assert_eq!(*committed.lock().unwrap(), vec![3]);
+
+    // The results of the synchronous hook are forwarded
+    assert_eq!(hook.prepare_term().await.unwrap(), b"prepared");
+    assert_eq!(hook.retreive_term(4).await.unwrap().index, 4);
+    assert_eq!(hook.snapshot(4).await, Ok(None));
+    assert!(matches!(
+        hook.restore_snapshot(4, b"state").await,
+        Err(HookError::Rejected(_))
+    ));
+}
+
+#[tokio::test]
+async fn a_slow_hook_does_not_hold_the_logs() {
+    let leader = String::from("10.10.10.10:1212");
+    let committed = Arc::new(Mutex::new(vec![]));
+    let node = Node::test_new(
+        Settings::default(),
+        Status::follower(leader.clone().into()),
+        BlockingHook::new(SlowHook {
+            committed: committed.clone(),
+        }),
+    );
+    let input = AppendTermInput {
+        term: 1,
+        leader_id: leader,
+        prev_log_index: 0,
+        prev_log_term: 0,
+        entries: vec![Term::_new(1, 1, "1st"), Term::_new(2, 1, "2nd")],
+        leader_commit_index: 2,
+    };
+
+    // The follower answers without waiting for the hook
+    let res = tokio::time::timeout(Duration::from_millis(100), node.receive_append_term(input))
+        .await
+        .expect("reply held during the commit")
+        .unwrap();
+    assert!(res.success);
+
+    // The logs and the votes stay available while the hook commits the
+    // entries
+    for _ in 0..10 {
+        let logs = tokio::time::timeout(Duration::from_millis(20), node.logs.lock())
+            .await
+            .expect("logs held during the commit");
+        assert_eq!(logs.last_index(), 2);
+        drop(logs);
+        let vote = RequestVoteInput {
+            term: 1,
+            candidate_id: "10.10.10.11:1212".into(),
+            last_log_index: 2,
+            last_log_term: 1,
+        };
+        tokio::time::timeout(Duration::from_millis(20), node.receive_request_vote(vote))
+            .await
+            .expect("vote held during the commit");
+        tokio::time::sleep(Duration::from_millis(10)).await;
+    }
+    assert!(committed.lock().unwrap().len() < 2);
+    wait_applied(&node, 2).await;
+    assert_eq!(*committed.lock().unwrap(), vec![1, 2]);
+    assert_eq!(node.logs.lock().await.commit_index(), 2);
+}
+
+#[tokio::test]
+async fn a_slow_status_hook_does_not_hold_the_election() {
+    let leader = String::from("10.10.10.10:1212");
+    let node = Node::test_new(
+        Settings::default(),
+        Status::candidate(),
+        BlockingHook::new(SlowHook::default()),
+    );
+    let input = AppendTermInput {
+        term: 1,
+        leader_id: leader,
+        prev_log_index: 0,
+        prev_log_term: 0,
+        entries: vec![],
+        leader_commit_index: 0,
+    };
+    let follower = node.clone();
+    let append = tokio::spawn(async move { follower.receive_append_term(input).await });
+    while !node.p_status.is_follower().await {
+        tokio::time::sleep(Duration::from_millis(5)).await;
+    }
+
+    // The hook follows the new status without holding the election state,
+    // the vote doesn't wait for its observer
+    let vote = RequestVoteInput {
+        term: 1,
+        candidate_id: "10.10.10.11:1212".into(),
+        last_log_index: 0,
+        last_log_term: 0,
+    };
+    tokio::time::timeout(Duration::from_millis(50), node.receive_request_vote(vote))
+        .await
+        .expect("vote held by the status hook");
+    assert!(append.await.unwrap().unwrap().success);
+}
+
+#[tokio::test]
+async fn a_follower_rejects_the_terms_its_hook_fails() {
+    let leader = String::from("10.10.10.10:1212");
+    let hook = TestHook::default();
+    let failures = hook.failures.clone();
+    let node = Node::test_new(
+        Settings::default(),
+        Status::follower(leader.clone().into()),
+        hook,
+    );
+    let input = AppendTermInput {
+        term: 1,
+        leader_id: leader,
+        prev_log_index: 0,
+        prev_log_term: 0,
+        entries: vec![Term::_new(1, 1, "1st"), Term::_new(2, 1, "2nd")],
+        leader_commit_index: 2,
+    };
+
+    for call in ["pre_append_term", "append_term"] {
+        failures
+            .lock()
+            .unwrap()
+            .insert(call, HookError::Failed("unavailable".into()));
+        let res = node.receive_append_term(input.clone()).await.unwrap();
+        assert!(!res.success, "{call}");
+        assert_eq!(node.logs.lock().await.last_index(), 0);
+        failures.lock().unwrap().clear();
+    }
+
+    // The terms are committed once the hook commits them
+    failures
+        .lock()
+        .unwrap()
+        .insert("commit_term", HookError::Failed("unavailable".into()));
+    let res = node.receive_append_term(input.clone()).await.unwrap();
+    assert!(res.success);
+    tokio::time::sleep(Duration::from_millis(50)).await;
+    assert_eq!(node.logs.lock().await.last_index(), 2);
+    assert_eq!(node.logs.lock().await.commit_index(), 0);
+    failures.lock().unwrap().clear();
+    let res = node.receive_append_term(input).await.unwrap();
+    assert!(res.success);
+    wait_applied(&node, 2).await;
+    assert_eq!(node.logs.lock().await.commit_index(), 2);
+    assert!(node.halt.borrow().is_none());
+}
+
+#[test]
+fn the_leader_steps_down_when_its_hook_fails() {
+    simulate(async {
+        let mut sim = Simulation::start(3, 12);
+        sim.run(Duration::from_secs(1)).await;
+        let leader = sim.leader().await.expect("no leader elected");
+        let term = sim.nodes[leader].current_term().await;
+        sim.failures[leader]
+            .lock()
+            .unwrap()
+            .insert("append_term", HookError::Failed("disk full".into()));
+        sim.run(Duration::from_secs(1)).await;
+        // The node may be elected again, but it doesn't lead that term
+        let node = &sim.nodes[leader];
+        assert!(!(node.p_status.is_leader().await && node.current_term().await == term));
+
+        // The cluster goes on once its hook works
+        sim.failures[leader].lock().unwrap().clear();
+        let committed = sim.commit_index().await;
+        sim.run(Duration::from_secs(2)).await;
+        assert!(sim.leader().await.is_some());
+        assert!(sim.commit_index().await > committed);
+    });
+}
+
+#[test]
+fn a_fatal_hook_error_halts_the_node() {
+    simulate(async {
+        let mut sim = Simulation::start(3, 13);
+        sim.run(Duration::from_secs(1)).await;
+        let leader = sim.leader().await.expect("no leader elected");
+        let follower = (leader + 1) % 3;
+        let fatal = HookError::Fatal("corrupted state".into());
+        sim.failures[follower]
+            .lock()
+            .unwrap()
+            .insert("commit_term", fatal.clone());
+        sim.run(Duration::from_secs(1)).await;
+        assert!(sim.halted(follower));
+        assert_eq!(*sim.nodes[follower].halt.borrow(), Some(fatal));
+
+        // The other nodes go on without it
+        assert_eq!(sim.leader().await, Some(leader));
+        let committed = sim.nodes[leader].logs.lock().await.commit_index();
+        sim.run(Duration::from_secs(1)).await;
+        assert!(sim.nodes[leader].logs.lock().await.commit_index() > committed);
+    });
+}
+
+/// True if the hook of the `node` saw the `event`
+fn has(sim: &Simulation, node: usize, event: &str) -> bool {
+    sim.events[node]
+        .lock()
+        .unwrap()
+        .iter()
+        .any(|seen| seen == event)
+}
+
+#[test]
+fn notify_the_lifecycle_events() {
+    simulate(async {
+        let mut sim = Simulation::start(3, 14);
+        sim.run(Duration::from_secs(1)).await;
+        let leader = sim.leader().await.expect("no leader elected");
+        let leader_url = sim.url(leader).to_string();
+
+        let followers: Vec<usize> = (0..3).filter(|i| *i != leader).collect();
+        assert!(has(&sim, leader, &format!("leader_change {leader_url}")));
+        assert!(followers.iter().any(|i| has(
+            &sim,
+            leader,
+            &format!("receive_vote {} true", sim.url(*i))
+        )));
+        assert!(followers.iter().any(|i| has(
+            &sim,
+            *i,
+            &format!("request_vote {leader_url} true")
+        )));
+        for i in followers.iter() {
+            assert!(has(&sim, *i, &format!("leader_change {leader_url}")));
+            assert!(has(
+                &sim,
+                leader,
+                &format!("send_term {} true", sim.url(*i))
+            ));
+        }
+
+        // Every member sees the new node joining
+        let joined = sim.join();
+        let url = sim.url(joined).to_string();
+        sim.run(Duration::from_secs(2)).await;
+        assert!(has(&sim, leader, &format!("pre_add_connection {url}")));
+        for i in 0..3 {
+            assert!(has(&sim, i, &format!("post_add_connection {url}")), "{i}");
+        }
+    });
+}
+
+#[tokio::test]
+async fn the_leader_refuses_a_node_its_hook_rejects() {
+    let hook = TestHook::default();
+    hook.failures.lock().unwrap().insert(
+        "pre_add_connection",
+        HookError::Rejected("unknown node".into()),
+    );
+    let node = Node::test_new(Settings::default(), Status::leader(), hook);
+    let input = NodeInfo {
+        hash: [0; 16],
+        addr: "127.0.0.1:4010".to_string(),
+        learner: false,
+    };
+    assert!(node.receive_connection_request(input).await.is_none());
+    assert!(node.waiting_nodes.lock().await.is_empty());
+}
@@ -3,15 +3,8 @@
 // LICENSE file in the root directory of this source tree.
 
 use crate::{
-    common::{
-        config::Settings,
-        error::{Error, ErrorResult, Warning},
-    },
-    node::Node,
-    state::Status,
-    workflow::test::hook::TestHook,
+    api::InMemoryNetwork, common::config::Settings, node::Node, workflow::test::hook::TestHook,
 };
-use std::collections::VecDeque;
 
 #[tokio::test]
 async fn test_initialize_success() {
@@ -21,7 +14,8 @@ async fn test_initialize_success() {
         ..Default::default()
     };
 
-    let mut node = Node::new_with_settings(settings, TestHook::default());
+    let mut node = Node::new_with_settings_async(settings, TestHook::default())
+        .with_transport(InMemoryNetwork::new().transport("127.0.0.1:3000"));
     node.utest_data.error_result_bool = Some(Ok(true));
 
     assert!(node.initialize().await.is_ok());
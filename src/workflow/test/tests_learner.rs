@@ -0,0 +1,106 @@
+// License:
+// This source code is licensed under the GPLv3 license, you can found the
+// LICENSE file in the root directory of this source tree.
+use crate::{
+    common::{config::Settings, error::MembershipError},
+    workflow::test::simulation::{simulate, Simulation},
+};
+use std::{collections::BTreeSet, time::Duration};
+
+#[test]
+fn a_learner_does_not_vote() {
+    simulate(async {
+        let mut sim = Simulation::start(3, 10);
+        sim.run(Duration::from_secs(1)).await;
+        let leader = sim.leader().await.expect("no leader elected");
+        let learner = sim.join_as_learner();
+        let url = sim.url(learner).to_string();
+        sim.run(Duration::from_secs(2)).await;
+
+        // The learner receives the terms but stays out of the voters
+        for (i, node) in sim.nodes.iter().enumerate() {
+            let membership = node.membership.read().await;
+            assert_eq!(membership.committed.learners, BTreeSet::from([url.clone()]));
+            assert_eq!(membership.committed.voters.len(), 3, "voters of {i}");
+        }
+        let joined = sim.nodes[leader].membership.read().await.committed_index;
+        assert!(sim.nodes[learner].logs.lock().await.commit_index() > joined);
+        assert!(sim.nodes[learner].p_status.is_follower().await);
+
+        // The leader and the learner aren't a majority
+        sim.network
+            .partition(&[vec![sim.url(leader), sim.url(learner)]]);
+        sim.run(Duration::from_secs(2)).await;
+        assert!(!sim.nodes[leader].p_status.is_leader().await);
+        let new_leader = sim.leader().await.expect("no leader in the majority");
+        assert_ne!(new_leader, leader);
+        sim.network.heal();
+        sim.run(Duration::from_secs(2)).await;
+    });
+}
+
+#[test]
+fn promote_a_learner() {
+    simulate(async {
+        let mut sim = Simulation::start(3, 11);
+        sim.run(Duration::from_secs(1)).await;
+        let learner = sim.join_as_learner();
+        let url = sim.url(learner).to_string();
+        sim.run(Duration::from_secs(2)).await;
+        let leader = sim.leader().await.expect("no leader elected");
+
+        // Only a learner that caught up can be promoted
+        let voter = sim.url((leader + 1) % 3).to_string();
+        assert!(matches!(
+            sim.nodes[leader].promote_learner(voter).await,
+            Err(MembershipError::UnknownNode(_))
+        ));
+        sim.network.partition(&[vec![sim.url(learner)]]);
+        sim.run(Duration::from_millis(500)).await;
+        assert!(matches!(
+            sim.nodes[leader].promote_learner(url.clone()).await,
+            Err(MembershipError::NotCaughtUp(_))
+        ));
+        sim.network.heal();
+        sim.run(Duration::from_secs(2)).await;
+
+        let node = sim.nodes[leader].clone();
+        let promoted = url.clone();
+        let promote = tokio::spawn(async move { node.promote_learner(promoted).await });
+        sim.run(Duration::from_secs(1)).await;
+        let index = promote.await.unwrap().expect("learner not promoted");
+        for node in sim.nodes.iter() {
+            let membership = node.membership.read().await;
+            assert!(membership.committed_index >= index);
+            assert!(membership.committed.is_voter(&url));
+            assert!(membership.committed.learners.is_empty());
+        }
+    });
+}
+
+#[test]
+fn a_static_follower_leaves_the_voters() {
+    simulate(async {
+        // The follower is in the `nodes` of the others, it starts as a voter
+        let mut sim = Simulation::start_with_followers(4, 1, 12, Settings::default());
+        let follower = 3;
+        let url = sim.url(follower).to_string();
+        sim.run(Duration::from_secs(3)).await;
+        for (i, node) in sim.nodes.iter().enumerate() {
+            let membership = node.membership.read().await;
+            assert_eq!(membership.committed.learners, BTreeSet::from([url.clone()]));
+            assert_eq!(membership.committed.voters.len(), 3, "voters of {i}");
+            assert!(!membership.committed.is_voter(&url));
+        }
+        let leader = sim.leader().await.expect("no leader elected");
+
+        // Two of the three voters are a majority without the follower
+        sim.network
+            .partition(&[vec![sim.url(leader), sim.url(follower)]]);
+        sim.run(Duration::from_secs(2)).await;
+        let new_leader = sim.leader().await.expect("no leader in the majority");
+        assert!(new_leader != leader && new_leader != follower);
+        sim.network.heal();
+        sim.run(Duration::from_secs(2)).await;
+    });
+}
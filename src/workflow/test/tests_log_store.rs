@@ -0,0 +1,115 @@
+// License:
+// This source code is licensed under the GPLv3 license, you can found the
+// LICENSE file in the root directory of this source tree.
+
+use crate::{
+    log_entry::{Entries, Term},
+    storage::{FileLogStore, LogStore},
+    workflow::test::mock::temp_data_dir,
+};
+use std::{fs::OpenOptions, io::Write};
+
+#[test]
+fn wal_replay_after_restart() {
+    let dir = temp_data_dir();
+    {
+        let mut entries = Entries::with_store(Box::new(
+            FileLogStore::open(&dir).unwrap().with_segment_size(256),
+        ));
+        for i in 1..=10 {
+            entries.append(1, format!("term {i}").into()).unwrap();
+        }
+        entries.set_commit(4).unwrap();
+        // conflict with a new leader, rollback from 8
+        entries.insert(&Term::_new(8, 2, "new 8th term")).unwrap();
+    }
+    // segments have been rotated
+    assert!(std::fs::read_dir(&dir).unwrap().count() > 1);
+
+    let entries = Entries::with_store(Box::new(FileLogStore::open(&dir).unwrap()));
+    assert_eq!(entries.last_index(), 8);
+    assert_eq!(entries.commit_index(), 4);
+    assert_eq!(entries.last_term(), 2);
+    assert_eq!(entries.find(8).unwrap().content, b"new 8th term");
+    assert_eq!(entries.find(3).unwrap().content, b"term 3");
+    assert!(entries.find(9).is_none());
+    assert_eq!(entries.range(2, 5).len(), 4);
+    let _ = std::fs::remove_dir_all(dir);
+}
+
+#[test]
+fn wal_drop_torn_record() {
+    let dir = temp_data_dir();
+    {
+        let mut store = FileLogStore::open(&dir).unwrap();
+        store.insert(&Term::_new(1, 1, "1st term")).unwrap();
+        store.insert(&Term::_new(2, 1, "2nd term")).unwrap();
+    }
+    // Simulate a crash in the middle of a write
+    let segment = std::fs::read_dir(&dir).unwrap().next().unwrap().unwrap();
+    let mut file = OpenOptions::new()
+        .append(true)
+        .open(segment.path())
+        .unwrap();
+    file.write_all(&[42, 0, 0, 0, 1, 2]).unwrap();
+    drop(file);
+
+    let mut store = FileLogStore::open(&dir).unwrap();
+    assert_eq!(store.last_index(), 2);
+    // The log is still writable after the recovery
+    store.insert(&Term::_new(3, 1, "3rd term")).unwrap();
+    let store = FileLogStore::open(&dir).unwrap();
+    assert_eq!(store.last_index(), 3);
+    assert_eq!(store.find(3).unwrap().content, b"3rd term");
+    let _ = std::fs::remove_dir_all(dir);
+}
+
+#[test]
+fn wal_keep_binary_contents() {
+    let dir = temp_data_dir();
+    let content = vec![0, 159, 146, 150, 255];
+    {
+        let mut store = FileLogStore::open(&dir).unwrap();
+        store.insert(&Term::_new(1, 1, content.clone())).unwrap();
+    }
+    let store = FileLogStore::open(&dir).unwrap();
+    assert_eq!(store.find(1).unwrap().content, content);
+    // The contents are in base64 in JSON
+    let json = serde_json::to_value(Term::_new(1, 1, content)).unwrap();
+    assert_eq!(json["content"], "AJ+Slv8=");
+    let _ = std::fs::remove_dir_all(dir);
+}
+
+#[test]
+fn wal_failed_compaction_keeps_the_log() {
+    let dir = temp_data_dir();
+    let mut store = FileLogStore::open(&dir).unwrap();
+    for i in 1..=5 {
+        store
+            .insert(&Term::_new(i, 1, format!("term {i}")))
+            .unwrap();
+    }
+    store.set_commit_index(4).unwrap();
+
+    // Block the temporary file of the next segment
+    let sequence: u64 = std::fs::read_dir(&dir)
+        .unwrap()
+        .filter_map(|e| e.unwrap().path().file_stem()?.to_str()?.parse().ok())
+        .max()
+        .unwrap();
+    let tmp = std::path::Path::new(&dir).join(format!("{:020}.tmp", sequence + 1));
+    std::fs::create_dir(&tmp).unwrap();
+    let last_included = store.find(3).unwrap();
+    assert!(store.compact(&last_included).is_err());
+    assert!(store.compacted().is_none());
+    assert_eq!(store.find(2).unwrap().content, b"term 2");
+
+    std::fs::remove_dir(&tmp).unwrap();
+    store.compact(&last_included).unwrap();
+    assert!(store.find(2).is_none());
+    let store = FileLogStore::open(&dir).unwrap();
+    assert_eq!(store.compacted().unwrap().index, 3);
+    assert_eq!(store.commit_index(), 4);
+    assert_eq!(store.find(5).unwrap().content, b"term 5");
+    let _ = std::fs::remove_dir_all(dir);
+}
@@ -0,0 +1,291 @@
+// License:
+// This source code is licensed under the GPLv3 license, you can found the
+// LICENSE file in the root directory of this source tree.
+use crate::{
+    api::{
+        io_msg::{AppendTermInput, HttpResult},
+        server::on_receive_remove_node,
+    },
+    common::{config::Settings, error::MembershipError, Url},
+    log_entry::{EntryKind, Term},
+    membership::Configuration,
+    node::{NextIndex, Node, NodeInfo},
+    state::Status,
+    workflow::test::{
+        hook::TestHook,
+        simulation::{simulate, Simulation},
+    },
+};
+use hyper::{body::Bytes, header, Body, Response, StatusCode};
+use std::{collections::BTreeSet, time::Duration};
+
+fn set(nodes: &[&str]) -> BTreeSet<String> {
+    nodes.iter().map(|node| node.to_string()).collect()
+}
+
+#[test]
+fn joint_quorum() {
+    let joint = Configuration {
+        voters: set(&["a", "b", "c"]),
+        next: Some(set(&["c", "d", "e"])),
+        learners: set(&["f", "g", "h"]),
+    };
+    // A majority of the old voters isn't enough, the learners don't count
+    assert!(!joint.has_quorum(|node| ["a", "b"].contains(&node)));
+    assert!(!joint.has_quorum(|node| ["a", "b", "f", "g", "h"].contains(&node)));
+    assert!(joint.has_quorum(|node| ["b", "c", "d"].contains(&node)));
+
+    let index_of = |node: &str| match node {
+        "a" | "b" => 10,
+        "c" => 5,
+        _ => 2,
+    };
+    assert_eq!(
+        Configuration::new(set(&["a", "b", "c"])).quorum_index(index_of),
+        10
+    );
+    assert_eq!(joint.quorum_index(index_of), 2);
+}
+
+#[tokio::test]
+async fn add_a_waiting_node() {
+    let settings = Settings {
+        nodes: vec!["10.10.10.10:3000".to_string()],
+        ..Default::default()
+    };
+    let node = Node::test_new(settings, Status::leader(), TestHook::default());
+    let local = node.node_url();
+    node.waiting_nodes.lock().await.push_back(NodeInfo {
+        hash: [0; 16],
+        addr: "10.10.10.11:3000".to_string(),
+        learner: false,
+    });
+
+    // The leader appends the node to the learners and sends the terms to
+    // the new node right away
+    node.advance_membership(0).await.unwrap();
+    let learner = node.logs.lock().await.find(1).unwrap();
+    let old = set(&["10.10.10.10:3000", &local]);
+    let new = set(&["10.10.10.10:3000", "10.10.10.11:3000", &local]);
+    assert_eq!(
+        learner.kind,
+        EntryKind::Configuration(Configuration {
+            voters: old.clone(),
+            next: None,
+            learners: set(&["10.10.10.11:3000"]),
+        })
+    );
+    assert!(node.node_list.read().await.contains("10.10.10.11:3000"));
+
+    // The learner waits until it has all the entries
+    node.commit_entries(1).await.unwrap();
+    node.advance_membership(0).await.unwrap();
+    assert_eq!(node.logs.lock().await.last_index(), 1);
+    node.next_indexes
+        .write()
+        .await
+        .insert("10.10.10.11:3000".into(), NextIndex::Validated(1));
+
+    // Then the leader appends the joint configuration, nothing happens
+    // until it's committed
+    node.advance_membership(0).await.unwrap();
+    let joint = node.logs.lock().await.find(2).unwrap();
+    assert_eq!(
+        joint.kind,
+        EntryKind::Configuration(Configuration {
+            voters: old,
+            next: Some(new.clone()),
+            learners: BTreeSet::new(),
+        })
+    );
+    node.advance_membership(0).await.unwrap();
+    assert_eq!(node.logs.lock().await.last_index(), 2);
+
+    node.commit_entries(2).await.unwrap();
+    node.advance_membership(0).await.unwrap();
+    let last = node.logs.lock().await.find(3).unwrap();
+    assert_eq!(last.configuration(), Some(&Configuration::new(new)));
+    assert!(node.waiting_nodes.lock().await.is_empty());
+}
+
+#[tokio::test]
+async fn forget_a_removed_configuration() {
+    let leader = "10.10.10.10:3000".to_string();
+    let settings = Settings {
+        nodes: vec![leader.clone()],
+        ..Default::default()
+    };
+    let node = Node::test_new(
+        settings,
+        Status::follower(leader.clone().into()),
+        TestHook::default(),
+    );
+    let append = |entries: Vec<Term>| AppendTermInput {
+        term: entries[0].term,
+        leader_id: leader.clone(),
+        prev_log_index: 0,
+        prev_log_term: 0,
+        entries,
+        leader_commit_index: 0,
+    };
+
+    let mut joint = Term::_new(1, 1, "");
+    joint.kind = EntryKind::Configuration(Configuration {
+        voters: set(&[&leader, &node.node_url()]),
+        next: Some(set(&[&leader, &node.node_url(), "10.10.10.11:3000"])),
+        learners: BTreeSet::new(),
+    });
+    node.receive_append_term(append(vec![joint])).await.unwrap();
+    assert!(node.node_list.read().await.contains("10.10.10.11:3000"));
+
+    // A new leader replaces the uncommitted configuration entry
+    node.receive_append_term(append(vec![Term::_new(1, 2, "")]))
+        .await
+        .unwrap();
+    assert_eq!(*node.node_list.read().await, set(&[&leader]));
+}
+
+#[test]
+fn a_node_joins_the_cluster() {
+    simulate(async {
+        let mut sim = Simulation::start(3, 3);
+        sim.run(Duration::from_secs(1)).await;
+        let new = sim.join();
+        sim.run(Duration::from_secs(2)).await;
+
+        let url = sim.url(new).to_string();
+        for node in sim.nodes.iter() {
+            let membership = node.membership.read().await;
+            assert!(!membership.is_changing());
+            assert_eq!(membership.committed.voters.len(), 4);
+            assert!(membership.committed.voters.contains(&url));
+        }
+        let committed = sim.commit_index().await;
+        sim.run(Duration::from_secs(1)).await;
+        assert!(sim.commit_index().await > committed);
+    });
+}
+
+#[test]
+fn change_the_voters() {
+    simulate(async {
+        let mut sim = Simulation::start(5, 4);
+        sim.run(Duration::from_secs(1)).await;
+        let leader = sim.leader().await.expect("no leader elected");
+
+        // Keep the leader and two other nodes
+        let voters: BTreeSet<String> = (0..3)
+            .map(|i| sim.url((leader + i) % 5).to_string())
+            .collect();
+        let node = sim.nodes[leader].clone();
+        let change = {
+            let voters = voters.clone();
+            tokio::spawn(async move { node.change_membership(voters).await })
+        };
+        sim.run(Duration::from_secs(1)).await;
+        change.await.unwrap().expect("membership not changed");
+        assert_eq!(
+            sim.nodes[leader].membership.read().await.committed,
+            Configuration::new(voters)
+        );
+        assert!(matches!(
+            sim.nodes[leader].change_membership(BTreeSet::new()).await,
+            Err(MembershipError::EmptyConfiguration)
+        ));
+
+        // The removed nodes can't stop the cluster anymore
+        sim.network
+            .partition(&[vec![sim.url((leader + 3) % 5), sim.url((leader + 4) % 5)]]);
+        let committed = sim.nodes[leader].logs.lock().await.commit_index();
+        sim.run(Duration::from_secs(1)).await;
+        assert!(sim.nodes[leader].logs.lock().await.commit_index() > committed);
+    });
+}
+
+#[test]
+fn remove_a_follower() {
+    simulate(async {
+        let mut sim = Simulation::start(5, 5);
+        sim.run(Duration::from_secs(1)).await;
+        let leader = sim.leader().await.expect("no leader elected");
+        let removed = (leader + 1) % 5;
+        let url = sim.url(removed).to_string();
+
+        let node = sim.nodes[leader].clone();
+        let remove = {
+            let url = url.clone();
+            tokio::spawn(async move { node.remove_member(url).await })
+        };
+        sim.run(Duration::from_secs(1)).await;
+        remove.await.unwrap().expect("node not removed");
+        for (i, removed) in sim.removed.iter().enumerate() {
+            assert_eq!(*removed.lock().unwrap(), vec![url.clone()], "hook of {i}");
+        }
+
+        // The removed node doesn't hear from the leader anymore, but it
+        // doesn't start any candidature
+        let term = sim.nodes[removed].current_term().await;
+        sim.run(Duration::from_secs(3)).await;
+        assert_eq!(sim.nodes[removed].current_term().await, term);
+        assert!(sim.nodes[removed].p_status.is_follower().await);
+        assert_eq!(sim.leader().await, Some(leader));
+
+        assert!(matches!(
+            sim.nodes[leader].remove_member(url).await,
+            Err(MembershipError::UnknownNode(_))
+        ));
+    });
+}
+
+#[test]
+fn the_leader_removes_itself() {
+    simulate(async {
+        let mut sim = Simulation::start(3, 6);
+        sim.run(Duration::from_secs(1)).await;
+        let old_leader = sim.leader().await.expect("no leader elected");
+        let url = sim.url(old_leader).to_string();
+
+        let node = sim.nodes[old_leader].clone();
+        let remove = tokio::spawn(async move { node.remove_member(url).await });
+        sim.run(Duration::from_secs(2)).await;
+        remove.await.unwrap().expect("leader not removed");
+
+        // The other nodes elect a new leader and keep committing
+        let leader = sim.leader().await.expect("no new leader");
+        assert_ne!(leader, old_leader);
+        assert!(!sim.nodes[old_leader].is_voter().await);
+        let committed = sim.nodes[leader].logs.lock().await.commit_index();
+        sim.run(Duration::from_secs(1)).await;
+        assert!(sim.nodes[leader].logs.lock().await.commit_index() > committed);
+    });
+}
+
+#[tokio::test]
+async fn http_remove_node() {
+    let remove = |node: Node| async move {
+        let mut response = Response::new(Body::empty());
+        on_receive_remove_node(&node, &Bytes::from("10.10.10.12:3000"), &mut response).await;
+        let body = hyper::body::to_bytes(response.body_mut()).await.unwrap();
+        let result: HttpResult = serde_json::from_slice(&body).unwrap();
+        (response, result)
+    };
+
+    let leader = Url::from("10.10.10.10:3000".to_string());
+    let node = Node::test_new(
+        Settings::default(),
+        Status::follower(leader),
+        TestHook::default(),
+    );
+    let (response, result) = remove(node).await;
+    assert_eq!(response.status(), StatusCode::TEMPORARY_REDIRECT);
+    assert_eq!(
+        response.headers()[header::LOCATION],
+        "http://10.10.10.10:3000/remove_node"
+    );
+    assert!(matches!(result, HttpResult::Error(err) if err.err_id == "515"));
+
+    let node = Node::test_new(Settings::default(), Status::leader(), TestHook::default());
+    let (response, result) = remove(node).await;
+    assert_eq!(response.status(), StatusCode::NOT_FOUND);
+    assert!(matches!(result, HttpResult::Error(err) if err.err_id == "521"));
+}
@@ -0,0 +1,110 @@
+// License:
+// This source code is licensed under the GPLv3 license, you can found the
+// LICENSE file in the root directory of this source tree.
+
+use crate::{
+    api::io_msg::{AppendTermInput, RequestVoteInput},
+    common::config::Settings,
+    log_entry::Term,
+    node::Node,
+    state::Status,
+    workflow::test::{
+        hook::TestHook,
+        mock::{temp_data_dir, wait_applied},
+    },
+};
+use std::sync::{Arc, Mutex as StdMutex};
+
+fn settings(data_dir: &str) -> Settings {
+    Settings {
+        nodes: vec!["10.10.10.10:1212".to_string()],
+        data_dir: Some(data_dir.to_string()),
+        ..Default::default()
+    }
+}
+
+#[tokio::test]
+async fn vote_survives_restart() {
+    let data_dir = temp_data_dir();
+    let node = Node::test_new(
+        settings(&data_dir),
+        Status::connection_pending(),
+        TestHook::default(),
+    );
+    let res = node
+        .receive_request_vote(RequestVoteInput {
+            term: 1,
+            candidate_id: "candidate_a".to_string(),
+            last_log_index: 0,
+            last_log_term: 0,
+        })
+        .await;
+    assert!(res.vote_granted);
+
+    // Restart the node, the previous vote is still there
+    let node = Node::test_new(
+        settings(&data_dir),
+        Status::connection_pending(),
+        TestHook::default(),
+    );
+    node.load_hard_state().await.unwrap();
+    let res = node
+        .receive_request_vote(RequestVoteInput {
+            term: 1,
+            candidate_id: "candidate_b".to_string(),
+            last_log_index: 0,
+            last_log_term: 0,
+        })
+        .await;
+    assert!(!res.vote_granted);
+    assert_eq!(res.current_term, 1);
+    let _ = std::fs::remove_dir_all(data_dir);
+}
+
+#[tokio::test]
+async fn log_position_survives_restart() {
+    let data_dir = temp_data_dir();
+    let leader_url = String::from("10.10.10.10:1212");
+    let hook = || TestHook {
+        pre_append_terms: Arc::new(StdMutex::new(vec![1].into())),
+        ..TestHook::default()
+    };
+    let node = Node::test_new(
+        settings(&data_dir),
+        Status::follower(leader_url.clone().into()),
+        hook(),
+    );
+    for (prev_log_index, leader_commit_index) in [(0, 0), (1, 1)] {
+        let entries = if prev_log_index == 0 {
+            vec![Term::_new(1, 1, "1st term")]
+        } else {
+            vec![]
+        };
+        let res = node
+            .receive_append_term(AppendTermInput {
+                term: 1,
+                leader_id: leader_url.clone(),
+                prev_log_index,
+                prev_log_term: prev_log_index,
+                entries,
+                leader_commit_index,
+            })
+            .await
+            .unwrap();
+        assert!(res.success);
+    }
+    wait_applied(&node, 1).await;
+
+    let node = Node::test_new(
+        settings(&data_dir),
+        Status::follower(leader_url.into()),
+        hook(),
+    );
+    node.load_hard_state().await.unwrap();
+    assert_eq!(node.election.read().await.current_term, 1);
+    let logs = node.logs.lock().await;
+    assert_eq!(logs.last_term(), 1);
+    assert_eq!(logs.commit_index(), 1);
+    assert_eq!(logs.last_index(), 1);
+    let _ = std::fs::remove_dir_all(data_dir);
+}
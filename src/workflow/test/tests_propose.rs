@@ -0,0 +1,165 @@
+// License:
+// This source code is licensed under the GPLv3 license, you can found the
+// LICENSE file in the root directory of this source tree.
+use crate::{
+    api::{
+        io_msg::{AppendTermInput, HttpResult},
+        server::on_receive_propose,
+    },
+    common::{config::Settings, error::ProposeError, Url},
+    node::Node,
+    state::Status,
+    workflow::test::{
+        hook::TestHook,
+        simulation::{simulate, Simulation},
+    },
+};
+use hyper::{body::Bytes, header, Body, Response, StatusCode};
+use std::time::Duration;
+
+async fn http_propose(node: &Node, content: &'static str) -> (Response<Body>, HttpResult) {
+    let mut response = Response::new(Body::empty());
+    on_receive_propose(node, &Bytes::from(content), &mut response).await;
+    let body = hyper::body::to_bytes(response.body_mut()).await.unwrap();
+    let result = serde_json::from_slice(&body).unwrap();
+    (response, result)
+}
+
+#[tokio::test]
+async fn propose_to_a_follower() {
+    let leader = Url::from("10.10.10.10:3000".to_string());
+    let node = Node::test_new(
+        Settings::default(),
+        Status::follower(leader.clone()),
+        TestHook::default(),
+    );
+    match node.propose("content".to_string()).await {
+        Err(ProposeError::NotLeader(hint)) => assert_eq!(hint, Some(leader)),
+        res => panic!("unexpected result {:?}", res),
+    }
+    assert_eq!(node.logs.lock().await.last_index(), 0);
+}
+
+#[test]
+fn propose_committed_by_the_cluster() {
+    simulate(async {
+        let mut sim = Simulation::start(3, 1);
+        sim.run(Duration::from_secs(1)).await;
+        let leader = sim.leader().await.expect("no leader elected");
+
+        let node = sim.nodes[leader].clone();
+        let proposal = tokio::spawn(async move { node.propose("hello".to_string()).await });
+        sim.run(Duration::from_secs(1)).await;
+        let index = proposal.await.unwrap().expect("proposal not committed");
+        for node in sim.nodes.iter() {
+            let logs = node.logs.lock().await;
+            assert!(logs.commit_index() >= index);
+            assert_eq!(logs.find(index).unwrap().content, b"hello");
+        }
+
+        let follower = (leader + 1) % 3;
+        match sim.nodes[follower].propose("hello".to_string()).await {
+            Err(ProposeError::NotLeader(hint)) => assert_eq!(hint, Some(sim.url(leader))),
+            res => panic!("unexpected result {:?}", res),
+        }
+    });
+}
+
+#[test]
+fn propose_to_a_partitioned_leader() {
+    simulate(async {
+        let mut sim = Simulation::start(3, 2);
+        sim.run(Duration::from_secs(1)).await;
+        let old_leader = sim.leader().await.expect("no leader elected");
+
+        // The entry can't be committed without a majority, the leader
+        // steps down and fails the proposal without waiting for the
+        // partition to heal
+        sim.network.partition(&[vec![sim.url(old_leader)]]);
+        let node = sim.nodes[old_leader].clone();
+        let proposal = tokio::spawn(async move { node.propose("lost".to_string()).await });
+        sim.run(Duration::from_secs(2)).await;
+        assert!(!sim.nodes[old_leader].p_status.is_leader().await);
+        assert!(proposal.is_finished());
+        assert!(matches!(
+            proposal.await.unwrap(),
+            Err(ProposeError::LeadershipLost)
+        ));
+
+        // A new leader replaces the entry when the partition is healed
+        sim.network.heal();
+        sim.run(Duration::from_secs(2)).await;
+        let leader = sim.leader().await.expect("no leader elected");
+        let logs = sim.nodes[leader].logs.lock().await;
+        assert!((1..=logs.last_index()).all(|i| logs.find(i).unwrap().content != b"lost"));
+    });
+}
+
+#[tokio::test]
+async fn propose_to_a_deposed_leader() {
+    let node = Node::test_new(Settings::default(), Status::leader(), TestHook::default());
+    node.election.write().await.current_term = 1;
+    let leader = node.clone();
+    let proposal = tokio::spawn(async move { leader.propose("deposed".to_string()).await });
+    while node.logs.lock().await.last_index() == 0 {
+        tokio::task::yield_now().await;
+    }
+
+    // A leader of a newer term takes the lead, the entry isn't committed
+    let res = node
+        .receive_append_term(AppendTermInput {
+            term: 2,
+            leader_id: "10.10.10.10:3000".into(),
+            prev_log_index: 0,
+            prev_log_term: 0,
+            entries: vec![],
+            leader_commit_index: 0,
+        })
+        .await
+        .unwrap();
+    assert!(res.success);
+    let res = tokio::time::timeout(Duration::from_secs(1), proposal)
+        .await
+        .expect("proposal of a deposed leader hangs");
+    assert!(matches!(res.unwrap(), Err(ProposeError::LeadershipLost)));
+}
+
+#[tokio::test]
+async fn http_propose_redirect_to_the_leader() {
+    let leader = Url::from("10.10.10.10:3000".to_string());
+    let node = Node::test_new(
+        Settings::default(),
+        Status::follower(leader),
+        TestHook::default(),
+    );
+    let (response, result) = http_propose(&node, "content").await;
+    assert_eq!(response.status(), StatusCode::TEMPORARY_REDIRECT);
+    assert_eq!(
+        response.headers()[header::LOCATION],
+        "http://10.10.10.10:3000/propose"
+    );
+    assert!(matches!(result, HttpResult::Error(err) if err.err_id == "515"));
+
+    let node = Node::test_new(
+        Settings::default(),
+        Status::candidate(),
+        TestHook::default(),
+    );
+    let (response, _) = http_propose(&node, "content").await;
+    assert_eq!(response.status(), StatusCode::SERVICE_UNAVAILABLE);
+}
+
+#[tokio::test]
+async fn http_propose_to_the_leader() {
+    let node = Node::test_new(Settings::default(), Status::leader(), TestHook::default());
+    let leader = node.clone();
+    let request = tokio::spawn(async move { http_propose(&leader, "content").await });
+    while node.logs.lock().await.last_index() == 0 {
+        tokio::task::yield_now().await;
+    }
+    node.commit_entries(1).await.unwrap();
+    let (response, result) = request.await.unwrap();
+    assert_eq!(response.status(), StatusCode::OK);
+    assert!(matches!(result, HttpResult::Propose(res) if res.index == 1));
+    assert_eq!(node.logs.lock().await.find(1).unwrap().content, b"content");
+}
@@ -0,0 +1,52 @@
+// License:
+// This source code is licensed under the GPLv3 license, you can found the
+// LICENSE file in the root directory of this source tree.
+use crate::{
+    common::error::ReadIndexError,
+    workflow::test::simulation::{simulate, Simulation},
+};
+use std::time::Duration;
+
+#[test]
+fn read_index_on_the_leader() {
+    simulate(async {
+        let mut sim = Simulation::start(3, 1);
+        sim.run(Duration::from_secs(1)).await;
+        let leader = sim.leader().await.expect("no leader elected");
+
+        let node = sim.nodes[leader].clone();
+        let committed = node.logs.lock().await.commit_index();
+        let read = tokio::spawn(async move { node.read_index().await });
+        sim.run(Duration::from_millis(500)).await;
+        let read_index = read.await.unwrap().expect("read not confirmed");
+        assert!(read_index >= committed);
+        assert!(*sim.nodes[leader].applied.borrow() >= read_index);
+
+        let follower = (leader + 1) % 3;
+        match sim.nodes[follower].read_index().await {
+            Err(ReadIndexError::NotLeader(hint)) => assert_eq!(hint, Some(sim.url(leader))),
+            res => panic!("unexpected result {:?}", res),
+        }
+    });
+}
+
+#[test]
+fn read_index_on_a_deposed_leader() {
+    simulate(async {
+        let mut sim = Simulation::start(3, 2);
+        sim.run(Duration::from_secs(1)).await;
+        let old_leader = sim.leader().await.expect("no leader elected");
+
+        // The isolated leader can't confirm its lead, the majority may have
+        // committed newer entries
+        sim.network.partition(&[vec![sim.url(old_leader)]]);
+        sim.run(Duration::from_millis(20)).await;
+        let node = sim.nodes[old_leader].clone();
+        let read = tokio::spawn(async move { node.read_index().await });
+        sim.run(Duration::from_secs(2)).await;
+        assert!(matches!(
+            read.await.unwrap(),
+            Err(ReadIndexError::LeadershipLost)
+        ));
+    });
+}
@@ -0,0 +1,132 @@
+// License:
+// This source code is licensed under the GPLv3 license, you can found the
+// LICENSE file in the root directory of this source tree.
+
+use crate::{
+    api::io_msg::RequestVoteInput, common::config::Settings, node::Node, state::Status,
+    workflow::test::hook::TestHook,
+};
+
+fn get_voter() -> Node {
+    let settings = Settings {
+        nodes: vec!["10.10.10.10:1212".to_string()],
+        ..Default::default()
+    };
+    Node::test_new(settings, Status::candidate(), TestHook::default())
+}
+
+fn request(
+    term: usize,
+    candidate_id: &str,
+    last_log_index: usize,
+    last_log_term: usize,
+) -> RequestVoteInput {
+    RequestVoteInput {
+        term,
+        candidate_id: candidate_id.to_string(),
+        last_log_index,
+        last_log_term,
+    }
+}
+
+#[tokio::test]
+async fn refuse_less_up_to_date_candidate() {
+    /* ****
+    §5.4.1, the voter denies its vote if its own log is more up-to-date
+    than that of the candidate. A later last term wins over a longer log.
+    **** */
+    let node = get_voter();
+    {
+        let mut logs = node.logs.lock().await;
+        logs.append(1, "1st term".into()).unwrap();
+        logs.append(2, "2nd term".into()).unwrap();
+    }
+
+    // Longer log but older last term
+    let res = node.receive_request_vote(request(3, "a", 5, 1)).await;
+    assert!(!res.vote_granted);
+    // The newer election term is still adopted, and we step down
+    assert_eq!(res.current_term, 3);
+    assert!(node.p_status.is_follower().await);
+
+    // Same last term but shorter log
+    let res = node.receive_request_vote(request(3, "b", 1, 2)).await;
+    assert!(!res.vote_granted);
+
+    // Same last term and same length
+    let res = node.receive_request_vote(request(3, "c", 2, 2)).await;
+    assert!(res.vote_granted);
+}
+
+#[tokio::test]
+async fn vote_once_per_term() {
+    let node = get_voter();
+    assert!(
+        node.receive_request_vote(request(1, "a", 0, 0))
+            .await
+            .vote_granted
+    );
+    // Another candidate in the same term
+    assert!(
+        !node
+            .receive_request_vote(request(1, "b", 0, 0))
+            .await
+            .vote_granted
+    );
+    // The same candidate can ask again
+    assert!(
+        node.receive_request_vote(request(1, "a", 0, 0))
+            .await
+            .vote_granted
+    );
+    // A new election term, the vote is free again
+    assert!(
+        node.receive_request_vote(request(2, "b", 0, 0))
+            .await
+            .vote_granted
+    );
+    // An old candidate is refused
+    let res = node.receive_request_vote(request(1, "a", 3, 3)).await;
+    assert!(!res.vote_granted);
+    assert_eq!(res.current_term, 2);
+}
+
+#[tokio::test]
+async fn pre_vote_changes_nothing() {
+    let node = get_voter();
+    node.logs.lock().await.append(1, "1st term".into()).unwrap();
+
+    let res = node.receive_pre_vote(request(3, "a", 1, 1)).await;
+    assert!(res.vote_granted);
+    // Neither the term nor the vote are updated
+    assert_eq!(res.current_term, 0);
+    assert_eq!(node.election.read().await.vote_for, None);
+    assert!(node.p_status.is_candidate().await);
+
+    // The log check is the one of a real vote
+    let res = node.receive_pre_vote(request(3, "a", 0, 0)).await;
+    assert!(!res.vote_granted);
+}
+
+#[tokio::test(start_paused = true)]
+async fn refuse_pre_vote_while_the_leader_is_alive() {
+    let node = get_voter();
+    *node.leader_contact.lock().unwrap() = Some(tokio::time::Instant::now());
+    assert!(
+        !node
+            .receive_pre_vote(request(1, "a", 0, 0))
+            .await
+            .vote_granted
+    );
+
+    // No news of the leader after the minimum election timeout
+    tokio::time::advance(std::time::Duration::from_millis(
+        node.settings.timeout_min as u64,
+    ))
+    .await;
+    assert!(
+        node.receive_pre_vote(request(1, "a", 0, 0))
+            .await
+            .vote_granted
+    );
+}
@@ -0,0 +1,135 @@
+// License:
+// This source code is licensed under the GPLv3 license, you can found the
+// LICENSE file in the root directory of this source tree.
+use crate::{
+    common::{error::HookError, Url},
+    log_entry::Term,
+    state::EStatus,
+    workflow::test::mock::temp_data_dir,
+    DefaultHook, Hook, NodeContext,
+};
+use std::{
+    fs,
+    os::unix::fs::PermissionsExt,
+    path::Path,
+    sync::{atomic::AtomicUsize, Arc},
+    time::{Duration, Instant},
+};
+use tokio::sync::watch;
+
+fn write_script(dir: &Path, name: &str, content: &str) {
+    let script = dir.join(name);
+    fs::write(&script, format!("#!/bin/sh\n{content}\n")).unwrap();
+    fs::set_permissions(&script, fs::Permissions::from_mode(0o755)).unwrap();
+}
+
+#[test]
+fn run_the_scripts_of_the_hooks_directory() {
+    let dir = temp_data_dir();
+    let dir = Path::new(&dir);
+    fs::create_dir_all(dir).unwrap();
+    write_script(
+        dir,
+        "prepare_term",
+        "echo \"$HOOK_NODE_ID $HOOK_STATUS $HOOK_LEADER $HOOK_COMMIT_INDEX $HOOK_LAST_INDEX\"",
+    );
+    write_script(dir, "append_term", "echo refused >&2; exit 1");
+    write_script(dir, "commit_term", "exit 3");
+    write_script(dir, "update_node", "echo False");
+    // The content is on the standard input
+    write_script(
+        dir,
+        "pre_append_term",
+        "test \"$2 $(cat)\" = \"normal set x\" && echo 12",
+    );
+    write_script(
+        dir,
+        "retrieve_term",
+        "test $1 = 4 && printf '3\\n\\000\\377' || echo missing",
+    );
+    write_script(
+        dir,
+        "retrieve_n_term",
+        "test $1 = 2 && echo '[{\"id\":2,\"term\":3,\"content\":\"AP8=\"}]' \
+            || echo '[{\"id\":1,\"content\":\"eA==\"}]'",
+    );
+    // The files of the snapshots are recorded to check their removal
+    let paths = dir.join("paths");
+    let record = format!("echo \"$2\" >> {}", paths.display());
+    write_script(dir, "snapshot", &format!("{record}; sleep 5"));
+    write_script(
+        dir,
+        "restore_snapshot",
+        &format!("{record}; test \"$(cat \"$2\")\" = data"),
+    );
+    let hook = DefaultHook::new()
+        .with_dir(dir)
+        .with_script_timeout("snapshot", Duration::from_millis(200));
+
+    let (_status, status) = watch::channel((EStatus::Follower, Some(Url::from("leader:80"))));
+    let (_applied, applied) = watch::channel(3);
+    let last_index = Arc::new(AtomicUsize::new(5));
+    hook.set_context(NodeContext::new(
+        "node:80".into(),
+        status,
+        applied,
+        last_index,
+    ));
+
+    // Exit codes and outputs
+    assert_eq!(
+        hook.prepare_term().unwrap(),
+        b"node:80 follower leader:80 3 5\n"
+    );
+    assert_eq!(hook.pre_append_term(&Term::_new(1, 1, "set x")), Ok(12));
+    assert!(matches!(
+        hook.pre_append_term(&Term::_new(1, 1, "set y")),
+        Err(HookError::Rejected(_))
+    ));
+    // Binary contents go through untouched
+    let term = hook.retreive_term(4).unwrap();
+    assert_eq!((term.term, term.content), (3, vec![0, 255]));
+    let terms = hook.retreive_terms(2, 2).unwrap();
+    assert_eq!(terms.len(), 1);
+    assert_eq!((terms[0].index, terms[0].term), (2, 3));
+    assert_eq!(terms[0].content, vec![0, 255]);
+    // The election term can't be guessed
+    assert!(matches!(hook.retreive_term(5), Err(HookError::Failed(_))));
+    assert!(matches!(
+        hook.retreive_terms(1, 1),
+        Err(HookError::Failed(_))
+    ));
+    assert!(matches!(
+        hook.append_term(&Term::_new(1, 1, "")),
+        Err(HookError::Rejected(_))
+    ));
+    assert!(matches!(
+        hook.commit_term(&Term::_new(1, 1, "")),
+        Err(HookError::Failed(_))
+    ));
+    assert!(matches!(hook.update_node(), Err(HookError::Rejected(_))));
+
+    // A hung script is killed
+    let start = Instant::now();
+    assert!(matches!(hook.snapshot(1), Err(HookError::Failed(_))));
+    assert!(start.elapsed() < Duration::from_secs(2));
+    assert!(hook.restore_snapshot(1, b"data").is_ok());
+    assert!(matches!(
+        hook.restore_snapshot(1, b"other"),
+        Err(HookError::Rejected(_))
+    ));
+    // Each call has its own file, removed after the call
+    let paths = fs::read_to_string(paths).unwrap();
+    let paths: Vec<&str> = paths.lines().collect();
+    assert_eq!(paths.len(), 3);
+    assert!(paths[1] != paths[2]);
+    assert!(paths.iter().all(|path| !Path::new(path).exists()));
+
+    // Terms can't be retrieved without script
+    fs::remove_file(dir.join("retrieve_n_term")).unwrap();
+    assert!(matches!(
+        hook.retreive_terms(1, 4),
+        Err(HookError::Failed(_))
+    ));
+    fs::remove_dir_all(dir).unwrap();
+}
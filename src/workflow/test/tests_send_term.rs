@@ -2,268 +2,139 @@
 // This source code is licensed under the GPLv3 license, you can found the
 // LICENSE file in the root directory of this source tree.
 use crate::{
-    api::{io_msg::UpdateNodeInput, Url},
-    common::{config::Settings, scripts::DefaultHook},
-    log_entry::Entries,
-    node::generate_uuid,
-    state::{Leader, Status},
-    workflow::leader::_term_preparation,
-    Node,
+    api::InMemoryNetwork,
+    common::{config::Settings, Url},
+    log_entry::EntryKind,
+    node::{NextIndex, Node},
+    state::Status,
+    workflow::{leader::_term_preparation, test::hook::TestHook},
 };
-use std::{
-    collections::{HashMap, HashSet, VecDeque},
-    sync::Arc,
-};
-use tokio::sync::{Mutex, RwLock};
-use tracing_test::traced_test;
-
-#[tokio::test]
-#[serial_test::serial]
-#[traced_test]
-async fn term_preparation_1() {
-    // Check if nodes_waiting is poped correctly
-    let mut nodes_waiting = VecDeque::new();
-    let hash = generate_uuid();
-    nodes_waiting.push_front(
-        serde_json::to_string(&UpdateNodeInput {
-            hash,
-            port: "8080".to_string(),
-            follower: true,
-        })
-        .unwrap(),
-    );
-    let node = Node {
-        waiting_nodes: Arc::new(Mutex::new(nodes_waiting)),
-        ..Node::_init(
-            Settings::default(),
-            Status::<Leader>::create(),
-            DefaultHook {},
-        )
-    };
-    for _ in 0..100 {
-        _term_preparation(
-            &node.logs,
-            &node.p_status,
-            &node.waiting_nodes,
-            &node.node_list,
-            &node.hook,
-        )
-        .await;
-        if node.waiting_nodes.lock().await.is_empty() {
-            break;
+use std::time::Duration;
+
+fn leader_with_entries(count: usize) -> Node {
+    let node = Node::test_new(Settings::default(), Status::leader(), TestHook::default());
+    {
+        let mut logs = node.logs.try_lock().unwrap();
+        for i in 1..=count {
+            logs.append(1, format!("term {i}").into()).unwrap();
         }
     }
-    let nodes_waiting = node.waiting_nodes.lock().await;
-    let logs = node.logs.lock().await;
-    assert!(nodes_waiting.is_empty());
-    assert!(!logs.len() > 0);
-    assert!(logs.back().is_some());
-    assert!(logs.back().unwrap().content.starts_with("conn:"));
-
-    let mut c = logs.back().unwrap().content;
-    c.drain(.."conn:".len());
-    let conn_to: UpdateNodeInput = serde_json::from_str(&c.to_string()).unwrap();
-    assert_eq!(conn_to.hash, hash);
-    assert!(conn_to.follower);
+    node
 }
 
 #[tokio::test]
-#[serial_test::serial]
-#[traced_test]
-async fn create_term_for_node_1() {
-    let known_follower = Url::from("127.0.0.1:8081");
-
-    // first time we speak with him, we don't know where he is in his logs
-
-    let node = Node {
-        leader: Arc::new(RwLock::new(Some(Url::from("127.0.0.1:8080")))),
-        logs: Arc::new(Mutex::new(Entries::default())),
-        ..Node::_init(
-            Settings::default(),
-            Status::<Leader>::create(),
-            DefaultHook {},
-        )
+async fn term_preparation_1() {
+    // The leader prepares terms with the hook when someone listens
+    let settings = Settings {
+        nodes: vec!["10.10.10.10:3000".to_string()],
+        ..Default::default()
     };
-    let input = node.create_term_input(&known_follower).await;
-    assert_eq!(input.entries.len(), 0);
-    assert_eq!(input.prev_term.id, 0);
-    assert_eq!(input.term.id, 1);
-    assert_eq!(input.term.content, "");
-
-    let input = node.create_term_input(&known_follower).await;
-    assert_eq!(input.entries.len(), 0);
-    assert_eq!(input.prev_term.id, 1);
-    assert_eq!(input.term.id, 2);
-    assert_eq!(input.term.content, "");
+    let node = Node::test_new(settings, Status::leader(), TestHook::default());
+    let should_break = _term_preparation(&node, 0).await;
+    assert!(!should_break);
 
-    logs_assert(|logs| {
-        let mut c = 0;
-        for log in logs.iter() {
-            if log.contains("create an empty term") {
-                c += 1;
-            }
-        }
-        if c == 2 {
-            Ok(())
-        } else {
-            // we should just once trigger a warning,
-            // because the next call use the term created
-            // from the previous call
-            Err("invalid number of warnings".to_string())
-        }
-    });
+    let logs = node.logs.lock().await;
+    let term = logs.find(logs.last_index()).unwrap();
+    assert_eq!(term.term, 0);
+    assert_eq!(term.kind, EntryKind::Normal);
+    std::mem::drop(logs);
+
+    // The preparation stops when the election term changed
+    node.election.write().await.current_term = 1;
+    assert!(_term_preparation(&node, 0).await);
 }
 
 #[tokio::test]
-#[serial_test::serial]
-#[traced_test]
-async fn create_term_for_node_2() {
+async fn create_term_for_unknown_node() {
+    // first time we speak with him, we suppose he's up to date
     let known_follower = Url::from("127.0.0.1:8081");
-
-    // first time we speak with him, we don't know where he is in his logs
-
-    // initialize some logs
-    let mut entries = Entries::new();
-    entries.append("1st term".to_string());
-    entries.append("2nd term".to_string());
-    entries.append("3rd term".to_string());
-    entries.append("4th term".to_string());
-
-    let node = Node {
-        leader: Arc::new(RwLock::new(Some(Url::from("127.0.0.1:8080")))),
-        logs: Arc::new(Mutex::new(entries)),
-        ..Node::_init(
-            Settings::default(),
-            Status::<Leader>::create(),
-            DefaultHook {},
-        )
-    };
-    let input = node.create_term_input(&known_follower).await;
-    assert_eq!(input.entries.len(), 0);
-    assert_eq!(input.prev_term.id, 3);
-    assert_eq!(input.prev_term.content, "4th term");
-    assert_eq!(input.term.id, 3);
+    let node = leader_with_entries(4);
+    let input = node.create_term_input(&known_follower, 2).await.unwrap();
+    assert_eq!(input.term, 2);
+    assert_eq!(input.prev_log_index, 4);
+    assert_eq!(input.prev_log_term, 1);
+    assert!(input.entries.is_empty());
 }
 
 #[tokio::test]
-#[serial_test::serial]
-#[traced_test]
-async fn create_term_for_node_3() {
-    // same as create_term_for_node_2 but target already know some logs
+async fn create_term_for_late_node() {
     let known_follower = Url::from("127.0.0.1:8081");
-
-    let mut next_indexes = HashMap::default();
-    // target know 3 entries in logs
-    next_indexes.insert(known_follower.clone(), 2);
-
-    // initialize some logs
-    let mut entries = Entries::new();
-    entries.append("1st term".to_string());
-    entries.append("2nd term".to_string());
-    entries.append("3rd term".to_string());
-    entries.append("4th term".to_string());
-
-    let node = Node {
-        follower_list: Arc::new(RwLock::new(follower_list)),
-        next_indexes: Arc::new(RwLock::new(next_indexes)),
-        leader: Arc::new(RwLock::new(Some(Url::from("127.0.0.1:8080")))),
-        logs: Arc::new(Mutex::new(entries)),
-        ..Node::_init(
-            Settings::default(),
-            Status::<Leader>::create(),
-            DefaultHook {},
-        )
-    };
-    let input = node.create_term_input(&known_follower).await;
-    // should be empty because there is just one new log, in "term"
-    assert_eq!(input.entries.len(), 0);
-    // id of the 4th term
-    assert_eq!(input.term.id, 3);
-    // content of the 4th term
-    assert_eq!(input.term.content, "4th term");
-    // previous term is the next_indexe of the target
-    assert_eq!(input.prev_term.id, 2)
+    let node = leader_with_entries(4);
+    node.next_indexes
+        .write()
+        .await
+        .insert(known_follower.clone(), NextIndex::Validated(2));
+    node.commit_entries(2).await.unwrap();
+
+    let input = node.create_term_input(&known_follower, 1).await.unwrap();
+    assert_eq!(input.prev_log_index, 2);
+    assert_eq!(input.prev_log_term, 1);
+    assert_eq!(
+        input.entries.iter().map(|t| t.index).collect::<Vec<_>>(),
+        vec![3, 4]
+    );
+    assert_eq!(input.entries[1].content, b"term 4");
+    assert_eq!(input.leader_commit_index, 2);
 }
 
 #[tokio::test]
-#[serial_test::serial]
-#[traced_test]
-async fn create_term_for_node_4() {
-    // same as create_term_for_node_3 but target already know other logs
+async fn create_term_max_entries() {
     let known_follower = Url::from("127.0.0.1:8081");
-
-    let mut next_indexes = HashMap::default();
-    // target know 2 entries in logs
-    next_indexes.insert(known_follower.clone(), 1);
-
-    // initialize some logs
-    let mut entries = Entries::new();
-    entries.append("1st term".to_string());
-    entries.append("2nd term".to_string());
-    entries.append("3rd term".to_string());
-    entries.append("4th term".to_string());
-
-    let node = Node {
-        next_indexes: Arc::new(RwLock::new(next_indexes)),
-        leader: Arc::new(RwLock::new(Some(Url::from("127.0.0.1:8080")))),
-        logs: Arc::new(Mutex::new(entries)),
-        ..Node::_init(
-            Settings::default(),
-            Status::<Leader>::create(),
-            DefaultHook {},
-        )
-    };
-    let input = node.create_term_input(&known_follower).await;
-    // should be empty because there is just one new log, in "term"
-    assert_eq!(input.entries.len(), 1);
-    // the entry in the list should be the 3rd
-    assert!(input.entries.find(2).is_some());
-    // id of the 4th term
-    assert_eq!(input.term.id, 3);
-    // content of the 4th term
-    assert_eq!(input.term.content, "4th term");
-    // previous term is the next_indexe of the target
-    assert_eq!(input.prev_term.id, 1)
+    let node = leader_with_entries(30);
+    node.next_indexes
+        .write()
+        .await
+        .insert(known_follower.clone(), NextIndex::Pending(0));
+
+    let input = node.create_term_input(&known_follower, 1).await.unwrap();
+    assert_eq!(input.prev_log_index, 0);
+    assert_eq!(input.prev_log_term, 0);
+    assert_eq!(input.entries.len(), 10);
+    assert_eq!(input.entries[9].index, 10);
 }
 
 #[tokio::test]
-#[serial_test::serial]
-#[traced_test]
-async fn create_term_for_node_5() {
-    let known_follower = Url::from("127.0.0.1:8081");
-
-    let mut next_indexes = HashMap::default();
-    // target know everything
-    next_indexes.insert(known_follower.clone(), 3);
-
-    // initialize some logs
-    let mut entries = Entries::new();
-    entries.append("1st term".to_string());
-    entries.append("2nd term".to_string());
-    entries.append("3rd term".to_string());
-    entries.append("4th term".to_string());
-
-    let node = Node {
-        follower_list: Arc::new(RwLock::new(follower_list)),
-        next_indexes: Arc::new(RwLock::new(next_indexes)),
-        leader: Arc::new(RwLock::new(Some(Url::from("127.0.0.1:8080")))),
-        logs: Arc::new(Mutex::new(entries)),
-        p_commit_index: Arc::new(Mutex::new(2)),
-        ..Node::_init(
-            Settings::default(),
-            Status::<Leader>::create(),
-            DefaultHook {},
-        )
-    };
-
-    let input = node.create_term_input(&known_follower).await;
-    assert_eq!(input.entries.len(), 0);
-    // id of the 4th term
-    assert_eq!(input.term.id, 3);
-    // content of the 4th term
-    assert_eq!(input.term.content, "4th term");
-    // previous term is the next_indexe of the target
-    assert_eq!(input.prev_term.id, 3);
+async fn elect_and_replicate_in_memory() {
+    let network = InMemoryNetwork::new();
+    let urls: Vec<String> = (1..=3).map(|i| format!("127.0.0.1:300{i}")).collect();
+    let nodes: Vec<Node> = urls
+        .iter()
+        .map(|url| {
+            let settings = Settings {
+                port: Url::from(url).get_port(),
+                nodes: urls.iter().filter(|u| *u != url).cloned().collect(),
+                ..Default::default()
+            };
+            Node::new_with_settings_async(settings, TestHook::default())
+                .with_transport(network.transport(url))
+        })
+        .collect();
+    for node in nodes.iter() {
+        node.clone().spawn();
+    }
 
-    assert_eq!(input.leader_commit_index, 2);
+    // Wait for a leader to be elected and some terms to be committed by
+    // every node.
+    tokio::time::timeout(Duration::from_secs(10), async {
+        loop {
+            let mut committed = usize::MAX;
+            for node in nodes.iter() {
+                committed = committed.min(node.logs.lock().await.commit_index());
+            }
+            if committed >= 3 {
+                break;
+            }
+            tokio::time::sleep(Duration::from_millis(50)).await;
+        }
+    })
+    .await
+    .expect("no terms committed by the cluster");
+
+    // Committed terms are the same everywhere
+    for index in 1..=3 {
+        let expected = nodes[0].logs.lock().await.find(index).unwrap();
+        for node in nodes.iter().skip(1) {
+            assert_eq!(node.logs.lock().await.find(index).unwrap(), expected);
+        }
+    }
 }
@@ -0,0 +1,182 @@
+// License:
+// This source code is licensed under the GPLv3 license, you can found the
+// LICENSE file in the root directory of this source tree.
+use crate::{
+    common::error::HookError,
+    log_entry::Term,
+    workflow::test::simulation::{simulate, Simulation},
+};
+use std::time::Duration;
+
+#[test]
+fn elect_a_leader() {
+    for seed in 0..8 {
+        simulate(async move {
+            let mut sim = Simulation::start(5, seed);
+            sim.run(Duration::from_secs(3)).await;
+            assert!(sim.leader().await.is_some(), "no leader with seed {seed}");
+            assert!(sim.commit_index().await >= 5, "no commit with seed {seed}");
+        });
+    }
+}
+
+#[test]
+fn same_seed_same_run() {
+    let run = || {
+        simulate(async {
+            let mut sim = Simulation::start(5, 3);
+            sim.network.set_drop_rate(0.1);
+            sim.run(Duration::from_secs(3)).await;
+            let mut state = vec![];
+            for node in sim.nodes.iter() {
+                let term = node.current_term().await;
+                let logs = node.logs.lock().await;
+                let entries: Vec<(usize, usize)> = logs
+                    .range(1, logs.last_index())
+                    .iter()
+                    .map(|entry| (entry.index, entry.term))
+                    .collect();
+                state.push((term, logs.commit_index(), entries));
+            }
+            state
+        })
+    };
+    assert_eq!(run(), run());
+}
+
+#[test]
+fn lossy_network() {
+    for seed in 0..4 {
+        simulate(async move {
+            let mut sim = Simulation::start(5, seed);
+            sim.network.set_drop_rate(0.2);
+            sim.network.set_delay(1, 40);
+            sim.run(Duration::from_secs(10)).await;
+
+            // The cluster recovers when the network is stable again
+            sim.network.set_drop_rate(0.);
+            sim.network.set_delay(0, 2);
+            sim.run(Duration::from_secs(1)).await;
+            let committed = sim.commit_index().await;
+            sim.run(Duration::from_secs(3)).await;
+            assert!(sim.leader().await.is_some(), "no leader with seed {seed}");
+            assert!(
+                sim.commit_index().await > committed,
+                "no progress with seed {seed}"
+            );
+        });
+    }
+}
+
+#[test]
+fn isolated_node_does_not_disrupt_the_cluster() {
+    simulate(async {
+        let mut sim = Simulation::start(5, 7);
+        sim.run(Duration::from_secs(2)).await;
+        let leader = sim.leader().await.expect("no leader elected");
+        let term = sim.nodes[leader].current_term().await;
+
+        // The isolated node never gets the pre votes, its term doesn't move
+        let isolated = (leader + 1) % 5;
+        sim.network.partition(&[vec![sim.url(isolated)]]);
+        sim.run(Duration::from_secs(3)).await;
+        assert_eq!(sim.nodes[isolated].current_term().await, term);
+
+        // The leader keeps the lead when the node comes back
+        sim.network.heal();
+        sim.run(Duration::from_secs(2)).await;
+        assert_eq!(sim.leader().await, Some(leader));
+        assert_eq!(sim.nodes[leader].current_term().await, term);
+    });
+}
+
+#[test]
+fn partition_the_leader() {
+    simulate(async {
+        let mut sim = Simulation::start(5, 42);
+        sim.run(Duration::from_secs(2)).await;
+        let old_leader = sim.leader().await.expect("no leader elected");
+        let old_term = sim.nodes[old_leader].current_term().await;
+
+        // The majority elects a new leader without the old one
+        sim.network.partition(&[vec![sim.url(old_leader)]]);
+        sim.run(Duration::from_secs(3)).await;
+        let leader = sim.leader().await.expect("no leader in the majority");
+        assert_ne!(leader, old_leader);
+        assert!(sim.nodes[leader].current_term().await > old_term);
+
+        // The old leader catches up when the partition is healed
+        sim.network.heal();
+        sim.run(Duration::from_secs(1)).await;
+        let committed = sim.nodes[leader].logs.lock().await.commit_index();
+        sim.run(Duration::from_secs(3)).await;
+        assert!(sim.leader().await.is_some());
+        assert!(sim.commit_index().await >= committed);
+    });
+}
+
+/// Figure 8 of the Raft paper. An entry of a previous term stored by a
+/// majority isn't committed by counting the replicas, the next leader could
+/// replace it. The leader commits it with the no-op of its own term.
+#[test]
+fn commit_the_entries_of_a_previous_term() {
+    for seed in 0..4 {
+        simulate(async move {
+            let mut sim = Simulation::start(5, seed);
+            // Only the entries of the scenario are in the logs
+            for failures in sim.failures.iter() {
+                failures
+                    .lock()
+                    .unwrap()
+                    .insert("prepare_term", HookError::Rejected("no term".into()));
+            }
+            // (a) and (b): S1 led the term 2 and sent its entry to S2, S5 led
+            // the term 3 and kept its entry
+            let first = Term::_new(1, 1, "first");
+            let s1 = Term::_new(2, 2, "from S1");
+            let s5 = Term::_new(2, 3, "from S5");
+            for (i, node) in sim.nodes.iter().enumerate() {
+                let (term, entry) = match i {
+                    0 | 1 => (2, Some(&s1)),
+                    4 => (3, Some(&s5)),
+                    _ => (2, None),
+                };
+                node.election.write().await.current_term = term;
+                let mut logs = node.logs.lock().await;
+                logs.insert(&first).unwrap();
+                logs.set_commit(1).unwrap();
+                if let Some(entry) = entry {
+                    logs.insert(entry).unwrap();
+                }
+            }
+
+            // (c): S5 is down, S1 or S2 takes the lead and replicates the
+            // entry of the term 2 on a majority
+            sim.network.partition(&[vec![sim.url(4)]]);
+            sim.run(Duration::from_secs(2)).await;
+            let leader = sim.leader().await.expect("no leader elected");
+            assert!(leader < 2, "S{} elected with seed {seed}", leader + 1);
+            let logs = sim.nodes[leader].logs.lock().await;
+            assert!(logs.commit_index() >= 2, "no commit with seed {seed}");
+            assert_eq!(logs.find(2).unwrap(), s1);
+            std::mem::drop(logs);
+
+            // (d): the leader is down, S5 can't be elected with the votes of
+            // the nodes that store the entries of the new term
+            let others: Vec<_> = (0..4).filter(|i| *i != leader).collect();
+            sim.network.partition(&[
+                vec![sim.url(leader)],
+                vec![sim.url(4), sim.url(others[0]), sim.url(others[1])],
+            ]);
+            sim.run(Duration::from_secs(3)).await;
+            for node in [4, others[0], others[1]] {
+                let logs = sim.nodes[node].logs.lock().await;
+                assert_ne!(
+                    logs.find(2),
+                    Some(s5.clone()),
+                    "S5 elected with seed {seed}"
+                );
+            }
+        });
+    }
+}
@@ -0,0 +1,161 @@
+// License:
+// This source code is licensed under the GPLv3 license, you can found the
+// LICENSE file in the root directory of this source tree.
+
+use crate::{
+    api::io_msg::InstallSnapshotInput,
+    common::config::Settings,
+    log_entry::Term,
+    node::Node,
+    state::Status,
+    workflow::test::{
+        hook::TestHook,
+        mock::temp_data_dir,
+        simulation::{simulate, Simulation},
+    },
+};
+use std::time::Duration;
+
+#[tokio::test]
+async fn compact_logs_after_threshold() {
+    let data_dir = temp_data_dir();
+    let settings = Settings {
+        snapshot_threshold: 3,
+        data_dir: Some(data_dir.clone()),
+        ..Default::default()
+    };
+    let node = Node::test_new(settings.clone(), Status::leader(), TestHook::default());
+    {
+        let mut logs = node.logs.lock().await;
+        for i in 1..=5 {
+            logs.append(1, format!("term {i}").into()).unwrap();
+        }
+    }
+    node.commit_entries(2).await.unwrap();
+    assert!(node.logs.lock().await.compacted().is_none());
+
+    node.commit_entries(4).await.unwrap();
+    {
+        let logs = node.logs.lock().await;
+        assert_eq!(logs.compacted().unwrap().index, 4);
+        assert!(logs.find(3).is_none());
+        assert_eq!(logs.find(4).unwrap().content, b"term 4");
+        assert_eq!(logs.find(5).unwrap().content, b"term 5");
+    }
+    let snapshot = node.snapshot.read().await.clone().unwrap();
+    assert_eq!(snapshot.data, b"state at 4");
+
+    // Everything is back after a restart
+    let node = Node::test_new(settings, Status::leader(), TestHook::default());
+    node.load_hard_state().await.unwrap();
+    assert_eq!(
+        node.snapshot.read().await.as_ref().unwrap().data,
+        b"state at 4"
+    );
+    assert_eq!(node.logs.lock().await.compacted().unwrap().index, 4);
+    assert_eq!(node.logs.lock().await.last_index(), 5);
+    let _ = std::fs::remove_dir_all(data_dir);
+}
+
+#[tokio::test]
+async fn install_snapshot_from_leader() {
+    let leader_url = String::from("10.10.10.10:1212");
+    let settings = Settings {
+        nodes: vec![leader_url.clone()],
+        ..Default::default()
+    };
+    let hook = TestHook::default();
+    let restored = hook.restored.clone();
+    let node = Node::test_new(settings, Status::follower(leader_url.clone().into()), hook);
+    node.logs.lock().await.append(1, "1st term".into()).unwrap();
+
+    let res = node
+        .receive_install_snapshot(InstallSnapshotInput {
+            term: 4,
+            leader_id: leader_url,
+            last_included: Term::_new(10, 3, "10th term"),
+            data: b"leader state".to_vec(),
+            configuration: Default::default(),
+        })
+        .await
+        .unwrap();
+    assert!(res.success);
+    assert_eq!(res.current_term, 4);
+    assert_eq!(
+        *restored.lock().unwrap(),
+        vec![(10, b"leader state".to_vec())]
+    );
+
+    let logs = node.logs.lock().await;
+    assert_eq!(logs.commit_index(), 10);
+    assert_eq!(logs.last_index(), 10);
+    assert_eq!(logs.last_term(), 3);
+    assert!(logs.find(1).is_none());
+    assert_eq!(logs.find(10).unwrap().content, b"10th term");
+}
+
+#[tokio::test]
+async fn a_slow_restore_survives_the_timeout_of_the_leader() {
+    let leader_url = String::from("10.10.10.10:1212");
+    let hook = TestHook {
+        restore_delay: Duration::from_millis(200),
+        ..TestHook::default()
+    };
+    let restored = hook.restored.clone();
+    let node = Node::test_new(
+        Settings::default(),
+        Status::follower(leader_url.clone().into()),
+        hook,
+    );
+    let input = InstallSnapshotInput {
+        term: 1,
+        leader_id: leader_url,
+        last_included: Term::_new(10, 1, "10th term"),
+        data: b"leader state".to_vec(),
+        configuration: Default::default(),
+    };
+
+    // The leader stops waiting, the restore goes on
+    let res = tokio::time::timeout(
+        Duration::from_millis(50),
+        node.receive_install_snapshot(input.clone()),
+    )
+    .await;
+    assert!(res.is_err());
+    tokio::time::sleep(Duration::from_millis(300)).await;
+    assert_eq!(restored.lock().unwrap().len(), 1);
+    assert_eq!(node.logs.lock().await.commit_index(), 10);
+
+    // The snapshot sent again is already installed
+    let res = node.receive_install_snapshot(input).await.unwrap();
+    assert!(res.success);
+    assert_eq!(restored.lock().unwrap().len(), 1);
+}
+
+#[test]
+fn a_late_follower_catches_up_with_the_snapshot() {
+    simulate(async {
+        let settings = Settings {
+            snapshot_threshold: 5,
+            ..Default::default()
+        };
+        let mut sim = Simulation::start_with_settings(3, 21, settings);
+        sim.run(Duration::from_secs(1)).await;
+        let leader = sim.leader().await.expect("no leader elected");
+        let late = (leader + 1) % 3;
+
+        // The leader compacts the terms the late follower misses
+        sim.network.partition(&[vec![sim.url(late)]]);
+        sim.run(Duration::from_secs(2)).await;
+        let compacted = sim.nodes[leader].logs.lock().await.compacted();
+        let compacted = compacted.expect("logs not compacted").index;
+        assert!(sim.nodes[late].logs.lock().await.commit_index() < compacted);
+
+        // The others keep their leader while the snapshot is sent
+        sim.network.heal();
+        sim.run(Duration::from_secs(2)).await;
+        assert_eq!(sim.leader().await, Some(leader));
+        assert!(sim.nodes[late].logs.lock().await.commit_index() >= compacted);
+        assert!(sim.nodes[late].snapshot.read().await.is_some());
+    });
+}
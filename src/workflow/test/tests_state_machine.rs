@@ -0,0 +1,160 @@
+// License:
+// This source code is licensed under the GPLv3 license, you can found the
+// LICENSE file in the root directory of this source tree.
+use crate::{
+    api::io_msg::AppendTermInput,
+    common::{
+        config::Settings,
+        error::{HookError, HookResult, ProposeError},
+        Url,
+    },
+    log_entry::{EntryKind, Term},
+    membership::Configuration,
+    node::Node,
+    state::Status,
+    workflow::test::mock::{temp_data_dir, wait_applied},
+    BlockingHook, Hook, StateMachine, StateMachineHook,
+};
+
+/// Sum of the numbers applied
+#[derive(Default)]
+struct Counter {
+    total: i64,
+    commands: usize,
+}
+
+impl StateMachine for Counter {
+    type Output = i64;
+
+    fn apply(&mut self, _index: usize, command: &[u8]) -> i64 {
+        self.commands += 1;
+        self.total += String::from_utf8_lossy(command).parse::<i64>().unwrap();
+        self.total
+    }
+
+    fn snapshot(&self) -> HookResult<Vec<u8>> {
+        Ok(self.total.to_string().into_bytes())
+    }
+
+    fn restore(&mut self, data: &[u8]) -> HookResult<()> {
+        self.total = String::from_utf8_lossy(data)
+            .parse()
+            .map_err(|_| HookError::Failed("invalid snapshot".into()))?;
+        Ok(())
+    }
+}
+
+#[test]
+fn apply_each_committed_term_once() {
+    let hook = StateMachineHook::new(Counter::default());
+    let handle = hook.handle();
+    hook.commit_term(&Term::_new(1, 1, "2")).unwrap();
+    hook.commit_term(&Term::_new(2, 1, "3")).unwrap();
+    // Committed again after a failure of the node
+    hook.commit_term(&Term::_new(2, 1, "3")).unwrap();
+    hook.commit_term(&Term::_new(1, 1, "2")).unwrap();
+    // The configurations aren't commands
+    let mut configuration = Term::_new(3, 1, "");
+    configuration.kind = EntryKind::Configuration(Configuration::default());
+    hook.commit_term(&configuration).unwrap();
+
+    assert_eq!(handle.applied_index(), 3);
+    assert_eq!(
+        handle.read(|counter| (counter.total, counter.commands)),
+        (5, 2)
+    );
+    assert_eq!(handle.take_output(1), Some(2));
+    assert_eq!(handle.take_output(2), Some(5));
+    assert_eq!(handle.take_output(2), None);
+    assert_eq!(handle.take_output(3), None);
+
+    // A gap would make the state machine diverge
+    assert!(matches!(
+        hook.commit_term(&Term::_new(5, 1, "1")),
+        Err(HookError::Fatal(_))
+    ));
+    assert_eq!(handle.applied_index(), 3);
+
+    // The snapshot follows the applied entries
+    assert!(hook.snapshot(2).is_err());
+    let data = hook.snapshot(3).unwrap().unwrap();
+    let restored = StateMachineHook::new(Counter::default());
+    restored.restore_snapshot(3, &data).unwrap();
+    restored.commit_term(&Term::_new(4, 2, "10")).unwrap();
+    assert_eq!(restored.handle().take_output(4), Some(15));
+}
+
+#[tokio::test]
+async fn propose_a_command_to_a_follower() {
+    let leader = Url::from("10.10.10.10:3000".to_string());
+    let hook = StateMachineHook::new(Counter::default());
+    let handle = hook.handle();
+    let node = Node::test_new(
+        Settings::default(),
+        Status::follower(leader.clone()),
+        BlockingHook::new(hook),
+    );
+    match handle.propose(&node, "1").await {
+        Err(ProposeError::NotLeader(hint)) => assert_eq!(hint, Some(leader)),
+        res => panic!("unexpected result {:?}", res),
+    }
+}
+
+#[tokio::test]
+async fn restart_where_the_state_machine_stopped() {
+    let data_dir = temp_data_dir();
+    let leader = String::from("10.10.10.10:1212");
+    let settings = Settings {
+        nodes: vec![leader.clone()],
+        data_dir: Some(data_dir.clone()),
+        snapshot_threshold: 3,
+        ..Default::default()
+    };
+    let start = || {
+        let hook = StateMachineHook::new(Counter::default());
+        let handle = hook.handle();
+        let node = Node::test_new(
+            settings.clone(),
+            Status::follower(leader.clone().into()),
+            BlockingHook::new(hook),
+        );
+        (node, handle)
+    };
+    let append = |prev_log_index: usize, entries: Vec<usize>| AppendTermInput {
+        term: 1,
+        leader_id: leader.clone(),
+        prev_log_index,
+        prev_log_term: if prev_log_index == 0 { 0 } else { 1 },
+        leader_commit_index: *entries.last().unwrap(),
+        entries: entries
+            .into_iter()
+            .map(|i| Term::_new(i, 1, i.to_string()))
+            .collect(),
+    };
+
+    // A snapshot at 3, then two entries in the write-ahead log
+    let (node, handle) = start();
+    for input in [append(0, vec![1, 2, 3]), append(3, vec![4, 5])] {
+        let index = input.leader_commit_index;
+        assert!(node.receive_append_term(input).await.unwrap().success);
+        wait_applied(&node, index).await;
+    }
+    assert_eq!(node.logs.lock().await.compacted().unwrap().index, 3);
+    assert_eq!(handle.read(|counter| counter.total), 15);
+    drop(node);
+
+    // The snapshot is restored and the following entries applied again
+    let (node, handle) = start();
+    node.load_hard_state().await.unwrap();
+    assert_eq!(handle.applied_index(), 5);
+    assert_eq!(handle.read(|counter| counter.total), 15);
+    assert_eq!(*node.applied.borrow(), 5);
+
+    // The next commits follow
+    let res = node.receive_append_term(append(5, vec![6])).await.unwrap();
+    assert!(res.success);
+    wait_applied(&node, 6).await;
+    assert_eq!(handle.take_output(6), Some(21));
+    assert!(node.halt.borrow().is_none());
+    let _ = std::fs::remove_dir_all(data_dir);
+}
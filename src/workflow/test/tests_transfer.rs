@@ -0,0 +1,96 @@
+// License:
+// This source code is licensed under the GPLv3 license, you can found the
+// LICENSE file in the root directory of this source tree.
+use crate::{
+    api::io_msg::TimeoutNowInput,
+    common::{
+        config::Settings,
+        error::{ProposeError, TransferError},
+        Url,
+    },
+    node::{Election, Node},
+    state::Status,
+    workflow::test::{
+        hook::TestHook,
+        simulation::{simulate, Simulation},
+    },
+};
+use std::time::Duration;
+
+#[test]
+fn transfer_the_lead() {
+    simulate(async {
+        let mut sim = Simulation::start(5, 5);
+        sim.run(Duration::from_secs(2)).await;
+        let leader = sim.leader().await.expect("no leader elected");
+        let term = sim.nodes[leader].current_term().await;
+
+        let target = (leader + 2) % 5;
+        let node = sim.nodes[leader].clone();
+        let url = sim.url(target);
+        let transfer = tokio::spawn(async move { node.transfer_leadership(url).await });
+        sim.run(Duration::from_secs(1)).await;
+        transfer.await.unwrap().expect("lead not transferred");
+        assert_eq!(sim.leader().await, Some(target));
+        assert_eq!(sim.nodes[target].current_term().await, term + 1);
+
+        // The old leader follows the new one
+        sim.run(Duration::from_secs(1)).await;
+        assert_eq!(
+            sim.nodes[leader].p_status.get_leader().await,
+            Some(sim.url(target))
+        );
+    });
+}
+
+#[tokio::test]
+async fn refuse_proposals_while_transferring() {
+    let node = Node::test_new(Settings::default(), Status::leader(), TestHook::default());
+    *node.transfer.lock().unwrap() = Some(Url::from("10.10.10.10:3000".to_string()));
+    assert!(matches!(
+        node.propose("content".to_string()).await,
+        Err(ProposeError::TransferInProgress)
+    ));
+    assert_eq!(node.logs.lock().await.last_index(), 0);
+}
+
+#[tokio::test]
+async fn transfer_to_an_unknown_node() {
+    let settings = Settings {
+        nodes: vec!["10.10.10.10:3000".to_string()],
+        ..Default::default()
+    };
+    let node = Node::test_new(settings, Status::leader(), TestHook::default());
+    let target = Url::from("10.10.10.11:3000".to_string());
+    assert!(matches!(
+        node.transfer_leadership(target).await,
+        Err(TransferError::UnknownNode(_))
+    ));
+    assert!(node.transfer.lock().unwrap().is_none());
+}
+
+#[tokio::test]
+async fn timeout_now_from_an_old_leader() {
+    let leader = Url::from("10.10.10.10:3000".to_string());
+    let node = Node::test_new(
+        Settings::default(),
+        Status::follower(leader),
+        TestHook::default(),
+    );
+    *node.election.write().await = Election {
+        current_term: 3,
+        vote_for: None,
+    };
+    let input = |term| TimeoutNowInput {
+        term,
+        leader_id: "10.10.10.10:3000".to_string(),
+    };
+    let res = node.receive_timeout_now(input(2)).await;
+    assert!(!res.success);
+    assert_eq!(res.current_term, 3);
+    assert!(node.p_status.is_follower().await);
+
+    let res = node.receive_timeout_now(input(3)).await;
+    assert!(res.success);
+    assert!(node.p_status.is_candidate().await);
+}
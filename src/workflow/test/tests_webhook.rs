@@ -0,0 +1,101 @@
+// License:
+// This source code is licensed under the GPLv3 license, you can found the
+// LICENSE file in the root directory of this source tree.
+use crate::{common::error::HookError, log_entry::Term, AsyncHook, WebhookHook};
+use hyper::{
+    service::{make_service_fn, service_fn},
+    Body, Request, Response, Server, StatusCode,
+};
+use serde_json::{json, Value};
+use std::{
+    convert::Infallible,
+    net::SocketAddr,
+    sync::{
+        atomic::{AtomicUsize, Ordering},
+        Arc,
+    },
+    time::Duration,
+};
+
+/// Stand-in service: `update_node` fails once with a 500, `append_term`
+/// hangs, `commit_term` is rejected, `prepare_term` and `snapshot` answer
+/// and `restore_snapshot` only accepts the snapshot it sent.
+async fn handle(req: Request<Body>, calls: Arc<AtomicUsize>) -> Result<Response<Body>, Infallible> {
+    let call = calls.fetch_add(1, Ordering::SeqCst);
+    let body = hyper::body::to_bytes(req.into_body()).await.unwrap();
+    let req: Value = serde_json::from_slice(&body).unwrap();
+    let resp = match req["method"].as_str().unwrap() {
+        "update_node" if call == 0 => {
+            return Ok(Response::builder()
+                .status(StatusCode::INTERNAL_SERVER_ERROR)
+                .body(Body::empty())
+                .unwrap())
+        }
+        "append_term" => {
+            tokio::time::sleep(Duration::from_secs(5)).await;
+            json!({ "result": null })
+        }
+        "commit_term" => json!({ "error": { "kind": "rejected", "message": "not yet" } }),
+        // The contents are in base64
+        "prepare_term" | "snapshot" => json!({ "result": "AP8=" }),
+        "restore_snapshot" if req["params"]["data"] == "AP8=" => json!({ "result": null }),
+        "restore_snapshot" => json!({ "error": { "kind": "rejected", "message": "unknown" } }),
+        "pre_append_term" => json!({ "result": req["params"]["term"]["index"] }),
+        _ => json!({ "result": null }),
+    };
+    Ok(Response::new(Body::from(resp.to_string())))
+}
+
+fn serve(calls: Arc<AtomicUsize>) -> SocketAddr {
+    let service = make_service_fn(move |_| {
+        let calls = calls.clone();
+        async move { Ok::<_, Infallible>(service_fn(move |req| handle(req, calls.clone()))) }
+    });
+    let server = Server::bind(&([127, 0, 0, 1], 0).into()).serve(service);
+    let addr = server.local_addr();
+    tokio::spawn(server);
+    addr
+}
+
+#[tokio::test]
+async fn post_the_calls_to_a_webhook() {
+    let calls = Arc::new(AtomicUsize::new(0));
+    let addr = serve(calls.clone());
+    let hook = WebhookHook::new(format!("http://{addr}/hook"))
+        .with_timeout(Duration::from_millis(200))
+        .with_retries(1, Duration::from_millis(10));
+
+    // The first attempt fails, the second succeeds
+    assert!(hook.update_node().await.is_ok());
+    assert_eq!(calls.load(Ordering::SeqCst), 2);
+
+    assert_eq!(hook.prepare_term().await.unwrap(), [0, 255]);
+    assert_eq!(hook.pre_append_term(&Term::_new(7, 1, "")).await, Ok(7));
+    assert_eq!(
+        hook.commit_term(&Term::_new(1, 1, "")).await,
+        Err(HookError::Rejected("not yet".into()))
+    );
+    assert_eq!(calls.load(Ordering::SeqCst), 5);
+
+    // A call that times out is tried again, then fails
+    assert!(matches!(
+        hook.append_term(&Term::_new(1, 1, "")).await,
+        Err(HookError::Failed(_))
+    ));
+    assert_eq!(calls.load(Ordering::SeqCst), 7);
+
+    // The snapshots are in base64 too
+    assert_eq!(hook.snapshot(3).await.unwrap(), Some(vec![0, 255]));
+    assert_eq!(hook.restore_snapshot(3, &[0, 255]).await, Ok(()));
+    assert_eq!(
+        hook.restore_snapshot(3, b"other").await,
+        Err(HookError::Rejected("unknown".into()))
+    );
+
+    // A service that doesn't run fails the calls
+    let hook = WebhookHook::new("http://127.0.0.1:1/hook").with_retries(0, Duration::ZERO);
+    assert!(matches!(
+        hook.prepare_term().await,
+        Err(HookError::Failed(_))
+    ));
+}
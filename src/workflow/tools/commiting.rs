@@ -2,33 +2,113 @@
 // This source code is licensed under the GPLv3 license, you can found the
 // LICENSE file in the root directory of this source tree.
 
-use crate::Node;
+use crate::{common::error::ErrorResult, storage::Snapshot, Node};
 use tracing::{debug, trace, warn};
 
 impl Node {
     /// Commit up to the new commit index included.
     /// Saturate and return if the index to commit isn't in cache.
-    pub(crate) async fn commit_entries(&self, new_commit_index: usize) {
-        let mut logs = self.logs.lock().await;
-        let from = logs.commit_index() + 1;
-        if from <= new_commit_index {
+    ///
+    /// The entries are read from the logs, then given to the hook without
+    /// holding the logs. The commits are applied one at a time, see
+    /// `committing`.
+    pub(crate) async fn commit_entries(&self, new_commit_index: usize) -> ErrorResult<()> {
+        let _committing = self.committing.lock().await;
+        let entries = {
+            let logs = self.logs.lock().await;
+            let from = logs.commit_index() + 1;
+            if from > new_commit_index {
+                return Ok(());
+            }
             trace!("commit entries term from {from} to {new_commit_index}");
+            let mut entries = vec![];
             for index in from..=new_commit_index {
                 if !logs.check_commit(index) {
                     debug!("stop commit at log term {index}");
                     break;
                 }
-                let term = match logs.find(index) {
-                    Some(term) => term,
+                match logs.find(index) {
+                    Some(term) => entries.push(term),
                     None => {
                         warn!("unable to find a log while committing");
                         break;
                     }
-                };
-                debug!("commit term {:?}", term);
-                logs.set_commit(index);
-                self.hook.commit_term(&term);
+                }
+            }
+            entries
+        };
+        let mut committed = None;
+        for term in entries {
+            debug!("commit term {:?}", term);
+            // The term is committed once the hook applied it, the next
+            // commit calls the hook again after a failure
+            if let Err(err) = self.hook.commit_term(&term).await {
+                self.hook_failed("commit_term", &err);
+                break;
             }
+            committed = Some(term.index);
         }
+        let index = match committed {
+            Some(index) => index,
+            None => return Ok(()),
+        };
+        {
+            let mut logs = self.logs.lock().await;
+            logs.set_commit(index)?;
+            self.commit_configurations(logs.commit_index()).await;
+            self.applied.send_replace(logs.commit_index());
+            self.resolve_proposals(&logs).await;
+        }
+        self.compact_logs().await
+    }
+
+    /// Commit up to `new_commit_index` in a task of its own, the caller
+    /// replies without waiting for the hook. The commits stay in order, see
+    /// `committing`.
+    pub(crate) fn commit_in_background(&self, new_commit_index: usize) {
+        let node = self.clone();
+        tokio::spawn(async move {
+            if let Err(err) = node.commit_entries(new_commit_index).await {
+                warn!("failed to commit up to {new_commit_index}: {:?}", err);
+            }
+        });
+    }
+
+    /// Take a snapshot with the hook and compact the logs when more than
+    /// `snapshot_threshold` terms have been committed since the latest
+    /// snapshot. The caller holds `committing`, the hook takes the snapshot
+    /// without holding the logs.
+    async fn compact_logs(&self) -> ErrorResult<()> {
+        let threshold = self.settings.snapshot_threshold;
+        let (commit_index, last_included) = {
+            let logs = self.logs.lock().await;
+            let commit_index = logs.commit_index();
+            let compacted = logs.compacted().map_or(0, |term| term.index);
+            if threshold == 0 || commit_index - compacted < threshold {
+                return Ok(());
+            }
+            match logs.find(commit_index) {
+                Some(term) => (commit_index, term),
+                None => return Ok(()),
+            }
+        };
+        let data = match self.hook.snapshot(commit_index).await {
+            Ok(Some(data)) => data,
+            Ok(None) => return Ok(()),
+            Err(err) => {
+                self.hook_failed("snapshot", &err);
+                return Ok(());
+            }
+        };
+        trace!("compact logs up to {commit_index}");
+        let snapshot = Snapshot {
+            last_included,
+            data,
+        };
+        // The snapshot has to be on disk before we drop the terms
+        self.storage.save_snapshot(&snapshot)?;
+        self.logs.lock().await.compact(&snapshot.last_included)?;
+        *self.snapshot.write().await = Some(snapshot);
+        Ok(())
     }
 }
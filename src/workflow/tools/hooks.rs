@@ -0,0 +1,64 @@
+// License:
+// This source code is licensed under the GPLv3 license, you can found the
+// LICENSE file in the root directory of this source tree.
+
+use crate::{
+    common::{error::HookError, hook_trait::AsyncHook},
+    node::Node,
+};
+use std::{future::Future, sync::Arc};
+use tracing::{error, warn};
+
+impl Node {
+    /// Log the failure of the `call` to the hook. A fatal error halts the
+    /// node, the main loop returns `Error::HookFailure`.
+    pub(crate) fn hook_failed(&self, call: &str, err: &HookError) {
+        if err.is_fatal() {
+            error!("{call}: {err}, halt the node");
+            self.halt.send_if_modified(|halt| {
+                if halt.is_some() {
+                    return false;
+                }
+                *halt = Some(err.clone());
+                true
+            });
+        } else {
+            warn!("{call}: {err}");
+        }
+    }
+
+    /// A hook failed on the leader, the leader can't go on with the entries
+    /// it appended and steps down. The entries are kept like the entries of
+    /// any leader that lost the lead.
+    pub(crate) async fn leader_hook_failed(&self, call: &str, err: &HookError) {
+        self.hook_failed(call, err);
+        match self.step_down().await {
+            Ok(events) => self.notify_status(events).await,
+            Err(err) => warn!("failed to step down, {:?}", err),
+        }
+    }
+
+    /// Call an observer of the protocol in a task of its own, the votes and
+    /// the heartbeats never wait for it
+    pub(crate) fn observe<F, Fut>(&self, call: F)
+    where
+        F: FnOnce(Arc<Box<dyn AsyncHook>>) -> Fut,
+        Fut: Future<Output = ()> + Send + 'static,
+    {
+        tokio::spawn(call(self.hook.clone()));
+    }
+
+    /// Wait for a fatal error of the hook
+    pub(crate) async fn halted(&self) -> HookError {
+        let mut halt = self.halt.subscribe();
+        let halt = halt
+            .wait_for(|halt| halt.is_some())
+            .await
+            .map(|err| err.clone());
+        match halt {
+            Ok(Some(err)) => err,
+            // The sender lives as long as the node
+            _ => std::future::pending().await,
+        }
+    }
+}
@@ -4,126 +4,91 @@
 
 use crate::{
     api::io_msg::AppendTermInput,
-    common::Url,
-    log_entry::{Entries, Term},
+    common::{error::HookResult, Url},
+    log_entry::Term,
     node::Node,
 };
-use tokio::sync::MutexGuard;
 use tracing::debug;
 
 impl Node {
-    /// Get term for target node
-    async fn get_target_term<'a>(
-        &self,
-        target_node: &Url,
-        logs_guard: &mut MutexGuard<'a, Entries>,
-    ) -> Term {
+    /// Get the term at `index` for a target node, `local` is the term found
+    /// in the logs if any.
+    async fn get_target_term(&self, index: usize, local: Option<usize>) -> HookResult<usize> {
         // If a node needs a specific term, we try to find it in the logs,
         // otherwise we defer the job to the hook.
-        //
-        // If the node doesn't have a next_indexes registered, fill with the
-        // current term.
-        match self.next_indexes.read().await.get(target_node) {
-            Some(node_index) => {
-                let id = node_index.unwrap();
-                match logs_guard.find(id) {
-                    Some(term) => term,
-                    _ => self
-                        .hook
-                        .retreive_term(id)
-                        .expect("Unable to retrieve term {id} as leader"),
-                }
-            }
-            _ => {
-                // suppose the last term is in under the latest
-                // leader commit. Minimum index is 1.
-                let mut index = logs_guard.commit_index();
-                index = index.saturating_sub(10);
-                if index == 0 {
-                    index = 1;
-                }
-                self.hook
-                    .retreive_term(index)
-                    .expect("Unable to retrieve term {id} as leader")
-            }
+        match local {
+            Some(term) => Ok(term),
+            _ => Ok(self.hook.retreive_term(index).await?.term),
         }
     }
 
-    /// Creates a term especially for the `target_node`
-    pub(crate) async fn create_term_input(&self, target_node: &Url) -> AppendTermInput {
-        let mut logs_guard = self.logs.lock().await;
-        // prev term is the latest term the remote node should have
-        let prev_term = self.get_target_term(target_node, &mut logs_guard).await;
-        let (created, local_latest_term) = logs_guard.latest();
-        if created {
-            self.hook.append_term(&local_latest_term);
-        }
-        let leader_id = self.settings.node_id.clone();
-        let leader_commit_index = logs_guard.commit_index();
-
-        // Add up to 10 entries only
-        let mut pos = prev_term.id + 1;
-        let end = pos + 10;
-
-        // Case 1:
-        // The latest term the remote has is also my term.
-        if prev_term == local_latest_term {
-            debug!("just send latest because previous term IS local latest");
-            return AppendTermInput {
-                term: local_latest_term.clone(),
-                leader_id,
-                prev_term: local_latest_term,
-                entries: vec![],
-                leader_commit_index,
-            };
-        } else if pos >= local_latest_term.id {
-            debug!("just send latest because previous term is just before our local latest");
-            return AppendTermInput {
-                term: local_latest_term.clone(),
-                leader_id,
-                prev_term: local_latest_term,
-                entries: vec![],
-                leader_commit_index,
-            };
+    /// Get the entry at `index` for a target node, `local` is the entry
+    /// found in the logs if any.
+    async fn get_target_entry(&self, index: usize, local: Option<Term>) -> HookResult<Term> {
+        match local {
+            Some(term) => Ok(term),
+            _ => self.hook.retreive_term(index).await,
         }
+    }
 
-        // Case 2:
-        // The latest term the remote is older than our.
-        // :=> prev_term.id + 1 <= local_latest_term.id
-        //     <=> pos <= local_latest_term.id
-        let entries = {
-            let mut retreived = vec![];
-            while pos < end && pos < local_latest_term.id - 1 {
-                if let Some(term) = logs_guard.find(pos) {
-                    retreived.push(term);
-                } else if let Some(term) = self.hook.retreive_term(pos) {
-                    retreived.push(term);
-                } else {
-                    panic!("impossible to retrieve a term as a leader");
-                }
-                pos += 1
-            }
-            retreived
-        };
-
-        // Limit the knowledge of distant node to the latest
-        // term. Note: I'm not so sure about that.
-        let term = if pos == local_latest_term.id {
-            local_latest_term
-        } else if let Some(term) = logs_guard.find(pos) {
-            term
-        } else if let Some(term) = self.hook.retreive_term(pos) {
-            term
-        } else {
-            panic!("impossible to retrieve a term as a leader");
+    /// Creates a term especially for the `target_node`, in the election
+    /// `term` of the leader.
+    ///
+    /// The entries follow the latest index we think the node has. If the
+    /// node doesn't have a next_indexes registered, suppose it's up to date
+    /// and just send a heartbeat, the result will tell us otherwise.
+    ///
+    /// The entries are read from the logs, the ones missing are rebuilt by
+    /// the hook once the logs are released.
+    ///
+    /// # Error
+    /// The error of the `retreive_term` hook if a term isn't in the logs.
+    pub(crate) async fn create_term_input(
+        &self,
+        target_node: &Url,
+        term: usize,
+    ) -> HookResult<AppendTermInput> {
+        let (prev_log_index, prev_log_term, local_entries, leader_commit_index) = {
+            let logs = self.logs.lock().await;
+            let last_index = logs.last_index();
+            let prev_log_index = self
+                .next_indexes
+                .read()
+                .await
+                .get(target_node)
+                .map_or(last_index, |index| index.unwrap())
+                .min(last_index);
+            // Add up to 10 entries only
+            let end = last_index.min(prev_log_index + 10);
+            let local_entries: Vec<(usize, Option<Term>)> = (prev_log_index + 1..=end)
+                .map(|index| (index, logs.find(index)))
+                .collect();
+            (
+                prev_log_index,
+                logs.term_at(prev_log_index),
+                local_entries,
+                logs.commit_index(),
+            )
         };
+        let prev_log_term = self.get_target_term(prev_log_index, prev_log_term).await?;
+        let mut entries: Vec<Term> = vec![];
+        for (index, local) in local_entries {
+            entries.push(self.get_target_entry(index, local).await?);
+        }
+        debug!(
+            "send {} entries after {} to {}",
+            entries.len(),
+            prev_log_index,
+            target_node
+        );
 
-        AppendTermInput {
+        Ok(AppendTermInput {
             term,
-            leader_id,
-            prev_term,
+            leader_id: self.node_url(),
+            prev_log_index,
+            prev_log_term,
             entries,
             leader_commit_index,
-        }
+        })
     }
 }
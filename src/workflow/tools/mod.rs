@@ -3,4 +3,5 @@
 // LICENSE file in the root directory of this source tree.
 
 pub(crate) mod commiting;
+pub(crate) mod hooks;
 pub(crate) mod leader_tools;
@@ -0,0 +1,140 @@
+// License:
+// This source code is licensed under the GPLv3 license, you can found the
+// LICENSE file in the root directory of this source tree.
+
+//! # LEADERSHIP TRANSFER
+//!
+//! The leader hands its lead to another node of the cluster (§3.10 of the
+//! raft thesis).
+//!
+//! 1. The leader stops accepting proposals and stops preparing terms, its
+//!    logs don't grow anymore.
+//! 2. It sends append terms to the target until the target has all the
+//!    entries of its logs.
+//! 3. It sends a `timeout_now` request, the target starts an election
+//!    without waiting for its heartbeat timeout and wins it since its logs
+//!    are up to date.
+//!
+//! If the target doesn't take the lead before an election timeout, the
+//! leader accepts the proposals again.
+
+use crate::{
+    api::io_msg::{TimeoutNowInput, TimeoutNowResult},
+    common::{error::TransferError, Url},
+    node::{NextIndex::Validated, Node},
+    state::EStatus,
+};
+use std::{sync::atomic::Ordering, time::Duration};
+use tracing::{debug, trace, warn};
+
+impl Node {
+    /// Transfer the lead to the `target`. Resolve when the local node isn't
+    /// the leader anymore.
+    ///
+    /// # Error
+    /// - `NotLeader` if the node isn't the leader, with the leader it knows
+    /// - `UnknownNode` if the target isn't another voter of the cluster
+    /// - `Refused` if the target didn't start an election
+    /// - `Timeout` if the node is still the leader after an election timeout
+    /// - `Failed` if the append terms to the target failed
+    pub async fn transfer_leadership(&self, target: Url) -> Result<(), TransferError> {
+        let term = {
+            let election = self.election.read().await;
+            if !self.p_status.is_leader().await {
+                return Err(TransferError::NotLeader(self.p_status.get_leader().await));
+            }
+            let target_id = target.to_string();
+            let is_voter = self.membership.read().await.active().is_voter(&target_id);
+            if !is_voter || target_id == self.node_url() {
+                return Err(TransferError::UnknownNode(target));
+            }
+            *self.transfer.lock().unwrap() = Some(target.clone());
+            election.current_term
+        };
+        trace!("transfer the lead to {target}");
+        let deadline = Duration::from_millis(self.settings.timeout_max as u64);
+        let result = tokio::time::timeout(deadline, self.internal_transfer(&target, term)).await;
+        *self.transfer.lock().unwrap() = None;
+        match result {
+            Ok(result) => result,
+            Err(_) => {
+                warn!("{target} didn't take the lead");
+                Err(TransferError::Timeout)
+            }
+        }
+    }
+
+    async fn internal_transfer(&self, target: &Url, term: usize) -> Result<(), TransferError> {
+        loop {
+            if !self.p_status.is_leader().await {
+                return Ok(());
+            }
+            let last_index = self.logs.lock().await.last_index();
+            if let Some(Validated(index)) = self.next_indexes.read().await.get(target) {
+                if *index >= last_index {
+                    break;
+                }
+            }
+            let mut fail_count = 0;
+            self.post_new_append_term(target.clone(), term, &mut fail_count)
+                .await
+                .map_err(TransferError::Failed)?;
+            if fail_count > 0 {
+                tokio::time::sleep(self.settings.get_send_term_sleep_duration()).await;
+            }
+        }
+        debug!("{target} is up to date, send timeout now");
+        let input = TimeoutNowInput {
+            term,
+            leader_id: self.node_url(),
+        };
+        match self.transport.timeout_now(target, input).await {
+            Ok(result) => {
+                if self.observe_term(result.current_term).await.is_err() || !result.success {
+                    return Err(TransferError::Refused);
+                }
+            }
+            Err(warn) => {
+                warn!("{}", *warn);
+                return Err(TransferError::Refused);
+            }
+        }
+        self.p_status.wait_while(EStatus::Leader).await;
+        Ok(())
+    }
+
+    /// Node reaction on receive a timeout now request. The leader of the
+    /// term transfers its lead, start an election immediately.
+    ///
+    /// 1. Reply false if term < currentTerm
+    /// 2. Reply false if the node is a pure follower, isn't a follower or
+    ///    isn't a voter
+    /// 3. Skip the heartbeat timeout and the pre vote round, turn into a
+    ///    candidate
+    pub async fn receive_timeout_now(&self, input: TimeoutNowInput) -> TimeoutNowResult {
+        trace!("receive a timeout now request {:#?}", input);
+        let election = self.election.read().await;
+        let mut result = TimeoutNowResult {
+            current_term: election.current_term,
+            success: false,
+        };
+        if input.term < election.current_term {
+            debug!("refuse timeout now because term < current");
+            return result;
+        }
+        if self.settings.follower || !self.p_status.is_follower().await || !self.is_voter().await {
+            debug!("refuse timeout now, not a follower");
+            return result;
+        }
+        self.forced_election.store(true, Ordering::SeqCst);
+        self.heartbeat.lock().await.take();
+        let events = self.switch_to_candidate().await;
+        result.success = events.is_ok();
+        if !result.success {
+            self.forced_election.store(false, Ordering::SeqCst);
+        }
+        std::mem::drop(election);
+        self.notify_status(events.unwrap_or_default()).await;
+        result
+    }
+}